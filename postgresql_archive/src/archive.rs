@@ -1,276 +1,558 @@
 //! Manage PostgreSQL archive
 #![allow(dead_code)]
 
-use crate::error::Error::{AssetHashNotFound, AssetNotFound, ReleaseNotFound, Unexpected};
+use crate::cache::{self, Cache};
+use crate::download::{get_retry_policy, ArchiveWriter, PartialFileLock};
+use crate::error::Error::{
+    AssetHashNotFound, Unexpected, UnsafeArchiveEntry, UnsupportedArchiveFormat,
+};
 use crate::error::Result;
-use crate::github::{Asset, Release};
+use crate::format::ArchiveFormat;
+use crate::progress::{Progress, ProgressEvent};
+use crate::repository::{registry, Asset, Repository};
 use crate::version::Version;
 use crate::Error::ArchiveHashMismatch;
 use bytes::Bytes;
-use flate2::bufread::GzDecoder;
-use http::Extensions;
 use human_bytes::human_bytes;
 use num_format::{Locale, ToFormattedString};
 use regex::Regex;
-use reqwest::{header, Request, Response};
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
-use reqwest_retry::policies::ExponentialBackoff;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::RetryTransientMiddleware;
 use reqwest_tracing::TracingMiddleware;
 use sha2::{Digest, Sha256};
-use std::fs::{create_dir_all, remove_dir_all, remove_file, rename, File};
-use std::io::{copy, BufReader, Cursor};
-use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::fs::{create_dir_all, hard_link, remove_dir_all, remove_file, rename, File};
+use std::io::{copy, sink, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tar::Archive;
+use tempfile::{NamedTempFile, TempPath};
 use tracing::{debug, instrument, warn};
 
-const GITHUB_API_VERSION_HEADER: &str = "X-GitHub-Api-Version";
-const GITHUB_API_VERSION: &str = "2022-11-28";
+/// URL of the default repository of PostgreSQL archives
+pub const DEFAULT_POSTGRESQL_URL: &str = "https://github.com/theseus-rs/postgresql-binaries";
 
-lazy_static! {
-    static ref GITHUB_TOKEN: Option<String> = match std::env::var("GITHUB_TOKEN") {
-        Ok(token) => {
-            debug!("GITHUB_TOKEN environment variable found");
-            Some(token)
-        }
-        Err(_) => None,
-    };
+/// Creates a new reqwest client builder with middleware for tracing, and retrying transient errors
+/// according to the [retry policy](get_retry_policy).
+pub(crate) fn reqwest_client_builder() -> ClientBuilder {
+    let retry_policy = get_retry_policy().exponential_backoff();
+    ClientBuilder::new(reqwest::Client::new())
+        .with(TracingMiddleware::default())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
 }
 
-lazy_static! {
-    static ref USER_AGENT: String = format!(
-        "{PACKAGE}/{VERSION}",
-        PACKAGE = env!("CARGO_PKG_NAME"),
-        VERSION = env!("CARGO_PKG_VERSION")
-    );
+/// Creates a new reqwest client with middleware for tracing, and retrying transient errors.
+pub(crate) fn reqwest_client() -> ClientWithMiddleware {
+    reqwest_client_builder().build()
 }
 
-/// Middleware to add GitHub headers to the request. If a GitHub token is set, then it is added as a
-/// bearer token. This is used to authenticate with the GitHub API to increase the rate limit.
-#[derive(Debug)]
-struct GithubMiddleware;
-
-impl GithubMiddleware {
-    fn add_github_headers(&self, request: &mut Request) -> Result<()> {
-        let headers = request.headers_mut();
-
-        headers.append(
-            GITHUB_API_VERSION_HEADER,
-            GITHUB_API_VERSION.parse().unwrap(),
-        );
-        headers.append(header::USER_AGENT, USER_AGENT.parse().unwrap());
-
-        if let Some(token) = &*GITHUB_TOKEN {
-            headers.append(
-                header::AUTHORIZATION,
-                format!("Bearer {token}").parse().unwrap(),
-            );
-        }
-
-        Ok(())
-    }
+/// Gets the name of the archive for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html).
+///
+/// Archives are named `postgresql-<version>-<target>.tar.gz` (e.g.
+/// `postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz`) and are accompanied by a `.sha256` file
+/// containing the hash of the archive.
+pub fn archive_asset_name<S: AsRef<str>>(version: &Version, target: S) -> String {
+    format!("postgresql-{version}-{}.tar.gz", target.as_ref())
 }
 
-#[async_trait::async_trait]
-impl Middleware for GithubMiddleware {
-    async fn handle(
-        &self,
-        mut request: Request,
-        extensions: &mut Extensions,
-        next: Next<'_>,
-    ) -> reqwest_middleware::Result<Response> {
-        match self.add_github_headers(&mut request) {
-            Ok(_) => next.run(request, extensions).await,
-            Err(error) => Err(reqwest_middleware::Error::Middleware(error.into())),
-        }
-    }
+/// Gets the versions of PostgreSQL available in the repository at the given URL for the specified
+/// [version](Version), newest first. Only versions matching the major version, and the minor
+/// version if specified, are returned.
+#[instrument(level = "debug")]
+pub async fn get_versions(url: &str, version: &Version) -> Result<Vec<Version>> {
+    let repository = registry::get(url)?;
+    let mut versions: Vec<Version> = repository
+        .get_versions()
+        .await?
+        .into_iter()
+        .filter(|available_version| version.matches(available_version))
+        .collect();
+    versions.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(versions)
 }
 
-/// Creates a new reqwest client with middleware for tracing, GitHub, and retrying transient errors.
-fn reqwest_client() -> ClientWithMiddleware {
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-    ClientBuilder::new(reqwest::Client::new())
-        .with(TracingMiddleware::default())
-        .with(GithubMiddleware)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build()
+/// Gets the version of PostgreSQL for the specified [version](Version) from the
+/// [default repository](DEFAULT_POSTGRESQL_URL). If the version minor or release is not specified,
+/// then the latest version is returned. If a release for the [version](Version) is not found, then
+/// a [ReleaseNotFound](crate::Error::ReleaseNotFound) error is returned.
+#[instrument(level = "debug")]
+pub async fn get_version(version: &Version) -> Result<Version> {
+    get_version_from(DEFAULT_POSTGRESQL_URL, version).await
 }
 
-/// Gets a release from GitHub for a given [version](Version) of PostgreSQL. If a release for the
-/// [version](Version) is not found, then a [ReleaseNotFound] error is returned.
+/// Gets the version of PostgreSQL for the specified [version](Version) from the repository at the
+/// given URL. If the version minor or release is not specified, then the latest version is
+/// returned. If a release for the [version](Version) is not found, then a
+/// [ReleaseNotFound](crate::Error::ReleaseNotFound) error is returned.
 #[instrument(level = "debug")]
-async fn get_release(version: &Version) -> Result<Release> {
-    let url = "https://api.github.com/repos/theseus-rs/postgresql-binaries/releases";
-    let client = reqwest_client();
-
-    debug!("Attempting to locate release for version {version}");
+pub async fn get_version_from(url: &str, version: &Version) -> Result<Version> {
+    let repository = registry::get(url)?;
+    repository.get_version(version).await
+}
 
-    if version.minor.is_some() && version.release.is_some() {
-        let request = client.get(format!("{url}/tags/{version}"));
-        let response = request.send().await?.error_for_status()?;
-        let release = response.json::<Release>().await?;
+/// A verified PostgreSQL archive on disk, such as an archive in the [cache](crate::cache) or a
+/// downloaded archive in a temporary file. The archive is kept open, so it can still be read if
+/// another process evicts it from the cache. Temporary files are removed when the archive file is
+/// dropped.
+#[derive(Debug)]
+pub struct ArchiveFile {
+    version: Version,
+    path: PathBuf,
+    hash: String,
+    file: File,
+    temp_path: Option<TempPath>,
+}
 
-        debug!("Release found for version {version}");
-        return Ok(release);
+impl ArchiveFile {
+    /// Gets the version of PostgreSQL in the archive
+    pub fn version(&self) -> &Version {
+        &self.version
     }
 
-    let mut result: Option<Release> = None;
-    let mut page = 1;
-
-    loop {
-        let request = client
-            .get(url)
-            .query(&[("page", page.to_string().as_str()), ("per_page", "100")]);
-        let response = request.send().await?.error_for_status()?;
-        let response_releases = response.json::<Vec<Release>>().await?;
-        if response_releases.is_empty() {
-            break;
-        }
-
-        for release in response_releases {
-            let release_version = match Version::from_str(&release.tag_name) {
-                Ok(release_version) => release_version,
-                Err(_) => {
-                    warn!("Failed to parse release version {}", release.tag_name);
-                    continue;
-                }
-            };
+    /// Gets the path of the archive
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 
-            if version.matches(&release_version) {
-                match &result {
-                    Some(result_release) => {
-                        let result_version = Version::from_str(&result_release.tag_name)?;
-                        if release_version > result_version {
-                            result = Some(release);
-                        }
-                    }
-                    None => {
-                        result = Some(release);
-                    }
-                }
-            }
-        }
+    /// Gets the SHA-256 hash of the archive
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
 
-        page += 1;
+    /// Reads the archive into memory and verifies its SHA-256 hash
+    pub fn read(&self) -> Result<Bytes> {
+        let mut archive = Vec::new();
+        self.open()?.read_to_end(&mut archive)?;
+        let archive = Bytes::from(archive);
+        verify_hash(&archive, &self.hash)?;
+        Ok(archive)
     }
 
-    match result {
-        Some(release) => {
-            let release_version = Version::from_str(&release.tag_name)?;
-            debug!("Release {release_version} found for version {version}");
-            Ok(release)
-        }
-        None => Err(ReleaseNotFound(version.to_string())),
+    /// Gets a handle to the open archive, positioned at its start
+    pub(crate) fn open(&self) -> Result<File> {
+        let mut file = self.file.try_clone()?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
     }
 }
 
-/// Gets the version of PostgreSQL for the specified [version](Version).  If the version minor or release is not
-/// specified, then the latest version is returned. If a release for the [version](Version) is not found, then a
-/// [ReleaseNotFound] error is returned.
-#[instrument(level = "debug")]
-pub async fn get_version(version: &Version) -> Result<Version> {
-    let release = get_release(version).await?;
-    Version::from_str(&release.tag_name)
+/// Gets the archive for a given [version](Version) of PostgreSQL for the current target from the
+/// [default repository](DEFAULT_POSTGRESQL_URL). If the [version](Version) is not found for this
+/// target, then an [error](crate::error::Error) is returned.
+///
+/// Returns the archive version and bytes.
+#[instrument]
+pub async fn get_archive(version: &Version) -> Result<(Version, Bytes)> {
+    get_archive_from(DEFAULT_POSTGRESQL_URL, version).await
 }
 
-/// Gets the assets for a given [version](Version) of PostgreSQL and
-/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html).
-/// If the [version](Version) or [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html)
-/// is not found, then an [error](crate::error::Error) is returned.
+/// Gets the archive for a given [version](Version) of PostgreSQL for the current target from the
+/// repository at the given URL. If the [version](Version) is not found for this target, then an
+/// [error](crate::error::Error) is returned.
 ///
-/// Two assets are returned. The first [asset](Asset) is the archive, and the second [asset](Asset) is the archive hash.
-#[instrument(level = "debug", skip(target))]
-async fn get_asset<S: AsRef<str>>(version: &Version, target: S) -> Result<(Version, Asset, Asset)> {
-    let release = get_release(version).await?;
-    let asset_version = Version::from_str(&release.tag_name)?;
-    let mut asset: Option<Asset> = None;
-    let mut asset_hash: Option<Asset> = None;
-    let asset_name = format!("postgresql-{}-{}.tar.gz", asset_version, target.as_ref());
-    let asset_hash_name = format!("{asset_name}.sha256");
-
-    for release_asset in release.assets {
-        if release_asset.name == asset_name {
-            asset = Some(release_asset);
-        } else if release_asset.name == asset_hash_name {
-            asset_hash = Some(release_asset);
-        }
-
-        if asset.is_some() && asset_hash.is_some() {
-            break;
-        }
-    }
+/// Returns the archive version and bytes.
+#[instrument]
+pub async fn get_archive_from(url: &str, version: &Version) -> Result<(Version, Bytes)> {
+    get_archive_for_target_from(url, version, target_triple::TARGET).await
+}
 
-    match (asset, asset_hash) {
-        (Some(asset), Some(asset_hash)) => Ok((asset_version, asset, asset_hash)),
-        (None, _) => Err(AssetNotFound(asset_name.to_string())),
-        (_, None) => Err(AssetNotFound(asset_name.to_string())),
-    }
+/// Gets the archive for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the
+/// [default repository](DEFAULT_POSTGRESQL_URL). If the [version](Version) or
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) is not found, then an
+/// [error](crate::error::Error) is returned.
+///
+/// Returns the archive version and bytes.
+#[instrument(level = "debug", skip(target))]
+pub async fn get_archive_for_target<S: AsRef<str>>(
+    version: &Version,
+    target: S,
+) -> Result<(Version, Bytes)> {
+    get_archive_for_target_from(DEFAULT_POSTGRESQL_URL, version, target).await
 }
 
-/// Gets the archive for a given [version](Version) of PostgreSQL for the current target.
-/// If the [version](Version) is not found for this target, then an
+/// Gets the archive for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL. If the [version](Version) or
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) is not found, then an
 /// [error](crate::error::Error) is returned.
 ///
+/// This is a convenience wrapper around [get_archive_file_for_target] that reads the archive into
+/// memory.
+///
 /// Returns the archive version and bytes.
-#[instrument]
-pub async fn get_archive(version: &Version) -> Result<(Version, Bytes)> {
-    get_archive_for_target(version, target_triple::TARGET).await
+#[instrument(level = "debug", skip(target))]
+pub async fn get_archive_for_target_from<S: AsRef<str>>(
+    url: &str,
+    version: &Version,
+    target: S,
+) -> Result<(Version, Bytes)> {
+    get_archive_for_target_with_progress(url, version, target, &Progress::default()).await
 }
 
 /// Gets the archive for a given [version](Version) of PostgreSQL and
-/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html).
-/// If the [version](Version) or [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html)
-/// is not found, then an [error](crate::error::Error) is returned.
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL, reporting [progress](ProgressEvent) to the [progress](Progress). See
+/// [get_archive_for_target_from].
 ///
 /// Returns the archive version and bytes.
 #[instrument(level = "debug", skip(target))]
-pub async fn get_archive_for_target<S: AsRef<str>>(
+pub async fn get_archive_for_target_with_progress<S: AsRef<str>>(
+    url: &str,
     version: &Version,
     target: S,
+    progress: &Progress,
 ) -> Result<(Version, Bytes)> {
-    let (asset_version, asset, asset_hash) = get_asset(version, target).await?;
+    let archive = get_archive_file_for_target_with_progress(url, version, target, progress).await?;
+    let bytes = archive.read()?;
+    Ok((archive.version, bytes))
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL for the
+/// current target from the repository at the given URL. If the [version](Version) is not found for
+/// this target, then an [error](crate::error::Error) is returned.
+#[instrument]
+pub async fn get_archive_file(url: &str, version: &Version) -> Result<ArchiveFile> {
+    get_archive_file_for_target(url, version, target_triple::TARGET).await
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL for the
+/// current target from the repository at the given URL, reporting [progress](ProgressEvent) to
+/// the [progress](Progress). See [get_archive_file].
+#[instrument]
+pub async fn get_archive_file_with_progress(
+    url: &str,
+    version: &Version,
+    progress: &Progress,
+) -> Result<ArchiveFile> {
+    get_archive_file_for_target_with_progress(url, version, target_triple::TARGET, progress).await
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL. If the [version](Version) or
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) is not found, then an
+/// [error](crate::error::Error) is returned.
+///
+/// The archive is streamed to disk and hashed while it is downloaded, so it is never held in
+/// memory. The [default cache](cache::get_default) is consulted before downloading the archive,
+/// and populated once the downloaded archive has been verified. Archives are downloaded to a
+/// partial file in the cache, so that an interrupted download is
+/// [resumed](crate::download::ArchiveWriter) by the next attempt; processes downloading the same
+/// archive to the cache wait for each other, and reuse the archive cached by the first process to
+/// finish. If the SHA-256 hash of the
+/// downloaded archive does not match the hash of the repository, then the partial file is removed
+/// and an [ArchiveHashMismatch] error is returned.
+#[instrument(level = "debug", skip(target))]
+pub async fn get_archive_file_for_target<S: AsRef<str>>(
+    url: &str,
+    version: &Version,
+    target: S,
+) -> Result<ArchiveFile> {
+    get_archive_file_for_target_with_progress(url, version, target, &Progress::default()).await
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL, reporting [progress](ProgressEvent) to the [progress](Progress). See
+/// [get_archive_file_for_target].
+#[instrument(level = "debug", skip(target))]
+pub async fn get_archive_file_for_target_with_progress<S: AsRef<str>>(
+    url: &str,
+    version: &Version,
+    target: S,
+    progress: &Progress,
+) -> Result<ArchiveFile> {
+    progress.report(ProgressEvent::Resolving);
+    let repository = registry::get(url)?;
+    get_repository_archive_file(&*repository, version, target.as_ref(), progress).await
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the
+/// [repository](Repository), reporting [progress](ProgressEvent) to the [progress](Progress). See
+/// [get_archive_file_for_target].
+pub(crate) async fn get_repository_archive_file(
+    repository: &dyn Repository,
+    version: &Version,
+    target: &str,
+    progress: &Progress,
+) -> Result<ArchiveFile> {
+    let asset = repository.get_asset(version, target).await?;
+    let hash = repository.get_hash(&asset).await?;
+    let cache = match repository.cacheable() {
+        true => cache::get_default(),
+        false => None,
+    };
+
+    // Archives are opened as soon as they are found, so that another process evicting them from
+    // the cache cannot remove them before they are read; an archive that is evicted before it is
+    // opened is looked up again while the lock is held, and downloaded if it is still missing
+    let cached = cache
+        .as_ref()
+        .and_then(|cache| get_cached_archive(cache, repository, &asset, target, &hash));
+    let (path, archive_hash, file, temp_path) = match (cached, &cache) {
+        (Some((path, archive_hash, file)), _) => (path, archive_hash, file, None),
+        (None, Some(cache)) => {
+            let partial_path = cache.partial_path(&asset.version, target, &hash)?;
+            // Other processes share the partial file in the cache, so it is only written to while
+            // the lock is held
+            let _lock = PartialFileLock::acquire(&partial_path).await?;
+            match get_cached_archive(cache, repository, &asset, target, &hash) {
+                // The archive was cached by the process that held the lock
+                Some((path, archive_hash, file)) => (path, archive_hash, file, None),
+                None => {
+                    let archive_hash =
+                        write_verified_archive(repository, &asset, &hash, &partial_path, progress)
+                            .await?;
+                    let file = File::open(&partial_path)?;
+                    // Archives are cached by their SHA-256 hash, even if the repository only
+                    // publishes another hash
+                    match cache.put_file(&asset.version, target, &archive_hash, &partial_path) {
+                        Ok(path) => (path, archive_hash, file, None),
+                        Err(error) => {
+                            warn!("Failed to write archive {} to cache: {error}", asset.name);
+                            (
+                                partial_path.clone(),
+                                archive_hash,
+                                file,
+                                Some(TempPath::from_path(partial_path)),
+                            )
+                        }
+                    }
+                }
+            }
+        }
+        (None, None) => {
+            let temp_path = NamedTempFile::new()?.into_temp_path();
+            let archive_hash =
+                write_verified_archive(repository, &asset, &hash, &temp_path, progress).await?;
+            let file = File::open(&temp_path)?;
+            (temp_path.to_path_buf(), archive_hash, file, Some(temp_path))
+        }
+    };
+
+    // The verified archive is extracted as it is, unless the repository publishes archives that
+    // must be converted into a temporary file first
+    if repository.converts_archive(&asset) {
+        let converted_file = NamedTempFile::new()?;
+        let mut writer = HashWriter::new(BufWriter::new(converted_file.as_file()));
+        repository.convert_archive(&asset, &file, &mut writer)?;
+        let converted_hash = writer.finish()?;
+        let file = converted_file.reopen()?;
+        let converted_path = converted_file.into_temp_path();
+        debug!(
+            "Archive {} converted to {}",
+            asset.name,
+            converted_path.to_string_lossy()
+        );
+        progress.report(ProgressEvent::Done);
+        return Ok(ArchiveFile {
+            version: asset.version,
+            path: converted_path.to_path_buf(),
+            hash: converted_hash,
+            file,
+            temp_path: Some(converted_path),
+        });
+    }
+
+    progress.report(ProgressEvent::Done);
+    Ok(ArchiveFile {
+        version: asset.version,
+        path,
+        hash: archive_hash,
+        file,
+        temp_path,
+    })
+}
 
+/// Gets the path, SHA-256 hash and open file of the cached archive of the [asset](Asset) that
+/// matches the [hash](str) of the repository, or `None` if the archive is not cached or was evicted
+/// before it could be opened. Archives are cached by their SHA-256 hash; if the repository
+/// publishes another hash, such as the SHA-1 hash of zonky jars without a `.sha256` checksum, then
+/// the cached archives of the version and target are [verified](Repository::verify_archive)
+/// against it instead.
+pub(crate) fn get_cached_archive(
+    cache: &Cache,
+    repository: &dyn Repository,
+    asset: &Asset,
+    target: &str,
+    hash: &str,
+) -> Option<(PathBuf, String, File)> {
+    let archive_hash = match is_sha256(hash) {
+        true => hash.to_lowercase(),
+        false => cache
+            .hashes(&asset.version, target)
+            .into_iter()
+            .find(|archive_hash| {
+                let path = cache.archive_path(&asset.version, target, archive_hash);
+                repository
+                    .verify_archive(asset, &path, archive_hash, hash)
+                    .is_ok()
+            })?,
+    };
+
+    let path = cache.get_path(&asset.version, target, &archive_hash)?;
+    match File::open(&path) {
+        Ok(file) => Some((path, archive_hash, file)),
+        Err(error) => {
+            debug!(
+                "Cached archive {} could not be opened: {error}",
+                path.to_string_lossy()
+            );
+            None
+        }
+    }
+}
+
+/// Checks if the [hash](str) is a hex encoded SHA-256 hash
+fn is_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Writes the archive of the [asset](Asset) to the partial file at the [path](Path) and verifies
+/// it against the [hash](str) of the repository. If the archive does not match the hash, then the
+/// partial file is removed so that it is not resumed, and an [ArchiveHashMismatch] error is
+/// returned.
+///
+/// Returns the SHA-256 hash of the archive, computed while it was written.
+async fn write_verified_archive(
+    repository: &dyn Repository,
+    asset: &Asset,
+    hash: &str,
+    path: &Path,
+    progress: &Progress,
+) -> Result<String> {
+    let mut writer = ArchiveWriter::open(path)?.with_progress(progress.clone());
+    let size = repository.write_archive(asset, &mut writer).await?;
+    let archive_hash = writer.finish()?;
     debug!(
-        "Downloading archive hash {}",
-        asset_hash.browser_download_url
+        "Archive {} written to {}: {}",
+        asset.name,
+        path.to_string_lossy(),
+        human_bytes(size as f64)
     );
-    let client = reqwest_client();
-    let request = client.get(&asset_hash.browser_download_url);
+    progress.report(ProgressEvent::Verifying);
+    if let Err(error) = repository.verify_archive(asset, path, &archive_hash, hash) {
+        // The partial archive is corrupt, so it must not be resumed
+        remove_file(path)?;
+        return Err(error);
+    }
+    Ok(archive_hash)
+}
+
+/// Downloads the SHA-256 hash for the asset with the given name from the hash [url](str).
+/// If the hash is not found, then an [AssetHashNotFound] error is returned.
+#[instrument(level = "debug", skip(client))]
+pub(crate) async fn get_hash(
+    client: &ClientWithMiddleware,
+    url: &str,
+    asset_name: &str,
+) -> Result<String> {
+    debug!("Downloading archive hash {url}");
+    let request = client.get(url);
     let response = request.send().await?.error_for_status()?;
     let text = response.text().await?;
-    let re = Regex::new(r"[0-9a-f]{64}")?;
-    let hash = match re.find(&text) {
-        Some(hash) => hash.as_str().to_string(),
-        None => return Err(AssetHashNotFound(asset.name)),
-    };
+    let hash = parse_hash(&text, asset_name)?;
     debug!(
-        "Archive hash {} downloaded: {}",
-        asset_hash.browser_download_url,
+        "Archive hash {url} downloaded: {}",
         human_bytes(text.len() as f64)
     );
 
-    debug!("Downloading archive {}", asset.browser_download_url);
-    let request = client.get(&asset.browser_download_url);
-    let response = request.send().await?.error_for_status()?;
-    let archive: Bytes = response.bytes().await?;
-    debug!(
-        "Archive {} downloaded: {}",
-        asset.browser_download_url,
-        human_bytes(archive.len() as f64)
-    );
+    Ok(hash)
+}
+
+/// Parses the SHA-256 hash for the asset with the given name from the contents of a hash file.
+/// If the hash is not found, then an [AssetHashNotFound] error is returned.
+pub(crate) fn parse_hash(text: &str, asset_name: &str) -> Result<String> {
+    let re = Regex::new(r"[0-9a-f]{64}")?;
+    match re.find(text) {
+        Some(hash) => Ok(hash.as_str().to_string()),
+        None => Err(AssetHashNotFound(asset_name.to_string())),
+    }
+}
 
+/// Verifies that the SHA-256 hash of the archive [bytes](Bytes) matches the expected hash. If the
+/// hashes do not match, then an [ArchiveHashMismatch] error is returned.
+pub(crate) fn verify_hash(archive: &Bytes, hash: &str) -> Result<()> {
     let mut hasher = Sha256::new();
-    hasher.update(&archive);
-    let archive_hash = hex::encode(hasher.finalize());
+    hasher.update(archive);
+    verify_archive_hash(&hex::encode(hasher.finalize()), hash)
+}
 
+/// Verifies that the SHA-256 hash of an archive matches the expected hash. If the hashes do not
+/// match, then an [ArchiveHashMismatch] error is returned.
+pub(crate) fn verify_archive_hash(archive_hash: &str, hash: &str) -> Result<()> {
     if archive_hash != hash {
-        return Err(ArchiveHashMismatch { archive_hash, hash });
+        return Err(ArchiveHashMismatch {
+            archive_hash: archive_hash.to_string(),
+            hash: hash.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Reader that computes the SHA-256 hash of the bytes read through it
+pub(crate) struct HashReader<R> {
+    reader: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Gets the hex encoded SHA-256 hash of the bytes read
+    pub(crate) fn finalize(self) -> String {
+        hex::encode(self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.reader.read(buf)?;
+        self.hasher.update(&buf[..size]);
+        Ok(size)
+    }
+}
+
+/// Writer that computes the SHA-256 hash of the bytes written through it
+pub(crate) struct HashWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Flushes the writer and gets the hex encoded SHA-256 hash of the bytes written
+    pub(crate) fn finish(mut self) -> Result<String> {
+        self.writer.flush()?;
+        Ok(hex::encode(self.hasher.finalize()))
     }
+}
 
-    Ok((asset_version, archive))
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.writer.write(buf)?;
+        self.hasher.update(&buf[..size]);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
 }
 
 /// Acquires a lock file in the [out_dir](Path) to prevent multiple processes from extracting the
@@ -319,15 +601,55 @@ fn acquire_lock(out_dir: &Path) -> Result<PathBuf> {
     Err(Unexpected("Failed to acquire lock".to_string()))
 }
 
-/// Extracts the compressed tar [bytes](Bytes) to the [out_dir](Path).
+/// Extracts the archive [bytes](Bytes) to the [out_dir](Path). The [format](ArchiveFormat) of the
+/// archive is detected from its magic bytes; formats other than gzip compressed tar archives
+/// require the cargo feature of the format, otherwise an
+/// [UnsupportedArchiveFormat](crate::Error::UnsupportedArchiveFormat) error is returned.
 #[instrument(skip(bytes))]
 pub async fn extract(bytes: &Bytes, out_dir: &Path) -> Result<()> {
-    let input = BufReader::new(Cursor::new(bytes));
-    let decoder = GzDecoder::new(input);
-    let mut archive = Archive::new(decoder);
-    let mut files = 0;
-    let mut extracted_bytes = 0;
+    extract_with_progress(bytes, out_dir, &Progress::default()).await
+}
 
+/// Extracts the archive [bytes](Bytes) to the [out_dir](Path), reporting
+/// [progress](ProgressEvent) to the [progress](Progress).
+#[instrument(skip(bytes))]
+pub async fn extract_with_progress(
+    bytes: &Bytes,
+    out_dir: &Path,
+    progress: &Progress,
+) -> Result<()> {
+    extract_archive(Cursor::new(bytes), out_dir, None, progress)
+}
+
+/// Extracts the [archive file](ArchiveFile) to the [out_dir](Path). The archive is
+/// read from disk as it is extracted, and its SHA-256 hash is verified before the extracted files
+/// are moved to the [out_dir](Path). If the hash does not match, then an [ArchiveHashMismatch]
+/// error is returned and nothing is extracted.
+#[instrument(skip(archive), fields(archive = %archive.path().to_string_lossy()))]
+pub async fn extract_file(archive: &ArchiveFile, out_dir: &Path) -> Result<()> {
+    extract_file_with_progress(archive, out_dir, &Progress::default()).await
+}
+
+/// Extracts the [archive file](ArchiveFile) to the [out_dir](Path), reporting
+/// [progress](ProgressEvent) to the [progress](Progress). See [extract_file].
+#[instrument(skip(archive), fields(archive = %archive.path().to_string_lossy()))]
+pub async fn extract_file_with_progress(
+    archive: &ArchiveFile,
+    out_dir: &Path,
+    progress: &Progress,
+) -> Result<()> {
+    extract_archive(archive.open()?, out_dir, Some(archive.hash()), progress)
+}
+
+/// Extracts the archive read from the [input](Read) to the [out_dir](Path). The
+/// archive is extracted to a temporary directory that is renamed to the [out_dir](Path) once
+/// extraction completes and, if a hash is specified, the SHA-256 hash of the input matches it.
+fn extract_archive<R: Read + Seek>(
+    input: R,
+    out_dir: &Path,
+    hash: Option<&str>,
+    progress: &Progress,
+) -> Result<()> {
     let parent_dir = match out_dir.parent() {
         Some(parent) => parent,
         None => {
@@ -346,133 +668,971 @@ pub async fn extract(bytes: &Bytes, out_dir: &Path) -> Result<()> {
             out_dir.to_string_lossy()
         );
         remove_file(&lock_file)?;
+        progress.report(ProgressEvent::Done);
         return Ok(());
     }
 
     let extract_dir = tempfile::tempdir_in(parent_dir)?.into_path();
     debug!("Extracting archive to {}", extract_dir.to_string_lossy());
 
-    for archive_entry in archive.entries()? {
-        let mut entry = archive_entry?;
-        let entry_header = entry.header();
-        let entry_type = entry_header.entry_type();
-        let entry_size = entry_header.size()?;
-        #[cfg(unix)]
-        let file_mode = entry_header.mode()?;
+    let result = extract_entries(input, &extract_dir, hash, progress);
+    let (files, extracted_bytes) = match result {
+        Ok(extracted) => extracted,
+        Err(error) => {
+            remove_dir_all(&extract_dir)?;
+            remove_file(&lock_file)?;
+            return Err(error);
+        }
+    };
+
+    if out_dir.exists() {
+        debug!(
+            "Directory already exists {}; skipping name and removing extraction directory: {}",
+            out_dir.to_string_lossy(),
+            extract_dir.to_string_lossy()
+        );
+        remove_dir_all(&extract_dir)?;
+    } else {
+        debug!(
+            "Renaming {} to {}",
+            extract_dir.to_string_lossy(),
+            out_dir.to_string_lossy()
+        );
+        rename(extract_dir, out_dir)?;
+    }
+
+    if lock_file.is_file() {
+        debug!("Removing lock file: {}", lock_file.to_string_lossy());
+        remove_file(lock_file)?;
+    }
+
+    debug!(
+        "Extracting {} files totalling {}",
+        files.to_formatted_string(&Locale::en),
+        human_bytes(extracted_bytes as f64)
+    );
 
-        let entry_header_path = entry_header.path()?.to_path_buf();
-        let prefix = match entry_header_path.components().next() {
-            Some(component) => component.as_os_str().to_str().unwrap_or_default(),
+    progress.report(ProgressEvent::Done);
+    Ok(())
+}
+
+/// Extracts the entries of the archive read from the [input](Read) to the
+/// [extract_dir](Path), stripping the first component of the entry paths (e.g. `postgresql/`)
+/// unless a tar archive starts with a `./` entry.
+/// The [format](ArchiveFormat) of the archive is detected from its magic bytes. If a hash is
+/// specified, then the SHA-256 hash of the input is verified.
+///
+/// Returns the number of files and bytes extracted.
+fn extract_entries<R: Read + Seek>(
+    mut input: R,
+    extract_dir: &Path,
+    hash: Option<&str>,
+    progress: &Progress,
+) -> Result<(u64, u64)> {
+    let mut magic_bytes = Vec::new();
+    input.by_ref().take(6).read_to_end(&mut magic_bytes)?;
+    input.seek(SeekFrom::Start(0))?;
+    let format = ArchiveFormat::from_magic_bytes(&magic_bytes)
+        .ok_or_else(|| UnsupportedArchiveFormat("unknown archive format".to_string()))?;
+    format.check_supported()?;
+    debug!("Detected {format} archive");
+
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => extract_zip_entries(input, extract_dir, hash, progress),
+        _ => extract_tar_entries(input, format, extract_dir, hash, progress),
+    }
+}
+
+/// Returns an [UnsafeArchiveEntry] error naming the [entry path](Path) and the reason it is unsafe
+pub(crate) fn unsafe_archive_entry(entry_path: &Path, reason: &str) -> crate::Error {
+    UnsafeArchiveEntry {
+        path: entry_path.to_string_lossy().to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Gets the path in the [extract_dir](Path) of an archive entry, stripping the first component of
+/// the [entry path](Path) unless the entries of the archive are at its root. Returns an
+/// [UnsafeArchiveEntry] error if the entry path is absolute or contains `..` components, so that
+/// every entry stays inside the [extract_dir](Path).
+fn entry_path(extract_dir: &Path, entry_path: &Path, strip_first: bool) -> Result<PathBuf> {
+    let mut components = entry_path.components();
+    if strip_first {
+        match components.next() {
+            Some(Component::Normal(_) | Component::CurDir) => {}
+            Some(Component::ParentDir) => {
+                return Err(unsafe_archive_entry(
+                    entry_path,
+                    "parent directory component",
+                ));
+            }
+            Some(Component::RootDir | Component::Prefix(_)) => {
+                return Err(unsafe_archive_entry(entry_path, "absolute path"));
+            }
             None => {
                 return Err(Unexpected(
                     "Failed to get file header path prefix".to_string(),
                 ));
             }
-        };
-        let stripped_entry_header_path = entry_header_path.strip_prefix(prefix)?.to_path_buf();
-        let mut entry_name = extract_dir.to_path_buf();
-        entry_name.push(stripped_entry_header_path);
+        }
+    }
+
+    let mut path = extract_dir.to_path_buf();
+    for component in components {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(unsafe_archive_entry(
+                    entry_path,
+                    "parent directory component",
+                ));
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_archive_entry(entry_path, "absolute path"));
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Validates that the [target](Path) of the symlink at the [link path](Path) resolves inside the
+/// [extract_dir](Path). Absolute targets are rejected, and `..` components are only allowed at the
+/// start of the target, where they are resolved from the canonical parent directory of the link.
+/// Every symlink therefore points inside the [extract_dir](Path), and so do the paths of entries
+/// written through a symlink.
+fn validate_symlink(
+    extract_dir: &Path,
+    link_path: &Path,
+    target: &Path,
+    entry_path: &Path,
+) -> Result<()> {
+    let root = extract_dir.canonicalize()?;
+    let parent = link_path.parent().unwrap_or(extract_dir);
+    create_dir_all(parent)?;
+    let mut resolved = parent.canonicalize()?;
+    let mut descending = false;
+
+    for component in target.components() {
+        match component {
+            Component::Normal(name) => {
+                descending = true;
+                resolved.push(name);
+            }
+            Component::CurDir => {}
+            Component::ParentDir if !descending => {
+                resolved.pop();
+            }
+            Component::ParentDir => {
+                return Err(unsafe_archive_entry(
+                    entry_path,
+                    "symlink target contains a parent directory component after a name",
+                ));
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_archive_entry(
+                    entry_path,
+                    "symlink target is an absolute path",
+                ));
+            }
+        }
+        if !resolved.starts_with(&root) {
+            return Err(unsafe_archive_entry(
+                entry_path,
+                "symlink target is outside the extraction directory",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Creates the symlink at the [link path](Path) after [validating](validate_symlink) the
+/// [target](Path). Symlinks are only created on unix; on other platforms they are validated and
+/// skipped.
+pub(crate) fn create_symlink(
+    extract_dir: &Path,
+    link_path: &Path,
+    target: &Path,
+    entry_path: &Path,
+) -> Result<()> {
+    validate_symlink(extract_dir, link_path, target, entry_path)?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link_path)?;
+    Ok(())
+}
+
+/// Sets the modified time of the directory at the [path](Path). This is best effort, since
+/// directories cannot be opened as files on every platform.
+fn set_directory_modified(path: &Path, modified: SystemTime) {
+    if let Err(error) = File::open(path).and_then(|directory| directory.set_modified(modified)) {
+        debug!(
+            "Failed to set the modified time of {}: {error}",
+            path.display()
+        );
+    }
+}
+
+/// Extracts the entries of the compressed tar archive read from the [input](Read) to the
+/// [extract_dir](Path). Archives that start with a `./` entry have their entries at the root, so
+/// the first component of their entry paths is kept. The hash is verified once the whole input has
+/// been read. Entries that
+/// would be extracted outside the [extract_dir](Path) return an [UnsafeArchiveEntry] error; the
+/// modified times of files and directories are preserved.
+fn extract_tar_entries<R: Read>(
+    input: R,
+    format: ArchiveFormat,
+    extract_dir: &Path,
+    hash: Option<&str>,
+    progress: &Progress,
+) -> Result<(u64, u64)> {
+    let mut input = HashReader::new(input);
+    let mut archive = Archive::new(format.tar_decoder(BufReader::new(&mut input))?);
+    let mut files = 0;
+    let mut extracted_bytes = 0;
+    let mut directories = Vec::new();
+    let mut strip_first = None;
+
+    for archive_entry in archive.entries()? {
+        let mut entry = archive_entry?;
+        let entry_header = entry.header();
+        let entry_type = entry_header.entry_type();
+        let modified = UNIX_EPOCH + Duration::from_secs(entry_header.mtime()?);
+        #[cfg(unix)]
+        let file_mode = entry_header.mode()?;
+        // The path and size of the entry include GNU long name and PAX extensions, which paths
+        // longer than the 100 bytes of the header require
+        let entry_size = entry.size();
+        let header_path = entry.path()?.to_path_buf();
+        let strip_first = *strip_first.get_or_insert_with(|| {
+            !header_path
+                .components()
+                .all(|component| component == Component::CurDir)
+        });
+        let entry_name = entry_path(extract_dir, &header_path, strip_first)?;
 
         if entry_type.is_dir() || entry_name.is_dir() {
             create_dir_all(&entry_name)?;
+            directories.push((entry_name, modified));
         } else if entry_type.is_file() {
+            if let Some(parent) = entry_name.parent() {
+                create_dir_all(parent)?;
+            }
             let mut output_file = File::create(&entry_name)?;
             copy(&mut entry, &mut output_file)?;
+            output_file.set_modified(modified)?;
 
             files += 1;
             extracted_bytes += entry_size;
+            progress.report(ProgressEvent::Extracting {
+                files,
+                bytes: extracted_bytes,
+            });
 
             #[cfg(unix)]
             {
                 use std::os::unix::fs::PermissionsExt;
-                output_file.set_permissions(std::fs::Permissions::from_mode(file_mode))?;
+                // Strip the setuid, setgid and sticky bits
+                output_file.set_permissions(std::fs::Permissions::from_mode(file_mode & 0o777))?;
             }
         } else if entry_type.is_symlink() {
-            #[cfg(unix)]
             if let Some(symlink_target) = entry.link_name()? {
-                let symlink_path = entry_name;
-                std::os::unix::fs::symlink(symlink_target.as_ref(), symlink_path)?;
+                create_symlink(extract_dir, &entry_name, &symlink_target, &header_path)?;
+            }
+        } else if entry_type.is_hard_link() {
+            if let Some(link_target) = entry.link_name()? {
+                // Hard link targets are archive paths of entries extracted earlier
+                let link_target =
+                    entry_path(extract_dir, &link_target, strip_first).map_err(|_| {
+                        unsafe_archive_entry(&header_path, "hard link target is unsafe")
+                    })?;
+                if let Some(parent) = entry_name.parent() {
+                    create_dir_all(parent)?;
+                }
+                hard_link(link_target, &entry_name)?;
+
+                files += 1;
+                progress.report(ProgressEvent::Extracting {
+                    files,
+                    bytes: extracted_bytes,
+                });
             }
+        } else {
+            debug!(
+                "Skipping {entry_type:?} archive entry {}",
+                header_path.display()
+            );
         }
     }
+    drop(archive);
 
-    if out_dir.exists() {
-        debug!(
-            "Directory already exists {}; skipping name and removing extraction directory: {}",
-            out_dir.to_string_lossy(),
-            extract_dir.to_string_lossy()
-        );
-        remove_dir_all(&extract_dir)?;
-    } else {
-        debug!(
-            "Renaming {} to {}",
-            extract_dir.to_string_lossy(),
-            out_dir.to_string_lossy()
-        );
-        rename(extract_dir, out_dir)?;
+    // Set the modified times of the directories last, since extracting entries into a directory
+    // updates its modified time
+    for (directory, modified) in directories.into_iter().rev() {
+        set_directory_modified(&directory, modified);
     }
 
-    if lock_file.is_file() {
-        debug!("Removing lock file: {}", lock_file.to_string_lossy());
-        remove_file(lock_file)?;
+    // Read the remainder of the input, such as the end of the compressed stream, so that the
+    // hash covers the whole archive
+    copy(&mut input, &mut sink())?;
+    if let Some(hash) = hash {
+        progress.report(ProgressEvent::Verifying);
+        verify_archive_hash(&input.finalize(), hash)?;
     }
 
-    debug!(
-        "Extracting {} files totalling {}",
-        files.to_formatted_string(&Locale::en),
-        human_bytes(extracted_bytes as f64)
-    );
+    Ok((files, extracted_bytes))
+}
 
-    Ok(())
+/// Converts the MS-DOS [date and time](zip::DateTime) of a zip entry, which has no time zone, to a
+/// [SystemTime] as if it were UTC
+#[cfg(feature = "zip")]
+fn zip_modified(date_time: zip::DateTime) -> SystemTime {
+    // Days from the civil date: https://howardhinnant.github.io/date_algorithms.html
+    let month = u64::from(date_time.month());
+    let year = u64::from(date_time.year()) - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + u64::from(date_time.day()) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = days * 86_400
+        + u64::from(date_time.hour()) * 3_600
+        + u64::from(date_time.minute()) * 60
+        + u64::from(date_time.second());
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+/// Extracts the entries of the zip archive read from the [input](Read) to the
+/// [extract_dir](Path). Zip archives are read from the central directory at the end of the input,
+/// so the hash is verified before any entry is extracted. Entries that would be extracted outside
+/// the [extract_dir](Path) return an [UnsafeArchiveEntry] error; the modified times of files and
+/// directories are preserved.
+#[cfg(feature = "zip")]
+fn extract_zip_entries<R: Read + Seek>(
+    mut input: R,
+    extract_dir: &Path,
+    hash: Option<&str>,
+    progress: &Progress,
+) -> Result<(u64, u64)> {
+    if let Some(hash) = hash {
+        progress.report(ProgressEvent::Verifying);
+        let mut hash_reader = HashReader::new(&mut input);
+        copy(&mut hash_reader, &mut sink())?;
+        verify_archive_hash(&hash_reader.finalize(), hash)?;
+        input.seek(SeekFrom::Start(0))?;
+    }
+
+    let mut archive = zip::ZipArchive::new(input)?;
+    let mut files = 0;
+    let mut extracted_bytes = 0;
+    let mut directories = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let header_path = PathBuf::from(entry.name());
+        let entry_name = entry_path(extract_dir, &header_path, true)?;
+        let modified = entry.last_modified().map(zip_modified);
+
+        if entry.is_dir() {
+            create_dir_all(&entry_name)?;
+            if let Some(modified) = modified {
+                directories.push((entry_name, modified));
+            }
+        } else if entry.is_symlink() {
+            let mut symlink_target = String::new();
+            entry.read_to_string(&mut symlink_target)?;
+            create_symlink(
+                extract_dir,
+                &entry_name,
+                Path::new(&symlink_target),
+                &header_path,
+            )?;
+        } else {
+            // Zip archives do not always contain entries for directories
+            if let Some(parent) = entry_name.parent() {
+                create_dir_all(parent)?;
+            }
+            let mut output_file = File::create(&entry_name)?;
+            copy(&mut entry, &mut output_file)?;
+            if let Some(modified) = modified {
+                output_file.set_modified(modified)?;
+            }
+
+            files += 1;
+            extracted_bytes += entry.size();
+            progress.report(ProgressEvent::Extracting {
+                files,
+                bytes: extracted_bytes,
+            });
+
+            #[cfg(unix)]
+            if let Some(file_mode) = entry.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                // Strip the setuid, setgid and sticky bits
+                output_file.set_permissions(std::fs::Permissions::from_mode(file_mode & 0o777))?;
+            }
+        }
+    }
+
+    for (directory, modified) in directories.into_iter().rev() {
+        set_directory_modified(&directory, modified);
+    }
+
+    Ok((files, extracted_bytes))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
     use test_log::test;
 
-    /// Use a known, fully defined version to speed up test execution
-    const VERSION: Version = Version::new(16, Some(1), Some(0));
-    const INVALID_VERSION: Version = Version::new(1, Some(0), Some(0));
+    #[test]
+    fn test_archive_asset_name() {
+        let version = Version::new(16, Some(2), Some(0));
+        assert_eq!(
+            "postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz",
+            archive_asset_name(&version, "x86_64-unknown-linux-gnu")
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_unsupported_repository() {
+        let version = Version::new(16, Some(2), Some(0));
+        let result = get_archive_from("unsupported://repository", &version).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::UnsupportedRepository(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_hash() -> Result<()> {
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert_eq!(
+            hash,
+            parse_hash(&format!("{hash}  archive.tar.gz\n"), "archive")?
+        );
+        assert!(matches!(
+            parse_hash("invalid", "archive"),
+            Err(AssetHashNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_hash() -> Result<()> {
+        let archive = Bytes::from("test");
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        verify_hash(&archive, hash)?;
+        assert!(matches!(
+            verify_hash(&archive, "invalid"),
+            Err(ArchiveHashMismatch { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_reader_writer() -> Result<()> {
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let mut reader = HashReader::new(Cursor::new("test"));
+        let mut writer = HashWriter::new(Vec::new());
+        copy(&mut reader, &mut writer)?;
+        assert_eq!(hash, reader.finalize());
+        assert_eq!(hash, writer.finish()?);
+        Ok(())
+    }
+
+    /// Create a tar archive with a `postgresql/bin/postgres` file
+    fn tar_archive() -> Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for dir in ["postgresql/", "postgresql/bin/"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, dir, std::io::empty())?;
+        }
+        let contents = b"postgres";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(&mut header, "postgresql/bin/postgres", &contents[..])?;
+        Ok(builder.into_inner()?)
+    }
+
+    /// Create an archive in the [format](ArchiveFormat) with a `postgresql/bin/postgres` file
+    fn archive(format: ArchiveFormat) -> Result<Vec<u8>> {
+        let tar = tar_archive()?;
+        let archive = match format {
+            ArchiveFormat::TarGz => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&tar)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "xz")]
+            ArchiveFormat::TarXz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(&tar)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "zstd")]
+            ArchiveFormat::TarZst => zstd::stream::encode_all(tar.as_slice(), 0)?,
+            #[cfg(feature = "bzip2")]
+            ArchiveFormat::TarBz2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(&tar)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "zip")]
+            ArchiveFormat::Zip => {
+                use zip::write::SimpleFileOptions;
+                let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+                // Zip archives do not always contain entries for directories
+                zip.start_file(
+                    "postgresql/bin/postgres",
+                    SimpleFileOptions::default().unix_permissions(0o755),
+                )?;
+                zip.write_all(b"postgres")?;
+                zip.finish()?.into_inner()
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(UnsupportedArchiveFormat(format!(
+                    "{format} fixture requires the `{}` feature",
+                    format.feature().unwrap_or_default()
+                )))
+            }
+        };
+        Ok(archive)
+    }
+
+    /// Create a directory repository with an archive of the version for the target
+    fn repository(version: &str, target: &str) -> Result<tempfile::TempDir> {
+        let archive = archive(ArchiveFormat::TarGz)?;
+
+        let dir = tempfile::tempdir()?;
+        let name = archive_asset_name(&Version::from_str(version)?, target);
+        std::fs::write(dir.path().join(&name), &archive)?;
+        let hash = hex::encode(Sha256::digest(&archive));
+        std::fs::write(
+            dir.path().join(format!("{name}.sha256")),
+            format!("{hash}  {name}\n"),
+        )?;
+        Ok(dir)
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_file_and_extract() -> Result<()> {
+        let target = "x86_64-unknown-linux-gnu";
+        let repository_dir = repository("16.2.0", target)?;
+        let url = repository_dir.path().to_string_lossy().to_string();
+        let archive =
+            get_archive_file_for_target(&url, &Version::new(16, None, None), target).await?;
+        assert_eq!(&Version::new(16, Some(2), Some(0)), archive.version());
+        assert_eq!(
+            (Version::new(16, Some(2), Some(0)), archive.read()?),
+            get_archive_for_target_from(&url, &Version::new(16, None, None), target).await?
+        );
+
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        extract_file(&archive, &installation_dir).await?;
+        assert_eq!(
+            "postgres",
+            std::fs::read_to_string(installation_dir.join("bin").join("postgres"))?
+        );
+
+        let temp_path = archive.path().to_path_buf();
+        drop(archive);
+        assert!(!temp_path.exists());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_file_and_extract_with_progress() -> Result<()> {
+        let target = "x86_64-unknown-linux-gnu";
+        let repository_dir = repository("16.2.0", target)?;
+        let url = repository_dir.path().to_string_lossy().to_string();
+        let archive_size = std::fs::metadata(repository_dir.path().join(archive_asset_name(
+            &Version::new(16, Some(2), Some(0)),
+            target,
+        )))?
+        .len();
+        let (progress, receiver) = Progress::channel();
+
+        let archive = get_archive_file_for_target_with_progress(
+            &url,
+            &Version::new(16, None, None),
+            target,
+            &progress,
+        )
+        .await?;
+        assert_eq!(
+            vec![
+                ProgressEvent::Resolving,
+                ProgressEvent::Downloading {
+                    bytes: 0,
+                    total: Some(archive_size)
+                },
+                ProgressEvent::Downloading {
+                    bytes: archive_size,
+                    total: Some(archive_size)
+                },
+                ProgressEvent::Verifying,
+                ProgressEvent::Done,
+            ],
+            receiver.try_iter().collect::<Vec<_>>()
+        );
+
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        extract_file_with_progress(&archive, &installation_dir, &progress).await?;
+        assert_eq!(
+            vec![
+                ProgressEvent::Extracting { files: 1, bytes: 8 },
+                ProgressEvent::Verifying,
+                ProgressEvent::Done,
+            ],
+            receiver.try_iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_extract_formats() -> Result<()> {
+        for format in ArchiveFormat::ALL {
+            let out_dir = tempfile::tempdir()?;
+            let installation_dir = out_dir.path().join("16.2.0");
+            if !format.is_supported() {
+                // Archives in formats whose feature is disabled cannot be created either
+                assert!(matches!(archive(format), Err(UnsupportedArchiveFormat(_))));
+                continue;
+            }
+
+            let bytes = Bytes::from(archive(format)?);
+            assert_eq!(Some(format), ArchiveFormat::from_magic_bytes(&bytes));
+            extract(&bytes, &installation_dir).await?;
+            assert_eq!(
+                "postgres",
+                std::fs::read_to_string(installation_dir.join("bin").join("postgres"))?,
+                "{format}"
+            );
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let metadata = std::fs::metadata(installation_dir.join("bin").join("postgres"))?;
+                assert_eq!(0o755, metadata.permissions().mode() & 0o777, "{format}");
+            }
+        }
+        Ok(())
+    }
 
     #[test(tokio::test)]
-    async fn test_get_release() -> Result<()> {
-        let _ = get_release(&VERSION).await?;
+    async fn test_extract_file_formats() -> Result<()> {
+        for format in ArchiveFormat::ALL
+            .into_iter()
+            .filter(ArchiveFormat::is_supported)
+        {
+            let archive_file = NamedTempFile::new()?;
+            let bytes = archive(format)?;
+            std::fs::write(archive_file.path(), &bytes)?;
+            let archive = ArchiveFile {
+                version: Version::new(16, Some(2), Some(0)),
+                path: archive_file.path().to_path_buf(),
+                hash: hex::encode(Sha256::digest(&bytes)),
+                file: archive_file.reopen()?,
+                temp_path: None,
+            };
+
+            let out_dir = tempfile::tempdir()?;
+            let installation_dir = out_dir.path().join("16.2.0");
+            extract_file(&archive, &installation_dir).await?;
+            assert!(installation_dir.join("bin").join("postgres").is_file());
+
+            let archive = ArchiveFile {
+                hash: "0".repeat(64),
+                ..archive
+            };
+            let installation_dir = out_dir.path().join("16.2.1");
+            let result = extract_file(&archive, &installation_dir).await;
+            assert!(
+                matches!(result, Err(ArchiveHashMismatch { .. })),
+                "{format}"
+            );
+            assert!(!installation_dir.exists());
+        }
         Ok(())
     }
 
     #[test(tokio::test)]
-    async fn test_get_release_version_not_found() -> Result<()> {
-        let release = get_release(&INVALID_VERSION).await;
-        assert!(release.is_err());
+    async fn test_extract_file_removed() -> Result<()> {
+        let target = "x86_64-unknown-linux-gnu";
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path());
+        let version = Version::new(16, Some(2), Some(0));
+        let bytes = Bytes::from(archive(ArchiveFormat::TarGz)?);
+        let hash = hex::encode(Sha256::digest(&bytes));
+        cache.put(&version, target, &hash, &bytes)?;
+        let repository = registry::get(DEFAULT_POSTGRESQL_URL)?;
+        let asset = Asset {
+            version,
+            name: archive_asset_name(&version, target),
+            url: String::new(),
+            hash_url: String::new(),
+        };
+        let (path, archive_hash, file) =
+            get_cached_archive(&cache, &*repository, &asset, target, &hash)
+                .expect("cached archive");
+        let archive = ArchiveFile {
+            version,
+            path,
+            hash: archive_hash,
+            file,
+            temp_path: None,
+        };
+
+        // Another process evicts the archive after it was found in the cache
+        cache.clean()?;
+        assert_eq!(None, cache.get_path(&version, target, &hash));
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        extract_file(&archive, &installation_dir).await?;
+        assert!(installation_dir.join("bin").join("postgres").is_file());
+        assert_eq!(bytes, archive.read()?);
         Ok(())
     }
 
     #[test(tokio::test)]
-    async fn test_get_asset() -> Result<()> {
-        let target_triple = "x86_64-unknown-linux-musl".to_string();
-        let (asset_version, asset, asset_hash) = get_asset(&VERSION, &target_triple).await?;
-        assert!(asset_version.matches(&VERSION));
-        assert!(asset.name.contains(&target_triple));
-        assert!(asset_hash.name.contains(&target_triple));
-        assert!(asset_hash.name.starts_with(asset.name.as_str()));
-        assert!(asset_hash.name.ends_with(".sha256"));
+    async fn test_extract_unknown_format() -> Result<()> {
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        let result = extract(&Bytes::from_static(b"archive"), &installation_dir).await;
+        assert!(matches!(result, Err(UnsupportedArchiveFormat(_))));
+        assert!(!installation_dir.exists());
         Ok(())
     }
 
     #[test(tokio::test)]
-    async fn test_get_asset_version_not_found() -> Result<()> {
-        let target_triple = "x86_64-unknown-linux-musl".to_string();
-        let result = get_asset(&INVALID_VERSION, &target_triple).await;
-        assert!(result.is_err());
+    async fn test_extract_file_hash_mismatch() -> Result<()> {
+        let target = "x86_64-unknown-linux-gnu";
+        let repository_dir = repository("16.2.0", target)?;
+        let url = repository_dir.path().to_string_lossy().to_string();
+        let archive =
+            get_archive_file_for_target(&url, &Version::new(16, None, None), target).await?;
+        let archive = ArchiveFile {
+            hash: "0".repeat(64),
+            ..archive
+        };
+
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        let result = extract_file(&archive, &installation_dir).await;
+        assert!(matches!(result, Err(ArchiveHashMismatch { .. })));
+        assert!(!installation_dir.exists());
+        assert_eq!(0, std::fs::read_dir(out_dir.path())?.count());
         Ok(())
     }
 
+    /// Modified time of the entries of the [raw tar archive](raw_tar_archive)
+    const MTIME: u64 = 1_700_000_000;
+
+    /// Create a gzip compressed tar archive of the raw entries (path, entry type, link name and
+    /// contents), bypassing the path validation of the tar builder
+    fn raw_tar_archive(entries: &[(&str, tar::EntryType, &str, &str)]) -> Result<Bytes> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for (path, entry_type, link_name, contents) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.as_old_mut().linkname[..link_name.len()].copy_from_slice(link_name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_mtime(MTIME);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes())?;
+        }
+        Ok(Bytes::from(builder.into_inner()?.finish()?))
+    }
+
     #[test(tokio::test)]
-    async fn test_get_asset_target_not_found() -> Result<()> {
-        let target_triple = "wasm64-unknown-unknown".to_string();
-        let result = get_asset(&VERSION, &target_triple).await;
-        assert!(result.is_err());
+    async fn test_extract_unsafe_entries() -> Result<()> {
+        let directory = ("postgresql/", tar::EntryType::Directory, "", "");
+        let unsafe_entries = [
+            (
+                "postgresql/../escape",
+                tar::EntryType::Regular,
+                "",
+                "escape",
+            ),
+            (
+                "postgresql/bin/../../escape",
+                tar::EntryType::Regular,
+                "",
+                "escape",
+            ),
+            ("../escape", tar::EntryType::Regular, "", "escape"),
+            ("/tmp/escape", tar::EntryType::Regular, "", "escape"),
+            ("postgresql/lib", tar::EntryType::Symlink, "..", ""),
+            ("postgresql/lib", tar::EntryType::Symlink, "/etc", ""),
+            ("postgresql/lib", tar::EntryType::Symlink, "bin/../..", ""),
+            (
+                "postgresql/lib",
+                tar::EntryType::Link,
+                "postgresql/../escape",
+                "",
+            ),
+        ];
+
+        for entry in unsafe_entries {
+            let out_dir = tempfile::tempdir()?;
+            let installation_dir = out_dir.path().join("16.2.0");
+            let archive = raw_tar_archive(&[directory, entry])?;
+            let result = extract(&archive, &installation_dir).await;
+            assert!(
+                matches!(&result, Err(UnsafeArchiveEntry { path, .. }) if path == entry.0),
+                "{entry:?}: {result:?}"
+            );
+            assert!(!installation_dir.exists(), "{entry:?}");
+            assert_eq!(0, std::fs::read_dir(out_dir.path())?.count(), "{entry:?}");
+        }
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_extract_links_and_modified_times() -> Result<()> {
+        let archive = raw_tar_archive(&[
+            ("postgresql/", tar::EntryType::Directory, "", ""),
+            ("postgresql/bin/", tar::EntryType::Directory, "", ""),
+            (
+                "postgresql/bin/postgres",
+                tar::EntryType::Regular,
+                "",
+                "postgres",
+            ),
+            (
+                "postgresql/bin/postmaster",
+                tar::EntryType::Link,
+                "postgresql/bin/postgres",
+                "",
+            ),
+            (
+                "postgresql/lib/libpq.so.5",
+                tar::EntryType::Regular,
+                "",
+                "libpq",
+            ),
+            (
+                "postgresql/lib/libpq.so",
+                tar::EntryType::Symlink,
+                "libpq.so.5",
+                "",
+            ),
+            (
+                "postgresql/bin/libpq.so",
+                tar::EntryType::Symlink,
+                "../lib/libpq.so",
+                "",
+            ),
+        ])?;
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        extract(&archive, &installation_dir).await?;
+
+        let bin_dir = installation_dir.join("bin");
+        assert_eq!(
+            "postgres",
+            std::fs::read_to_string(bin_dir.join("postmaster"))?
+        );
+        let modified = UNIX_EPOCH + Duration::from_secs(MTIME);
+        assert_eq!(
+            modified,
+            std::fs::metadata(bin_dir.join("postgres"))?.modified()?
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(2, std::fs::metadata(bin_dir.join("postgres"))?.nlink());
+            assert_eq!(modified, std::fs::metadata(&bin_dir)?.modified()?);
+            assert_eq!("libpq", std::fs::read_to_string(bin_dir.join("libpq.so"))?);
+        }
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_extract_long_paths() -> Result<()> {
+        let dir = format!("postgresql/share/{}", "extension/".repeat(12));
+        let file = format!("{dir}{}.sql", "postgres".repeat(16));
+        let link = format!("{dir}{}.sql", "postmaster".repeat(16));
+        assert!(file.len() > 100 && link.len() > 100);
+
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let contents = b"postgres";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, &file, &contents[..])?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Link);
+        header.set_size(0);
+        header.set_mode(0o644);
+        builder.append_link(&mut header, &link, &file)?;
+        let archive = Bytes::from(builder.into_inner()?.finish()?);
+
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        extract(&archive, &installation_dir).await?;
+
+        let relative_dir = dir.trim_start_matches("postgresql/");
+        let file_name = file.trim_start_matches(&dir);
+        let link_name = link.trim_start_matches(&dir);
+        let extension_dir = installation_dir.join(relative_dir);
+        assert_eq!(
+            "postgres",
+            std::fs::read_to_string(extension_dir.join(file_name))?
+        );
+        assert_eq!(
+            "postgres",
+            std::fs::read_to_string(extension_dir.join(link_name))?
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_zip_modified() -> Result<()> {
+        let date_time = zip::DateTime::from_date_and_time(2024, 1, 2, 3, 4, 6)
+            .map_err(|error| Unexpected(format!("{error:?}")))?;
+        assert_eq!(
+            UNIX_EPOCH + Duration::from_secs(1_704_164_646),
+            zip_modified(date_time)
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    #[test(tokio::test)]
+    async fn test_extract_unsafe_zip_entries() -> Result<()> {
+        use zip::write::SimpleFileOptions;
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.add_symlink("postgresql/lib", "../..", SimpleFileOptions::default())?;
+        let archive = Bytes::from(zip.finish()?.into_inner());
+
+        let out_dir = tempfile::tempdir()?;
+        let installation_dir = out_dir.path().join("16.2.0");
+        let result = extract(&archive, &installation_dir).await;
+        assert!(matches!(result, Err(UnsafeArchiveEntry { .. })));
+        assert!(!installation_dir.exists());
         Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use crate::Version;
+use crate::{ArchiveFile, Progress, Version};
 use bytes::Bytes;
 use std::path::Path;
 use tokio::runtime::Runtime;
@@ -7,18 +7,38 @@ lazy_static! {
     static ref RUNTIME: Runtime = Runtime::new().unwrap();
 }
 
-/// Gets the version of PostgreSQL for the specified [version](Version).  If the version minor or release is not
-/// specified, then the latest version is returned. If a release for the [version](Version) is not found, then a
-/// [ReleaseNotFound](crate::Error::ReleaseNotFound) error is returned.
+/// Gets the version of PostgreSQL for the specified [version](Version) from the
+/// [default repository](crate::DEFAULT_POSTGRESQL_URL). If the version minor or release is not
+/// specified, then the latest version is returned. If a release for the [version](Version) is not
+/// found, then a [ReleaseNotFound](crate::Error::ReleaseNotFound) error is returned.
 pub fn get_version(version: &Version) -> crate::Result<Version> {
     RUNTIME
         .handle()
         .block_on(async move { crate::get_version(version).await })
 }
 
-/// Gets the archive for a given [version](Version) of PostgreSQL for the current target.
-/// If the [version](Version) is not found for this target, then an
-/// [error](crate::Error) is returned.
+/// Gets the version of PostgreSQL for the specified [version](Version) from the repository at the
+/// given URL. If the version minor or release is not specified, then the latest version is
+/// returned. If a release for the [version](Version) is not found, then a
+/// [ReleaseNotFound](crate::Error::ReleaseNotFound) error is returned.
+pub fn get_version_from(url: &str, version: &Version) -> crate::Result<Version> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::get_version_from(url, version).await })
+}
+
+/// Gets the versions of PostgreSQL available in the repository at the given URL for the specified
+/// [version](Version), newest first. Only versions matching the major version, and the minor
+/// version if specified, are returned.
+pub fn get_versions(url: &str, version: &Version) -> crate::Result<Vec<Version>> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::get_versions(url, version).await })
+}
+
+/// Gets the archive for a given [version](Version) of PostgreSQL for the current target from the
+/// [default repository](crate::DEFAULT_POSTGRESQL_URL). If the [version](Version) is not found for
+/// this target, then an [error](crate::Error) is returned.
 ///
 /// Returns the archive version and bytes.
 pub fn get_archive(version: &Version) -> crate::Result<(Version, Bytes)> {
@@ -27,10 +47,22 @@ pub fn get_archive(version: &Version) -> crate::Result<(Version, Bytes)> {
         .block_on(async move { crate::get_archive(version).await })
 }
 
+/// Gets the archive for a given [version](Version) of PostgreSQL for the current target from the
+/// repository at the given URL. If the [version](Version) is not found for this target, then an
+/// [error](crate::Error) is returned.
+///
+/// Returns the archive version and bytes.
+pub fn get_archive_from(url: &str, version: &Version) -> crate::Result<(Version, Bytes)> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::get_archive_from(url, version).await })
+}
+
 /// Gets the archive for a given [version](Version) of PostgreSQL and
-/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html).
-/// If the [version](Version) or [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html)
-/// is not found, then an [error](crate::error::Error) is returned.
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the
+/// [default repository](crate::DEFAULT_POSTGRESQL_URL). If the [version](Version) or
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) is not found, then an
+/// [error](crate::error::Error) is returned.
 ///
 /// Returns the archive version and bytes.
 pub fn get_archive_for_target<S: AsRef<str>>(
@@ -42,9 +74,190 @@ pub fn get_archive_for_target<S: AsRef<str>>(
         .block_on(async move { crate::get_archive_for_target(version, target).await })
 }
 
-/// Extracts the compressed tar [bytes](Bytes) to the [out_dir](Path).
+/// Gets the archive for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL. If the [version](Version) or
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) is not found, then an
+/// [error](crate::error::Error) is returned.
+///
+/// Returns the archive version and bytes.
+pub fn get_archive_for_target_from<S: AsRef<str>>(
+    url: &str,
+    version: &Version,
+    target: S,
+) -> crate::Result<(Version, Bytes)> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::get_archive_for_target_from(url, version, target).await })
+}
+
+/// Gets the archive for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL, reporting [progress](crate::ProgressEvent) to the [progress](Progress).
+///
+/// Returns the archive version and bytes.
+pub fn get_archive_for_target_with_progress<S: AsRef<str>>(
+    url: &str,
+    version: &Version,
+    target: S,
+    progress: &Progress,
+) -> crate::Result<(Version, Bytes)> {
+    RUNTIME.handle().block_on(async move {
+        crate::get_archive_for_target_with_progress(url, version, target, progress).await
+    })
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL for the
+/// current target from the repository at the given URL. If the [version](Version) is not found for
+/// this target, then an [error](crate::Error) is returned.
+pub fn get_archive_file(url: &str, version: &Version) -> crate::Result<ArchiveFile> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::get_archive_file(url, version).await })
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL for the
+/// current target from the repository at the given URL, reporting
+/// [progress](crate::ProgressEvent) to the [progress](Progress).
+pub fn get_archive_file_with_progress(
+    url: &str,
+    version: &Version,
+    progress: &Progress,
+) -> crate::Result<ArchiveFile> {
+    RUNTIME.handle().block_on(async move {
+        crate::get_archive_file_with_progress(url, version, progress).await
+    })
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL. If the [version](Version) or
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) is not found, then an
+/// [error](crate::Error) is returned.
+pub fn get_archive_file_for_target<S: AsRef<str>>(
+    url: &str,
+    version: &Version,
+    target: S,
+) -> crate::Result<ArchiveFile> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::get_archive_file_for_target(url, version, target).await })
+}
+
+/// Gets the [archive file](ArchiveFile) for a given [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL, reporting [progress](crate::ProgressEvent) to the [progress](Progress).
+pub fn get_archive_file_for_target_with_progress<S: AsRef<str>>(
+    url: &str,
+    version: &Version,
+    target: S,
+    progress: &Progress,
+) -> crate::Result<ArchiveFile> {
+    RUNTIME.handle().block_on(async move {
+        crate::get_archive_file_for_target_with_progress(url, version, target, progress).await
+    })
+}
+
+/// Extracts the archive [bytes](Bytes) to the [out_dir](Path).
 pub fn extract(bytes: &Bytes, out_dir: &Path) -> crate::Result<()> {
     RUNTIME
         .handle()
         .block_on(async move { crate::extract(bytes, out_dir).await })
 }
+
+/// Extracts the archive [bytes](Bytes) to the [out_dir](Path), reporting
+/// [progress](crate::ProgressEvent) to the [progress](Progress).
+pub fn extract_with_progress(
+    bytes: &Bytes,
+    out_dir: &Path,
+    progress: &Progress,
+) -> crate::Result<()> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::extract_with_progress(bytes, out_dir, progress).await })
+}
+
+/// Extracts the [archive file](ArchiveFile) to the [out_dir](Path), verifying its
+/// SHA-256 hash before the extracted files are moved to the [out_dir](Path).
+pub fn extract_file(archive: &ArchiveFile, out_dir: &Path) -> crate::Result<()> {
+    RUNTIME
+        .handle()
+        .block_on(async move { crate::extract_file(archive, out_dir).await })
+}
+
+/// Extracts the [archive file](ArchiveFile) to the [out_dir](Path), reporting
+/// [progress](crate::ProgressEvent) to the [progress](Progress).
+pub fn extract_file_with_progress(
+    archive: &ArchiveFile,
+    out_dir: &Path,
+    progress: &Progress,
+) -> crate::Result<()> {
+    RUNTIME.handle().block_on(async move {
+        crate::extract_file_with_progress(archive, out_dir, progress).await
+    })
+}
+
+/// Gets the archive for a given extension and [version](Version) of PostgreSQL for the current
+/// target from the repository at the given URL. If the archive hash does not match the expected
+/// hash, then an [error](crate::Error) is returned.
+///
+/// Returns the archive bytes.
+pub fn get_extension_archive(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+) -> crate::Result<Bytes> {
+    RUNTIME.handle().block_on(async move {
+        crate::get_extension_archive(url, name, extension_version, version).await
+    })
+}
+
+/// Gets the archive for a given extension, [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL. If the archive hash does not match the expected hash, then an
+/// [error](crate::Error) is returned.
+///
+/// Returns the archive bytes.
+pub fn get_extension_archive_for_target<S: AsRef<str>>(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+    target: S,
+) -> crate::Result<Bytes> {
+    RUNTIME.handle().block_on(async move {
+        crate::get_extension_archive_for_target(url, name, extension_version, version, target).await
+    })
+}
+
+/// Gets the [archive file](ArchiveFile) for a given extension and [version](Version) of
+/// PostgreSQL for the current target from the repository at the given URL. If the archive hash
+/// does not match the expected hash, then an [error](crate::Error) is returned.
+pub fn get_extension_archive_file(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+) -> crate::Result<ArchiveFile> {
+    RUNTIME.handle().block_on(async move {
+        crate::get_extension_archive_file(url, name, extension_version, version).await
+    })
+}
+
+/// Gets the [archive file](ArchiveFile) for a given extension, [version](Version) of PostgreSQL
+/// and [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the
+/// repository at the given URL. If the archive hash does not match the expected hash, then an
+/// [error](crate::Error) is returned.
+pub fn get_extension_archive_file_for_target<S: AsRef<str>>(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+    target: S,
+) -> crate::Result<ArchiveFile> {
+    RUNTIME.handle().block_on(async move {
+        crate::get_extension_archive_file_for_target(url, name, extension_version, version, target)
+            .await
+    })
+}
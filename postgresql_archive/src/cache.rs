@@ -0,0 +1,391 @@
+//! Persistent on-disk cache of verified PostgreSQL archives
+use crate::error::Result;
+use crate::version::Version;
+use bytes::Bytes;
+use home::home_dir;
+use human_bytes::human_bytes;
+use std::fs::{create_dir_all, read, read_dir, remove_dir_all, remove_file, rename, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+use tracing::{debug, instrument, warn};
+
+/// Environment variable that overrides the directory of the [default](Cache::default) cache
+pub const CACHE_DIR_ENV: &str = "POSTGRESQL_ARCHIVE_CACHE_DIR";
+
+/// Prefix of the temporary files of archives that are being downloaded into the cache
+const TEMP_FILE_PREFIX: &str = ".partial";
+
+/// Default maximum size of the cache in bytes (1 GiB)
+pub const DEFAULT_CACHE_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+
+lazy_static! {
+    static ref DEFAULT_CACHE: Mutex<Option<Cache>> = Mutex::new(Some(Cache::default()));
+}
+
+/// Content addressed cache of archives that have been verified against their SHA-256 hash.
+/// Archives are stored at `<dir>/<version>/<target>/<sha256>`, so an archive is only reused when
+/// the repository reports the same hash. When the total size of the cache exceeds the maximum
+/// size, the least recently used archives are evicted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl Cache {
+    /// Creates a new cache in the [directory](Path) with the
+    /// [default maximum size](DEFAULT_CACHE_MAX_SIZE)
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size: DEFAULT_CACHE_MAX_SIZE,
+        }
+    }
+
+    /// Sets the maximum size of the cache in bytes
+    #[must_use]
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Gets the directory of the cache
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Gets the maximum size of the cache in bytes
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// Gets the path of the archive for the [version](Version), target and SHA-256 hash
+    pub(crate) fn archive_path(&self, version: &Version, target: &str, hash: &str) -> PathBuf {
+        self.dir
+            .join(version.to_string())
+            .join(target)
+            .join(hash.to_lowercase())
+    }
+
+    /// Gets the path of the cached archive for the [version](Version), target and SHA-256 hash, or
+    /// `None` if the archive is not cached. Another process may [evict](Cache::evict) the archive
+    /// at any time, so it should be opened right away; an archive that is no longer found when it
+    /// is opened is not cached.
+    #[instrument(level = "debug")]
+    pub fn get_path(&self, version: &Version, target: &str, hash: &str) -> Option<PathBuf> {
+        let path = self.archive_path(version, target, hash);
+        if !path.is_file() {
+            return None;
+        }
+
+        // Update the modified time so that recently used archives are evicted last
+        if let Err(error) = File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+        {
+            warn!(
+                "Failed to update modified time of {}: {error}",
+                path.to_string_lossy()
+            );
+        }
+        debug!("Archive {} found in cache", path.to_string_lossy());
+        Some(path)
+    }
+
+    /// Gets the SHA-256 hashes of the cached archives for the [version](Version) and target
+    pub(crate) fn hashes(&self, version: &Version, target: &str) -> Vec<String> {
+        let dir = self.dir.join(version.to_string()).join(target);
+        let Ok(entries) = read_dir(dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                let is_file = entry.file_type().ok()?.is_file();
+                (is_file && !name.starts_with(TEMP_FILE_PREFIX)).then_some(name)
+            })
+            .collect()
+    }
+
+    /// Gets the cached archive for the [version](Version), target and SHA-256 hash, or `None` if
+    /// the archive is not cached
+    #[instrument(level = "debug")]
+    pub fn get(&self, version: &Version, target: &str, hash: &str) -> Result<Option<Bytes>> {
+        match self.get_path(version, target, hash) {
+            Some(path) => Ok(Some(Bytes::from(read(path)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Creates a temporary file in the cache directory for downloading an archive, so that the
+    /// verified archive can be [moved](Cache::put_file) into the cache without copying it
+    pub fn temp_file(&self) -> Result<NamedTempFile> {
+        create_dir_all(&self.dir)?;
+        let file = tempfile::Builder::new()
+            .prefix(TEMP_FILE_PREFIX)
+            .tempfile_in(&self.dir)?;
+        Ok(file)
+    }
+
+    /// Gets the path of the partial file for downloading the archive for the [version](Version),
+    /// target and SHA-256 hash. The path is the same across runs, so that an interrupted download
+    /// can be resumed; partial files are not counted towards the size of the cache.
+    pub fn partial_path(&self, version: &Version, target: &str, hash: &str) -> Result<PathBuf> {
+        let dir = self.dir.join(version.to_string()).join(target);
+        create_dir_all(&dir)?;
+        Ok(dir.join(format!("{TEMP_FILE_PREFIX}-{}", hash.to_lowercase())))
+    }
+
+    /// Moves the verified archive [file](Path) into the cache for the [version](Version), target
+    /// and SHA-256 hash, then evicts the least recently used archives if the cache exceeds its
+    /// maximum size. The file is moved with a rename so that concurrent readers never observe a
+    /// partially written archive; it should be on the same file system as the cache, such as a
+    /// [temporary file](Cache::temp_file) of the cache.
+    ///
+    /// Returns the path of the cached archive.
+    #[instrument(level = "debug")]
+    pub fn put_file(
+        &self,
+        version: &Version,
+        target: &str,
+        hash: &str,
+        file: &Path,
+    ) -> Result<PathBuf> {
+        let path = self.archive_path(version, target, hash);
+        create_dir_all(path.parent().unwrap_or(&self.dir))?;
+        rename(file, &path)?;
+        debug!("Archive {} written to cache", path.to_string_lossy());
+
+        self.evict_except(Some(&path))?;
+        Ok(path)
+    }
+
+    /// Stores the verified archive for the [version](Version), target and SHA-256 hash, then
+    /// evicts the least recently used archives if the cache exceeds its maximum size.
+    ///
+    /// Returns the path of the cached archive.
+    #[instrument(level = "debug", skip(archive))]
+    pub fn put(
+        &self,
+        version: &Version,
+        target: &str,
+        hash: &str,
+        archive: &Bytes,
+    ) -> Result<PathBuf> {
+        let mut file = self.temp_file()?;
+        file.write_all(archive)?;
+        file.as_file().sync_data()?;
+        let temp_path = file.into_temp_path();
+        let path = self.put_file(version, target, hash, &temp_path)?;
+        debug!(
+            "Archive {} written to cache: {}",
+            path.to_string_lossy(),
+            human_bytes(archive.len() as f64)
+        );
+        Ok(path)
+    }
+
+    /// Gets the cached archives along with their size and modified time. Temporary and partial
+    /// files of archives that are still being downloaded are skipped.
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut entries = Vec::new();
+        if !self.dir.is_dir() {
+            return Ok(entries);
+        }
+
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    dirs.push(path);
+                } else if metadata.is_file()
+                    && !entry
+                        .file_name()
+                        .to_string_lossy()
+                        .starts_with(TEMP_FILE_PREFIX)
+                {
+                    entries.push((path, metadata.len(), metadata.modified()?));
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Gets the total size of the cached archives in bytes
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.entries()?.iter().map(|(_, size, _)| size).sum())
+    }
+
+    /// Evicts the least recently used archives until the cache does not exceed its maximum size.
+    /// Archives that were already opened, such as those of an [ArchiveFile](crate::ArchiveFile),
+    /// can still be read once they are evicted.
+    ///
+    /// Returns the number of bytes evicted.
+    #[instrument(level = "debug")]
+    pub fn evict(&self) -> Result<u64> {
+        self.evict_except(None)
+    }
+
+    /// Evicts the least recently used archives, other than the archive at the [path](Path), until
+    /// the cache does not exceed its maximum size
+    fn evict_except(&self, path: Option<&Path>) -> Result<u64> {
+        let mut entries = self.entries()?;
+        let mut size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        let mut evicted = 0;
+        entries.sort_by_key(|(_, _, modified)| *modified);
+
+        for (entry_path, entry_size, _) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            if Some(entry_path.as_path()) == path {
+                continue;
+            }
+            debug!("Evicting {} from cache", entry_path.to_string_lossy());
+            remove_file(&entry_path)?;
+            size -= entry_size;
+            evicted += entry_size;
+        }
+
+        Ok(evicted)
+    }
+
+    /// Removes all archives from the cache.
+    ///
+    /// Returns the number of bytes removed.
+    #[instrument(level = "debug")]
+    pub fn clean(&self) -> Result<u64> {
+        let size = self.size()?;
+        if self.dir.exists() {
+            remove_dir_all(&self.dir)?;
+        }
+        debug!(
+            "Removed {} from cache {}",
+            human_bytes(size as f64),
+            self.dir.to_string_lossy()
+        );
+        Ok(size)
+    }
+}
+
+/// The default cache is in the directory named by the [CACHE_DIR_ENV] environment variable, or
+/// `.theseus/cache` in the home directory:
+///
+/// - Unix: `$HOME/.theseus/cache`
+/// - Windows: `%USERPROFILE%\.theseus\cache`
+impl Default for Cache {
+    fn default() -> Self {
+        let dir = match std::env::var_os(CACHE_DIR_ENV) {
+            Some(dir) => PathBuf::from(dir),
+            None => home_dir()
+                .unwrap_or_else(|| std::env::current_dir().unwrap_or_default())
+                .join(".theseus")
+                .join("cache"),
+        };
+        Self::new(dir)
+    }
+}
+
+/// Gets the cache used by [get_archive](crate::get_archive) and
+/// [get_archive_for_target](crate::get_archive_for_target), or `None` if caching is disabled
+pub fn get_default() -> Option<Cache> {
+    match DEFAULT_CACHE.lock() {
+        Ok(cache) => cache.clone(),
+        Err(_) => None,
+    }
+}
+
+/// Sets the cache used by [get_archive](crate::get_archive) and
+/// [get_archive_for_target](crate::get_archive_for_target); `None` disables caching.
+///
+/// ```no_run
+/// use postgresql_archive::cache::{self, Cache};
+///
+/// cache::set_default(Some(Cache::new("/var/cache/postgresql").with_max_size(512 * 1024 * 1024)));
+/// ```
+pub fn set_default(cache: Option<Cache>) {
+    if let Ok(mut default_cache) = DEFAULT_CACHE.lock() {
+        *default_cache = cache;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use test_log::test;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+    const VERSION: Version = Version::new(16, Some(2), Some(0));
+
+    fn hash(archive: &Bytes) -> String {
+        use sha2::{Digest, Sha256};
+        hex::encode(Sha256::digest(archive))
+    }
+
+    #[test]
+    fn test_default() {
+        let cache = Cache::default();
+        assert_eq!(DEFAULT_CACHE_MAX_SIZE, cache.max_size());
+        assert!(cache.dir().ends_with(Path::new(".theseus").join("cache")));
+    }
+
+    #[test]
+    fn test_get_put() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path());
+        let archive = Bytes::from("archive");
+        let hash = hash(&archive);
+
+        assert_eq!(None, cache.get(&VERSION, TARGET, &hash)?);
+        let path = cache.put(&VERSION, TARGET, &hash, &archive)?;
+        assert_eq!(dir.path().join("16.2.0").join(TARGET).join(&hash), path);
+        assert_eq!(Some(archive.clone()), cache.get(&VERSION, TARGET, &hash)?);
+        assert_eq!(None, cache.get(&VERSION, "aarch64-apple-darwin", &hash)?);
+        assert_eq!(archive.len() as u64, cache.size()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_evict() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path()).with_max_size(10);
+        let old_archive = Bytes::from("old archive");
+        let old_hash = hash(&old_archive);
+        let old_path = Cache::new(dir.path()).put(&VERSION, TARGET, &old_hash, &old_archive)?;
+        File::options()
+            .write(true)
+            .open(old_path)?
+            .set_modified(SystemTime::now() - Duration::from_secs(60))?;
+
+        let archive = Bytes::from("archive");
+        let hash = hash(&archive);
+        cache.put(&VERSION, TARGET, &hash, &archive)?;
+
+        assert_eq!(None, cache.get(&VERSION, TARGET, &old_hash)?);
+        assert_eq!(Some(archive), cache.get(&VERSION, TARGET, &hash)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path().join("cache"));
+        assert_eq!(0, cache.clean()?);
+
+        let archive = Bytes::from("archive");
+        cache.put(&VERSION, TARGET, &hash(&archive), &archive)?;
+        assert_eq!(archive.len() as u64, cache.clean()?);
+        assert!(!cache.dir().exists());
+        Ok(())
+    }
+}
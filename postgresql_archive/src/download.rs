@@ -0,0 +1,790 @@
+//! Resumable downloads of PostgreSQL archives
+use crate::error::Error::Unexpected;
+use crate::error::Result;
+use crate::progress::{Progress, ProgressEvent};
+use human_bytes::human_bytes;
+use reqwest::header::{HeaderMap, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Response, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
+use reqwest_retry::policies::ExponentialBackoff;
+use sha2::{Digest, Sha256};
+use std::fs::{read_to_string, remove_file, write, File, OpenOptions};
+use std::io::{copy, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument, warn};
+
+/// Age after which the lock of a partial file is considered stale when neither the lock file nor
+/// the partial file has been modified, e.g. because the process downloading the archive was killed
+const STALE_LOCK_AGE: Duration = Duration::from_secs(300);
+
+/// Interval between attempts to acquire the lock of a partial file
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum time to wait for the lock of a partial file held by another process, which is long
+/// enough for the other process to download a large archive over a slow connection
+const LOCK_TIMEOUT: Duration = Duration::from_secs(1800);
+
+lazy_static! {
+    static ref RETRY_POLICY: Mutex<RetryPolicy> = Mutex::new(RetryPolicy::default());
+}
+
+/// Policy for retrying failed downloads. Failed requests are retried by the HTTP client, and
+/// interrupted downloads are resumed, up to the maximum number of retries with an exponential
+/// backoff between the minimum and maximum retry intervals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    min_retry_interval: Duration,
+    max_retry_interval: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a new retry policy with the maximum number of retries, retrying after one second
+    /// and then twice as long after each retry, up to 30 seconds
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            min_retry_interval: Duration::from_secs(1),
+            max_retry_interval: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the minimum and maximum intervals between retries
+    #[must_use]
+    pub fn with_retry_bounds(
+        mut self,
+        min_retry_interval: Duration,
+        max_retry_interval: Duration,
+    ) -> Self {
+        self.min_retry_interval = min_retry_interval;
+        self.max_retry_interval = max_retry_interval.max(min_retry_interval);
+        self
+    }
+
+    /// Gets the maximum number of retries
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Gets the minimum interval between retries
+    pub fn min_retry_interval(&self) -> Duration {
+        self.min_retry_interval
+    }
+
+    /// Gets the maximum interval between retries
+    pub fn max_retry_interval(&self) -> Duration {
+        self.max_retry_interval
+    }
+
+    /// Gets the interval to wait before the retry following the number of past retries
+    fn retry_interval(&self, past_retries: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(past_retries);
+        self.min_retry_interval
+            .saturating_mul(factor)
+            .min(self.max_retry_interval)
+    }
+
+    /// Gets the exponential backoff policy used by the HTTP client for this policy
+    pub(crate) fn exponential_backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoff::builder()
+            .retry_bounds(self.min_retry_interval, self.max_retry_interval)
+            .build_with_max_retries(self.max_retries)
+    }
+}
+
+/// The default retry policy retries three times
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+/// Gets the [retry policy](RetryPolicy) used for downloads
+pub fn get_retry_policy() -> RetryPolicy {
+    match RETRY_POLICY.lock() {
+        Ok(policy) => *policy,
+        Err(_) => RetryPolicy::default(),
+    }
+}
+
+/// Sets the [retry policy](RetryPolicy) used for downloads.
+///
+/// ```no_run
+/// use postgresql_archive::download::{self, RetryPolicy};
+/// use std::time::Duration;
+///
+/// download::set_retry_policy(
+///     RetryPolicy::new(10).with_retry_bounds(Duration::from_secs(2), Duration::from_secs(60)),
+/// );
+/// ```
+pub fn set_retry_policy(policy: RetryPolicy) {
+    if let Ok(mut retry_policy) = RETRY_POLICY.lock() {
+        *retry_policy = policy;
+    }
+}
+
+/// Writer of an archive to a partial file, which is kept when a download is interrupted so that
+/// the download can be resumed from where it stopped. The validator of the download (the `ETag`
+/// or `Last-Modified` header) is stored next to the partial file, so that a download is only
+/// resumed when the archive on the server has not changed. The SHA-256 hash of the archive is
+/// computed as it is written, and [downloading](ProgressEvent::Downloading) progress is reported
+/// for each write.
+#[derive(Debug)]
+pub struct ArchiveWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    hasher: Sha256,
+    size: u64,
+    total: Option<u64>,
+    validator: Option<String>,
+    progress: Progress,
+}
+
+impl ArchiveWriter {
+    /// Opens the partial file at the [path](Path), creating it if it does not exist. The bytes of
+    /// an existing partial file are hashed so that writing can continue at the end of the file.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut hasher = Sha256::new();
+        let size = copy(&mut file, &mut hasher)?;
+        let validator = read_to_string(validator_path(path))
+            .ok()
+            .filter(|validator| !validator.is_empty());
+        if size > 0 {
+            debug!(
+                "Partial archive {} found: {}",
+                path.to_string_lossy(),
+                human_bytes(size as f64)
+            );
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+            hasher,
+            size,
+            total: None,
+            validator,
+            progress: Progress::default(),
+        })
+    }
+
+    /// Sets the [progress](Progress) that downloading events are reported to
+    #[must_use]
+    pub(crate) fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Gets the path of the partial file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the number of bytes written, which is the offset to resume the download from
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Gets the total size of the archive in bytes, if known
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// Sets the total size of the archive in bytes, if known, and reports the
+    /// [downloading](ProgressEvent::Downloading) progress
+    pub fn set_total(&mut self, total: Option<u64>) {
+        self.total = total;
+        self.report_progress();
+    }
+
+    /// Reports the [downloading](ProgressEvent::Downloading) progress
+    fn report_progress(&self) {
+        self.progress.report(ProgressEvent::Downloading {
+            bytes: self.size,
+            total: self.total,
+        });
+    }
+
+    /// Gets the validator (`ETag` or `Last-Modified`) of the download, if known
+    pub fn validator(&self) -> Option<&str> {
+        self.validator.as_deref()
+    }
+
+    /// Sets the validator (`ETag` or `Last-Modified`) of the download, persisting it next to the
+    /// partial file
+    pub fn set_validator(&mut self, validator: Option<String>) -> Result<()> {
+        let validator_path = validator_path(&self.path);
+        match &validator {
+            Some(validator) => write(validator_path, validator)?,
+            None if validator_path.exists() => remove_file(validator_path)?,
+            None => {}
+        }
+        self.validator = validator;
+        Ok(())
+    }
+
+    /// Discards the bytes written so that the download restarts from the beginning
+    pub fn restart(&mut self) -> Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().set_len(0)?;
+        self.hasher = Sha256::new();
+        self.size = 0;
+        self.set_validator(None)
+    }
+
+    /// Flushes the partial file to disk and removes the stored validator.
+    ///
+    /// Returns the hex encoded SHA-256 hash of the archive.
+    pub(crate) fn finish(mut self) -> Result<String> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        self.set_validator(None)?;
+        Ok(hex::encode(self.hasher.finalize()))
+    }
+}
+
+impl Write for ArchiveWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.file.write(buf)?;
+        self.hasher.update(&buf[..size]);
+        self.size += size as u64;
+        self.report_progress();
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Lock file preventing multiple processes from writing to the same partial file in the shared
+/// [cache](crate::cache) at the same time. The lock is released when it is dropped.
+#[derive(Debug)]
+pub(crate) struct PartialFileLock {
+    path: PathBuf,
+}
+
+impl PartialFileLock {
+    /// Acquires the lock of the partial file at the [path](Path), waiting while another process
+    /// holds it. A lock whose holder has not written to the partial file for [STALE_LOCK_AGE] is
+    /// removed, so that a download recovers from a process that was killed while holding the lock.
+    /// If the lock is not acquired within [LOCK_TIMEOUT], then an [Unexpected] error is returned.
+    pub(crate) async fn acquire(path: &Path) -> Result<Self> {
+        Self::acquire_with_timeout(path, LOCK_TIMEOUT).await
+    }
+
+    /// Acquires the lock of the partial file at the [path](Path), waiting at most the timeout
+    /// while another process holds it. See [acquire](PartialFileLock::acquire).
+    #[instrument(level = "debug")]
+    async fn acquire_with_timeout(path: &Path, timeout: Duration) -> Result<Self> {
+        let lock_path = lock_path(path);
+        debug!(
+            "Attempting to acquire lock: {}",
+            lock_path.to_string_lossy()
+        );
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+            {
+                Ok(_) => {
+                    debug!("Lock acquired: {}", lock_path.to_string_lossy());
+                    return Ok(Self { path: lock_path });
+                }
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                    if is_stale(&lock_path, path) {
+                        warn!(
+                            "Stale lock file detected; removing file to attempt process recovery: {}",
+                            lock_path.to_string_lossy()
+                        );
+                        match remove_file(&lock_path) {
+                            Ok(()) => continue,
+                            Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                            Err(error) => return Err(error.into()),
+                        }
+                    }
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(Unexpected(format!(
+                            "Failed to acquire lock {} within {timeout:?}",
+                            lock_path.to_string_lossy()
+                        )));
+                    }
+                    tokio::time::sleep(LOCK_RETRY_INTERVAL.min(remaining)).await;
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+impl Drop for PartialFileLock {
+    fn drop(&mut self) {
+        debug!("Removing lock file: {}", self.path.to_string_lossy());
+        if let Err(error) = remove_file(&self.path) {
+            warn!(
+                "Failed to remove lock file {}: {error}",
+                self.path.to_string_lossy()
+            );
+        }
+    }
+}
+
+/// Gets the path of the lock file of the partial file at the [path](Path)
+fn lock_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".lock");
+    path.with_file_name(file_name)
+}
+
+/// Gets the time since the file at the [path](Path) was modified, or `None` if it does not exist
+fn modified_age(path: &Path) -> Option<Duration> {
+    let modified = path
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    modified.elapsed().ok()
+}
+
+/// Checks if the lock file at the [lock_path](Path) is stale: neither the lock file nor the
+/// partial file at the [path](Path) has been modified for [STALE_LOCK_AGE]
+fn is_stale(lock_path: &Path, path: &Path) -> bool {
+    let lock_stale = modified_age(lock_path).is_some_and(|age| age > STALE_LOCK_AGE);
+    let partial_active = matches!(modified_age(path), Some(age) if age <= STALE_LOCK_AGE);
+    lock_stale && !partial_active
+}
+
+/// Gets the path of the file storing the validator of the partial file at the [path](Path)
+fn validator_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".validator");
+    path.with_file_name(file_name)
+}
+
+/// Gets the validator of the response: a strong `ETag`, or the `Last-Modified` date
+fn response_validator(headers: &HeaderMap) -> Option<String> {
+    let etag = headers
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"));
+    let last_modified = headers
+        .get(LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok());
+    etag.or(last_modified).map(ToString::to_string)
+}
+
+/// Gets the start offset of the `Content-Range` header of a partial response (e.g.
+/// `bytes 1024-2047/4096`)
+fn content_range_start(headers: &HeaderMap) -> Option<u64> {
+    let content_range = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = content_range.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Sends the request for the [url](str), resuming from the end of the partial archive when the
+/// archive has a validator. If the server does not support range requests, or the archive has
+/// changed since the partial archive was written, then the partial archive is discarded and the
+/// whole archive is downloaded.
+async fn send_request(
+    client: &ClientWithMiddleware,
+    url: &str,
+    writer: &mut ArchiveWriter,
+) -> Result<Response> {
+    if writer.size() > 0 && writer.validator().is_none() {
+        debug!("Partial archive has no validator; restarting download of {url}");
+        writer.restart()?;
+    }
+
+    loop {
+        let offset = writer.size();
+        let mut request = client.get(url);
+        if let (true, Some(validator)) = (offset > 0, writer.validator()) {
+            debug!("Resuming download of {url} from byte {offset}");
+            request = request
+                .header(RANGE, format!("bytes={offset}-"))
+                .header(IF_RANGE, validator);
+        }
+        let response = request.send().await?;
+        let headers = response.headers();
+
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT if offset > 0 => {
+                let validator = response_validator(headers);
+                if validator.as_deref() == writer.validator()
+                    && content_range_start(headers) == Some(offset)
+                {
+                    return Ok(response);
+                }
+                warn!("Archive {url} changed since the download was interrupted; restarting");
+                writer.restart()?;
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                warn!("Failed to resume download of {url}; restarting");
+                writer.restart()?;
+            }
+            _ => {
+                let response = response.error_for_status()?;
+                if response.status() != StatusCode::OK {
+                    return Err(Unexpected(format!(
+                        "Unexpected status {} downloading {url}",
+                        response.status()
+                    )));
+                }
+                if offset > 0 {
+                    debug!("Server does not support range requests; restarting download of {url}");
+                    writer.restart()?;
+                }
+                writer.set_validator(response_validator(response.headers()))?;
+                return Ok(response);
+            }
+        }
+    }
+}
+
+/// Writes the body of the [response](Response) to the [writer](ArchiveWriter) one chunk at a time
+async fn write_response(mut response: Response, writer: &mut ArchiveWriter) -> Result<()> {
+    while let Some(chunk) = response.chunk().await? {
+        writer.write_all(&chunk)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Downloads the [url](str) to the [writer](ArchiveWriter) one chunk at a time, so that the
+/// response is never held in memory. If the download is interrupted, then it is resumed with a
+/// range request according to the [retry policy](get_retry_policy).
+///
+/// Returns the size of the archive.
+#[instrument(level = "debug", skip(client, writer))]
+pub(crate) async fn download(
+    client: &ClientWithMiddleware,
+    url: &str,
+    writer: &mut ArchiveWriter,
+) -> Result<u64> {
+    let policy = get_retry_policy();
+    let mut retries = 0;
+    debug!("Downloading archive {url}");
+
+    loop {
+        let response = send_request(client, url, writer).await?;
+        // The content length of a partial response is the size of the remainder of the archive
+        let total = response
+            .content_length()
+            .map(|content_length| writer.size() + content_length);
+        writer.set_total(total);
+        match write_response(response, writer).await {
+            Ok(()) => break,
+            Err(error) if retries < policy.max_retries() => {
+                let retry_interval = policy.retry_interval(retries);
+                retries += 1;
+                warn!(
+                    "Download of {url} interrupted after {}; retrying in {retry_interval:?}: {error}",
+                    human_bytes(writer.size() as f64)
+                );
+                tokio::time::sleep(retry_interval).await;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    debug!(
+        "Archive {url} downloaded: {}",
+        human_bytes(writer.size() as f64)
+    );
+    Ok(writer.size())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::reqwest_client;
+    use std::fs::read;
+    use test_log::test;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const ARCHIVE: &[u8] = b"postgresql archive";
+    const ETAG_VALUE: &str = "\"archive\"";
+
+    fn hash(archive: &[u8]) -> String {
+        hex::encode(Sha256::digest(archive))
+    }
+
+    /// Writes a partial archive with the first bytes of the archive and the validator
+    fn partial_archive(path: &Path, size: usize, validator: Option<&str>) -> Result<()> {
+        let mut writer = ArchiveWriter::open(path)?;
+        writer.write_all(&ARCHIVE[..size])?;
+        writer.flush()?;
+        writer.set_validator(validator.map(ToString::to_string))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::new(5)
+            .with_retry_bounds(Duration::from_millis(100), Duration::from_millis(500));
+        assert_eq!(5, policy.max_retries());
+        assert_eq!(Duration::from_millis(100), policy.min_retry_interval());
+        assert_eq!(Duration::from_millis(500), policy.max_retry_interval());
+        assert_eq!(Duration::from_millis(100), policy.retry_interval(0));
+        assert_eq!(Duration::from_millis(200), policy.retry_interval(1));
+        assert_eq!(Duration::from_millis(400), policy.retry_interval(2));
+        assert_eq!(Duration::from_millis(500), policy.retry_interval(3));
+        assert_eq!(Duration::from_millis(500), policy.retry_interval(u32::MAX));
+        assert_eq!(RetryPolicy::new(3), RetryPolicy::default());
+    }
+
+    #[test]
+    fn test_archive_writer_resume() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        partial_archive(&path, 5, Some(ETAG_VALUE))?;
+
+        let mut writer = ArchiveWriter::open(&path)?;
+        assert_eq!(5, writer.size());
+        assert_eq!(Some(ETAG_VALUE), writer.validator());
+        writer.write_all(&ARCHIVE[5..])?;
+        assert_eq!(hash(ARCHIVE), writer.finish()?);
+        assert_eq!(ARCHIVE, read(&path)?);
+        assert!(!validator_path(&path).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_writer_restart() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        partial_archive(&path, 5, Some(ETAG_VALUE))?;
+
+        let mut writer = ArchiveWriter::open(&path)?;
+        writer.restart()?;
+        assert_eq!(0, writer.size());
+        assert_eq!(None, writer.validator());
+        writer.write_all(ARCHIVE)?;
+        assert_eq!(hash(ARCHIVE), writer.finish()?);
+        assert_eq!(ARCHIVE, read(&path)?);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_partial_file_lock() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        let lock = PartialFileLock::acquire(&path).await?;
+        assert!(lock_path(&path).is_file());
+
+        let result =
+            tokio::time::timeout(Duration::from_millis(200), PartialFileLock::acquire(&path)).await;
+        assert!(result.is_err());
+
+        drop(lock);
+        assert!(!lock_path(&path).exists());
+        let lock = PartialFileLock::acquire(&path).await?;
+        drop(lock);
+        assert!(!lock_path(&path).exists());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_partial_file_lock_stale() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        let stale = std::time::SystemTime::now() - STALE_LOCK_AGE * 2;
+        File::create(lock_path(&path))?.set_modified(stale)?;
+
+        let result =
+            tokio::time::timeout(Duration::from_millis(200), PartialFileLock::acquire(&path)).await;
+        assert!(result.is_ok_and(|lock| lock.is_ok()));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_partial_file_lock_active_download() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        let stale = std::time::SystemTime::now() - STALE_LOCK_AGE * 2;
+        File::create(lock_path(&path))?.set_modified(stale)?;
+        // The partial file is still being written by the process holding the lock
+        partial_archive(&path, 5, Some(ETAG_VALUE))?;
+
+        let result =
+            tokio::time::timeout(Duration::from_millis(200), PartialFileLock::acquire(&path)).await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_partial_file_lock_timeout() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        let _lock = PartialFileLock::acquire(&path).await?;
+        // The partial file is still being written by the process holding the lock
+        partial_archive(&path, 5, Some(ETAG_VALUE))?;
+
+        let result = PartialFileLock::acquire_with_timeout(&path, Duration::from_millis(200)).await;
+        assert!(matches!(result, Err(Unexpected(_))));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_download() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", ETAG_VALUE)
+                    .set_body_bytes(ARCHIVE),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        let url = format!("{}/archive.tar.gz", server.uri());
+
+        let mut writer = ArchiveWriter::open(&path)?;
+        let size = download(&reqwest_client(), &url, &mut writer).await?;
+        assert_eq!(ARCHIVE.len() as u64, size);
+        assert_eq!(Some(ETAG_VALUE), writer.validator());
+        assert_eq!(hash(ARCHIVE), writer.finish()?);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_download_resume() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.gz"))
+            .and(header("Range", "bytes=5-"))
+            .and(header("If-Range", ETAG_VALUE))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header("ETag", ETAG_VALUE)
+                    .insert_header(
+                        "Content-Range",
+                        format!("bytes 5-{}/{}", ARCHIVE.len() - 1, ARCHIVE.len()).as_str(),
+                    )
+                    .set_body_bytes(&ARCHIVE[5..]),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        partial_archive(&path, 5, Some(ETAG_VALUE))?;
+        let url = format!("{}/archive.tar.gz", server.uri());
+
+        let mut writer = ArchiveWriter::open(&path)?;
+        let size = download(&reqwest_client(), &url, &mut writer).await?;
+        assert_eq!(ARCHIVE.len() as u64, size);
+        assert_eq!(hash(ARCHIVE), writer.finish()?);
+        assert_eq!(ARCHIVE, read(&path)?);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_download_range_not_supported() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", ETAG_VALUE)
+                    .set_body_bytes(ARCHIVE),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        partial_archive(&path, 5, Some(ETAG_VALUE))?;
+        let url = format!("{}/archive.tar.gz", server.uri());
+
+        let mut writer = ArchiveWriter::open(&path)?;
+        let size = download(&reqwest_client(), &url, &mut writer).await?;
+        assert_eq!(ARCHIVE.len() as u64, size);
+        assert_eq!(hash(ARCHIVE), writer.finish()?);
+        assert_eq!(ARCHIVE, read(&path)?);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_download_archive_changed() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.gz"))
+            .and(header("Range", "bytes=5-"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header("ETag", "\"changed\"")
+                    .insert_header("Content-Range", "bytes 5-9/10")
+                    .set_body_bytes("changed"),
+            )
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"changed\"")
+                    .set_body_bytes(ARCHIVE),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        partial_archive(&path, 5, Some(ETAG_VALUE))?;
+        let url = format!("{}/archive.tar.gz", server.uri());
+
+        let mut writer = ArchiveWriter::open(&path)?;
+        download(&reqwest_client(), &url, &mut writer).await?;
+        assert_eq!(Some("\"changed\""), writer.validator());
+        assert_eq!(hash(ARCHIVE), writer.finish()?);
+        assert_eq!(ARCHIVE, read(&path)?);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_download_without_validator() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.gz"))
+            .and(header("Range", "bytes=5-"))
+            .respond_with(ResponseTemplate::new(500))
+            .with_priority(1)
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/archive.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(ARCHIVE))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".partial-archive");
+        partial_archive(&path, 5, None)?;
+        let url = format!("{}/archive.tar.gz", server.uri());
+
+        let mut writer = ArchiveWriter::open(&path)?;
+        download(&reqwest_client(), &url, &mut writer).await?;
+        assert_eq!(None, writer.validator());
+        assert_eq!(hash(ARCHIVE), writer.finish()?);
+        Ok(())
+    }
+}
@@ -25,6 +25,16 @@ pub enum Error {
     /// Release not found
     #[error("release not found for version [{0}]")]
     ReleaseNotFound(String),
+    /// Error when the archive format is not recognized, or its cargo feature is not enabled
+    #[error("archive format not supported: {0}")]
+    UnsupportedArchiveFormat(String),
+    /// Error when an archive entry would be extracted outside of the extraction directory, such
+    /// as an entry with an absolute path or `..` components, or a symlink pointing outside
+    #[error("unsafe archive entry [{path}]: {reason}")]
+    UnsafeArchiveEntry { path: String, reason: String },
+    /// Error when no registered repository supports the URL
+    #[error("repository not supported for URL [{0}]")]
+    UnsupportedRepository(String),
     /// Unexpected error
     #[error("{0}")]
     Unexpected(String),
@@ -79,6 +89,21 @@ impl From<std::path::StripPrefixError> for Error {
     }
 }
 
+/// Converts a [`url::ParseError`] into an [`ParseError`](Error::ParseError)
+impl From<url::ParseError> for Error {
+    fn from(error: url::ParseError) -> Self {
+        Error::ParseError(error.into())
+    }
+}
+
+/// Converts a [`zip::result::ZipError`] into an [`IoError`](Error::IoError)
+#[cfg(feature = "zip")]
+impl From<zip::result::ZipError> for Error {
+    fn from(error: zip::result::ZipError) -> Self {
+        Error::IoError(error.into())
+    }
+}
+
 /// Converts a [`anyhow::Error`] into an [`Unexpected`](Error::Unexpected)
 impl From<anyhow::Error> for Error {
     fn from(error: anyhow::Error) -> Self {
@@ -148,6 +173,24 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_from_url_parse_error() {
+        let result = url::Url::parse("test");
+        assert!(result.is_err());
+        if let Err(error) = result {
+            let error = Error::from(error);
+            assert_eq!(error.to_string(), "relative URL without a base");
+        }
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_from_zip_error() {
+        let zip_error = zip::result::ZipError::FileNotFound;
+        let error = Error::from(zip_error);
+        assert_eq!(error.to_string(), "specified file not found in archive");
+    }
+
     #[test]
     fn test_from_anyhow_error() {
         let anyhow_error = anyhow::Error::msg("test");
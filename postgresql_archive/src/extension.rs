@@ -0,0 +1,515 @@
+//! Manage PostgreSQL extension archives
+
+use crate::archive::{
+    create_symlink, get_hash, get_repository_archive_file, reqwest_client, unsafe_archive_entry,
+    verify_archive_hash, HashReader,
+};
+use crate::download::{download, ArchiveWriter};
+use crate::error::Result;
+use crate::progress::Progress;
+use crate::repository::{Asset, Repository};
+use crate::version::Version;
+use crate::ArchiveFile;
+use async_trait::async_trait;
+use bytes::Bytes;
+use flate2::bufread::GzDecoder;
+use human_bytes::human_bytes;
+use std::fs::{create_dir_all, remove_file, File};
+use std::io::{copy, sink, BufReader, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::Archive;
+use tracing::{debug, instrument, warn};
+
+/// Gets the name of the archive for a given extension, extension version, [version](Version) of
+/// PostgreSQL and [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html).
+///
+/// Extension archives are named `<name>-<extension version>-pg<major version>-<target>.tar.gz`
+/// (e.g. `vector-0.7.0-pg16-x86_64-unknown-linux-gnu.tar.gz`) and are accompanied by a
+/// `.sha256` file containing the hash of the archive.
+pub fn extension_asset_name<S: AsRef<str>>(
+    name: S,
+    extension_version: S,
+    version: &Version,
+    target: S,
+) -> String {
+    format!(
+        "{}-{}-pg{}-{}.tar.gz",
+        name.as_ref(),
+        extension_version.as_ref(),
+        version.major,
+        target.as_ref()
+    )
+}
+
+/// [Repository] of the archives of an extension version in the directory at a URL. The directory
+/// contains an archive named by [extension_asset_name] and its `.sha256` hash file for each
+/// version of PostgreSQL and target.
+#[derive(Debug)]
+struct ExtensionRepository {
+    url: String,
+    name: String,
+    extension_version: String,
+}
+
+#[async_trait]
+impl Repository for ExtensionRepository {
+    fn name(&self) -> &str {
+        "Extension"
+    }
+
+    /// The directory cannot be listed, so no versions of PostgreSQL are reported
+    async fn get_versions(&self) -> Result<Vec<Version>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_asset(&self, version: &Version, target: &str) -> Result<Asset> {
+        let name = extension_asset_name(
+            self.name.as_str(),
+            self.extension_version.as_str(),
+            version,
+            target,
+        );
+        let url = format!("{}/{name}", self.url.trim_end_matches('/'));
+        Ok(Asset {
+            version: *version,
+            hash_url: format!("{url}.sha256"),
+            name,
+            url,
+        })
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_archive(&self, asset: &Asset) -> Result<Bytes> {
+        debug!("Downloading extension archive {}", asset.url);
+        let client = reqwest_client();
+        let request = client.get(&asset.url);
+        let response = request.send().await?.error_for_status()?;
+        let archive: Bytes = response.bytes().await?;
+        debug!(
+            "Extension archive {} downloaded: {}",
+            asset.url,
+            human_bytes(archive.len() as f64)
+        );
+        Ok(archive)
+    }
+
+    #[instrument(level = "debug", skip(writer))]
+    async fn write_archive(&self, asset: &Asset, writer: &mut ArchiveWriter) -> Result<u64> {
+        let client = reqwest_client();
+        download(&client, &asset.url, writer).await
+    }
+
+    async fn get_hash(&self, asset: &Asset) -> Result<String> {
+        let client = reqwest_client();
+        get_hash(&client, &asset.hash_url, &asset.name).await
+    }
+}
+
+/// Gets the archive for a given extension and [version](Version) of PostgreSQL for the current
+/// target from the repository at the given URL. If the archive hash does not match the expected
+/// hash, then an [error](crate::error::Error) is returned.
+///
+/// Returns the archive bytes.
+#[instrument]
+pub async fn get_extension_archive(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+) -> Result<Bytes> {
+    get_extension_archive_for_target(url, name, extension_version, version, target_triple::TARGET)
+        .await
+}
+
+/// Gets the archive for a given extension, [version](Version) of PostgreSQL and
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the repository at
+/// the given URL. If the archive hash does not match the expected hash, then an
+/// [error](crate::error::Error) is returned.
+///
+/// This is a convenience wrapper around [get_extension_archive_file_for_target] that reads the
+/// archive into memory.
+///
+/// Returns the archive bytes.
+#[instrument(level = "debug", skip(target))]
+pub async fn get_extension_archive_for_target<S: AsRef<str>>(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+    target: S,
+) -> Result<Bytes> {
+    let archive =
+        get_extension_archive_file_for_target(url, name, extension_version, version, target)
+            .await?;
+    archive.read()
+}
+
+/// Gets the [archive file](ArchiveFile) for a given extension and [version](Version) of
+/// PostgreSQL for the current target from the repository at the given URL. See
+/// [get_extension_archive_file_for_target].
+#[instrument]
+pub async fn get_extension_archive_file(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+) -> Result<ArchiveFile> {
+    get_extension_archive_file_for_target(
+        url,
+        name,
+        extension_version,
+        version,
+        target_triple::TARGET,
+    )
+    .await
+}
+
+/// Gets the [archive file](ArchiveFile) for a given extension, [version](Version) of PostgreSQL
+/// and [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from the
+/// repository at the given URL.
+///
+/// The archive is downloaded in the same way as a [PostgreSQL archive](crate::get_archive_file):
+/// it is streamed to disk, verified against its `.sha256` hash file and stored in the
+/// [default cache](crate::cache::get_default). If the hash does not match, then an
+/// [ArchiveHashMismatch](crate::Error::ArchiveHashMismatch) error is returned.
+#[instrument(level = "debug", skip(target))]
+pub async fn get_extension_archive_file_for_target<S: AsRef<str>>(
+    url: &str,
+    name: &str,
+    extension_version: &str,
+    version: &Version,
+    target: S,
+) -> Result<ArchiveFile> {
+    let repository = ExtensionRepository {
+        url: url.to_string(),
+        name: name.to_string(),
+        extension_version: extension_version.to_string(),
+    };
+    get_repository_archive_file(&repository, version, target.as_ref(), &Progress::default()).await
+}
+
+/// Installs the extension in the compressed tar [bytes](Bytes) into the PostgreSQL
+/// [installation_dir](Path).
+///
+/// Entries under a `lib` or `share` directory in the archive are installed at the same relative
+/// path in the installation directory. Other shared libraries are installed into `lib`, and
+/// control and SQL script files are installed into `share/extension`. Any remaining entries are
+/// skipped. Symlinks are installed if their target stays inside the installation directory;
+/// entries with absolute paths or `..` components return an
+/// [UnsafeArchiveEntry](crate::Error::UnsafeArchiveEntry) error.
+///
+/// Returns the paths of the installed files.
+#[instrument(skip(bytes))]
+pub fn install_extension(bytes: &Bytes, installation_dir: &Path) -> Result<Vec<PathBuf>> {
+    install_extension_entries(Cursor::new(bytes), installation_dir, None)
+}
+
+/// Installs the extension in the [archive file](ArchiveFile) into the PostgreSQL
+/// [installation_dir](Path). The archive is read from disk as it is installed, and its SHA-256
+/// hash is verified once the whole archive has been read. See [install_extension].
+///
+/// Returns the paths of the installed files.
+#[instrument(skip(archive), fields(archive = %archive.path().to_string_lossy()))]
+pub fn install_extension_file(
+    archive: &ArchiveFile,
+    installation_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let file = archive.open()?;
+    install_extension_entries(file, installation_dir, Some(archive.hash()))
+}
+
+/// Installs the entries of the compressed tar archive read from the [input](Read) into the
+/// [installation_dir](Path). If a hash is specified, then the SHA-256 hash of the input is
+/// verified once the whole input has been read.
+fn install_extension_entries<R: Read>(
+    input: R,
+    installation_dir: &Path,
+    hash: Option<&str>,
+) -> Result<Vec<PathBuf>> {
+    let mut input = HashReader::new(input);
+    let mut archive = Archive::new(GzDecoder::new(BufReader::new(&mut input)));
+    let mut installed_files = Vec::new();
+
+    for archive_entry in archive.entries()? {
+        let mut entry = archive_entry?;
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_symlink() {
+            continue;
+        }
+        #[cfg(unix)]
+        let file_mode = entry.header().mode()?;
+
+        let header_path = entry.path()?.to_path_buf();
+        let relative_path = match extension_file_path(&header_path)? {
+            Some(relative_path) => relative_path,
+            None => {
+                warn!(
+                    "Skipping extension archive entry {}",
+                    header_path.to_string_lossy()
+                );
+                continue;
+            }
+        };
+
+        let file_path = installation_dir.join(relative_path);
+        if let Some(parent) = file_path.parent() {
+            create_dir_all(parent)?;
+        }
+        // Replace files and symlinks of a previous installation of the extension
+        if file_path.symlink_metadata().is_ok() {
+            remove_file(&file_path)?;
+        }
+
+        if entry_type.is_symlink() {
+            let Some(symlink_target) = entry.link_name()? else {
+                continue;
+            };
+            create_symlink(installation_dir, &file_path, &symlink_target, &header_path)?;
+        } else {
+            let mut output_file = File::create(&file_path)?;
+            copy(&mut entry, &mut output_file)?;
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                // Strip the setuid, setgid and sticky bits
+                output_file.set_permissions(std::fs::Permissions::from_mode(file_mode & 0o777))?;
+            }
+        }
+
+        debug!("Installed extension file {}", file_path.to_string_lossy());
+        installed_files.push(file_path);
+    }
+    drop(archive);
+
+    // Read the remainder of the input, such as the end of the compressed stream, so that the
+    // hash covers the whole archive
+    copy(&mut input, &mut sink())?;
+    if let Some(hash) = hash {
+        verify_archive_hash(&input.finalize(), hash)?;
+    }
+
+    Ok(installed_files)
+}
+
+/// Gets the path relative to the installation directory where an extension archive entry is
+/// installed, or `None` if the entry is not part of the extension. Returns an
+/// [UnsafeArchiveEntry](crate::Error::UnsafeArchiveEntry) error if the entry path is absolute or
+/// contains `..` components.
+fn extension_file_path(path: &Path) -> Result<Option<PathBuf>> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(component) => components.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(unsafe_archive_entry(path, "parent directory component"));
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_archive_entry(path, "absolute path"));
+            }
+        }
+    }
+
+    if let Some(index) = components
+        .iter()
+        .position(|component| *component == "lib" || *component == "share")
+    {
+        return Ok(Some(components[index..].iter().collect()));
+    }
+
+    let Some(file_name) = components.last() else {
+        return Ok(None);
+    };
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str());
+    match extension {
+        Some("so" | "dylib" | "dll") => Ok(Some(Path::new("lib").join(file_name))),
+        Some("control" | "sql") => Ok(Some(Path::new("share").join("extension").join(file_name))),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error::{ArchiveHashMismatch, UnsafeArchiveEntry};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use sha2::{Digest, Sha256};
+    use test_log::test;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    fn archive(files: &[&str], symlinks: &[(&str, &str)]) -> Result<Bytes> {
+        let encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for file in files {
+            let contents = file.as_bytes();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, file, contents)?;
+        }
+        for (link, target) in symlinks {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            header.set_mode(0o777);
+            builder.append_link(&mut header, link, target)?;
+        }
+        let bytes = builder.into_inner()?.finish()?;
+        Ok(Bytes::from(bytes))
+    }
+
+    #[test]
+    fn test_extension_asset_name() {
+        let version = Version::new(16, Some(2), Some(0));
+        assert_eq!(
+            "vector-0.7.0-pg16-x86_64-unknown-linux-gnu.tar.gz",
+            extension_asset_name("vector", "0.7.0", &version, "x86_64-unknown-linux-gnu")
+        );
+    }
+
+    #[test]
+    fn test_extension_file_path() -> Result<()> {
+        assert_eq!(
+            Some(PathBuf::from("lib/vector.so")),
+            extension_file_path(Path::new("vector/lib/vector.so"))?
+        );
+        assert_eq!(
+            Some(PathBuf::from("share/extension/vector.control")),
+            extension_file_path(Path::new("./share/extension/vector.control"))?
+        );
+        assert_eq!(
+            Some(PathBuf::from("lib/vector.dylib")),
+            extension_file_path(Path::new("vector.dylib"))?
+        );
+        assert_eq!(
+            Some(PathBuf::from("share/extension/vector--0.7.0.sql")),
+            extension_file_path(Path::new("vector/vector--0.7.0.sql"))?
+        );
+        assert_eq!(None, extension_file_path(Path::new("vector/README.md"))?);
+        assert!(matches!(
+            extension_file_path(Path::new("../lib/vector.so")),
+            Err(UnsafeArchiveEntry { .. })
+        ));
+        assert!(matches!(
+            extension_file_path(Path::new("/lib/vector.so")),
+            Err(UnsafeArchiveEntry { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_install_extension() -> Result<()> {
+        let bytes = archive(
+            &[
+                "vector/lib/vector.so",
+                "vector/share/extension/vector.control",
+                "vector/vector--0.7.0.sql",
+                "vector/README.md",
+            ],
+            &[],
+        )?;
+        let installation_dir = tempfile::tempdir()?;
+
+        let installed_files = install_extension(&bytes, installation_dir.path())?;
+
+        assert_eq!(3, installed_files.len());
+        let library = installation_dir.path().join("lib").join("vector.so");
+        assert_eq!("vector/lib/vector.so", std::fs::read_to_string(library)?);
+        let extension_dir = installation_dir.path().join("share").join("extension");
+        assert!(extension_dir.join("vector.control").is_file());
+        assert!(extension_dir.join("vector--0.7.0.sql").is_file());
+        assert!(!installation_dir.path().join("README.md").exists());
+
+        // Installing the extension again replaces the installed files
+        assert_eq!(3, install_extension(&bytes, installation_dir.path())?.len());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_extension_symlinks() -> Result<()> {
+        let bytes = archive(
+            &["postgis/lib/postgis-3.so.3.4"],
+            &[("postgis/lib/postgis-3.so", "postgis-3.so.3.4")],
+        )?;
+        let installation_dir = tempfile::tempdir()?;
+
+        let installed_files = install_extension(&bytes, installation_dir.path())?;
+        assert_eq!(2, installed_files.len());
+        let library = installation_dir.path().join("lib").join("postgis-3.so");
+        assert_eq!(
+            PathBuf::from("postgis-3.so.3.4"),
+            std::fs::read_link(&library)?
+        );
+        assert_eq!(
+            "postgis/lib/postgis-3.so.3.4",
+            std::fs::read_to_string(library)?
+        );
+
+        let bytes = archive(&[], &[("postgis/lib/postgis-3.so", "/etc/passwd")])?;
+        assert!(matches!(
+            install_extension(&bytes, installation_dir.path()),
+            Err(UnsafeArchiveEntry { .. })
+        ));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_extension_archive_file() -> Result<()> {
+        let bytes = archive(&["vector/lib/vector.so"], &[])?;
+        let hash = hex::encode(Sha256::digest(&bytes));
+        let name = format!("vector-0.7.0-pg16-{TARGET}.tar.gz");
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/extensions/{name}")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bytes.to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/extensions/{name}.sha256")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!("{hash}  {name}")))
+            .mount(&server)
+            .await;
+        let url = format!("{}/extensions/", server.uri());
+        let version = Version::new(16, Some(2), Some(0));
+
+        let archive =
+            get_extension_archive_file_for_target(&url, "vector", "0.7.0", &version, TARGET)
+                .await?;
+        assert_eq!(hash, archive.hash());
+        let installation_dir = tempfile::tempdir()?;
+        let installed_files = install_extension_file(&archive, installation_dir.path())?;
+        assert_eq!(
+            vec![installation_dir.path().join("lib").join("vector.so")],
+            installed_files
+        );
+
+        let result =
+            get_extension_archive_file_for_target(&url, "vector", "0.8.0", &version, TARGET).await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_install_extension_hash_mismatch() -> Result<()> {
+        let bytes = archive(&["vector/lib/vector.so"], &[])?;
+        let installation_dir = tempfile::tempdir()?;
+        assert!(matches!(
+            install_extension_entries(
+                Cursor::new(&bytes),
+                installation_dir.path(),
+                Some(&"0".repeat(64))
+            ),
+            Err(ArchiveHashMismatch { .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! Formats of PostgreSQL archives
+use crate::error::Error::UnsupportedArchiveFormat;
+use crate::error::Result;
+use std::fmt;
+use std::io::{BufRead, Read};
+
+/// Format of a PostgreSQL archive. The format is detected from the magic bytes at the start of the
+/// archive, or from the extension of the asset name. Formats other than gzip compressed tar
+/// archives require the cargo feature of the format to be enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArchiveFormat {
+    /// Gzip compressed tar archive (`.tar.gz` or `.tgz`)
+    TarGz,
+    /// Xz compressed tar archive (`.tar.xz` or `.txz`); requires the `xz` feature
+    TarXz,
+    /// Zstandard compressed tar archive (`.tar.zst` or `.tzst`); requires the `zstd` feature
+    TarZst,
+    /// Bzip2 compressed tar archive (`.tar.bz2` or `.tbz2`); requires the `bzip2` feature
+    TarBz2,
+    /// Zip archive (`.zip`); requires the `zip` feature
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// All archive formats
+    pub const ALL: [ArchiveFormat; 5] = [
+        ArchiveFormat::TarGz,
+        ArchiveFormat::TarXz,
+        ArchiveFormat::TarZst,
+        ArchiveFormat::TarBz2,
+        ArchiveFormat::Zip,
+    ];
+
+    /// Gets the file extensions of the format, without the leading `.`
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ArchiveFormat::TarGz => &["tar.gz", "tgz"],
+            ArchiveFormat::TarXz => &["tar.xz", "txz"],
+            ArchiveFormat::TarZst => &["tar.zst", "tzst"],
+            ArchiveFormat::TarBz2 => &["tar.bz2", "tbz2"],
+            ArchiveFormat::Zip => &["zip"],
+        }
+    }
+
+    /// Gets the cargo feature required for the format, or `None` if the format is always
+    /// supported
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            ArchiveFormat::TarGz => None,
+            ArchiveFormat::TarXz => Some("xz"),
+            ArchiveFormat::TarZst => Some("zstd"),
+            ArchiveFormat::TarBz2 => Some("bzip2"),
+            ArchiveFormat::Zip => Some("zip"),
+        }
+    }
+
+    /// Checks if the cargo feature of the format is enabled
+    pub fn is_supported(&self) -> bool {
+        match self {
+            ArchiveFormat::TarGz => true,
+            ArchiveFormat::TarXz => cfg!(feature = "xz"),
+            ArchiveFormat::TarZst => cfg!(feature = "zstd"),
+            ArchiveFormat::TarBz2 => cfg!(feature = "bzip2"),
+            ArchiveFormat::Zip => cfg!(feature = "zip"),
+        }
+    }
+
+    /// Detects the format from the extension of the asset name (e.g.
+    /// `postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        split_extension(name).map(|(_, format)| format)
+    }
+
+    /// Detects the format from the magic bytes at the start of the archive
+    pub fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveFormat::TarGz)
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(ArchiveFormat::TarXz)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(ArchiveFormat::TarZst)
+        } else if bytes.starts_with(b"BZh") {
+            Some(ArchiveFormat::TarBz2)
+        } else if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Returns an [UnsupportedArchiveFormat] error if the cargo feature of the format is not
+    /// enabled
+    pub(crate) fn check_supported(&self) -> Result<()> {
+        match (self.is_supported(), self.feature()) {
+            (false, Some(feature)) => Err(UnsupportedArchiveFormat(format!(
+                "{self} (enable the `{feature}` feature)"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates a decoder of the compressed tar archive read from the [reader](BufRead). Returns an
+    /// [UnsupportedArchiveFormat] error for zip archives, which are not compressed tar archives,
+    /// and for formats whose cargo feature is not enabled.
+    pub(crate) fn tar_decoder<'a, R: BufRead + 'a>(&self, reader: R) -> Result<Box<dyn Read + 'a>> {
+        self.check_supported()?;
+        let decoder: Box<dyn Read + 'a> = match self {
+            ArchiveFormat::TarGz => Box::new(flate2::bufread::GzDecoder::new(reader)),
+            #[cfg(feature = "xz")]
+            // Archives may consist of several concatenated xz streams, like the zonky archives
+            ArchiveFormat::TarXz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+            #[cfg(feature = "zstd")]
+            ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+            #[cfg(feature = "bzip2")]
+            ArchiveFormat::TarBz2 => Box::new(bzip2::bufread::BzDecoder::new(reader)),
+            _ => {
+                return Err(UnsupportedArchiveFormat(format!(
+                    "{self} is not a tar archive"
+                )))
+            }
+        };
+        Ok(decoder)
+    }
+}
+
+/// Displays the primary file extension of the format (e.g. `tar.gz`)
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}", self.extensions()[0])
+    }
+}
+
+/// Splits the asset name into the name without the extension and the [format](ArchiveFormat) of
+/// the extension (e.g. `postgresql-16.2.0.tar.gz` into `postgresql-16.2.0` and
+/// [TarGz](ArchiveFormat::TarGz))
+pub(crate) fn split_extension(name: &str) -> Option<(&str, ArchiveFormat)> {
+    ArchiveFormat::ALL.into_iter().find_map(|format| {
+        format.extensions().iter().find_map(|extension| {
+            let stem = name.strip_suffix(extension)?.strip_suffix('.')?;
+            Some((stem, format))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            Some(ArchiveFormat::TarGz),
+            ArchiveFormat::from_name("postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz")
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarGz),
+            ArchiveFormat::from_name("archive.tgz")
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarXz),
+            ArchiveFormat::from_name("archive.txz")
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarXz),
+            ArchiveFormat::from_name("archive.tar.xz")
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarZst),
+            ArchiveFormat::from_name("archive.tar.zst")
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarBz2),
+            ArchiveFormat::from_name("archive.tar.bz2")
+        );
+        assert_eq!(
+            Some(ArchiveFormat::Zip),
+            ArchiveFormat::from_name("archive.zip")
+        );
+        assert_eq!(None, ArchiveFormat::from_name("archive.tar.gz.sha256"));
+        assert_eq!(None, ArchiveFormat::from_name("archivezip"));
+    }
+
+    #[test]
+    fn test_from_magic_bytes() {
+        assert_eq!(
+            Some(ArchiveFormat::TarGz),
+            ArchiveFormat::from_magic_bytes(&[0x1f, 0x8b, 0x08])
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarXz),
+            ArchiveFormat::from_magic_bytes(&[0xfd, b'7', b'z', b'X', b'Z', 0x00])
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarZst),
+            ArchiveFormat::from_magic_bytes(&[0x28, 0xb5, 0x2f, 0xfd])
+        );
+        assert_eq!(
+            Some(ArchiveFormat::TarBz2),
+            ArchiveFormat::from_magic_bytes(b"BZh91AY")
+        );
+        assert_eq!(
+            Some(ArchiveFormat::Zip),
+            ArchiveFormat::from_magic_bytes(b"PK\x03\x04")
+        );
+        assert_eq!(None, ArchiveFormat::from_magic_bytes(b"archive"));
+        assert_eq!(None, ArchiveFormat::from_magic_bytes(&[]));
+    }
+
+    #[test]
+    fn test_split_extension() {
+        assert_eq!(
+            Some(("postgresql-16.2.0", ArchiveFormat::TarZst)),
+            split_extension("postgresql-16.2.0.tar.zst")
+        );
+        assert_eq!(None, split_extension("postgresql-16.2.0"));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("tar.gz", ArchiveFormat::TarGz.to_string());
+        assert_eq!("zip", ArchiveFormat::Zip.to_string());
+    }
+
+    #[test]
+    fn test_check_supported() {
+        assert!(ArchiveFormat::TarGz.check_supported().is_ok());
+        for format in ArchiveFormat::ALL {
+            assert_eq!(format.is_supported(), format.check_supported().is_ok());
+        }
+    }
+}
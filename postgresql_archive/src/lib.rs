@@ -50,9 +50,14 @@
 //!
 //! The following features are available:
 //!
-//! | Name         | Description              | Default? |
-//! |--------------|--------------------------|----------|
-//! | `blocking`   | Enables the blocking API | No       |
+//! | Name         | Description                                                                  | Default? |
+//! |--------------|------------------------------------------------------------------------------|----------|
+//! | `blocking`   | Enables the blocking API                                                     | No       |
+//! | `bzip2`      | Enables extracting bzip2 compressed tar archives (`.tar.bz2`)                | No       |
+//! | `xz`         | Enables extracting xz compressed tar archives (`.tar.xz`)                    | No       |
+//! | `zip`        | Enables extracting zip archives (`.zip`)                                     | No       |
+//! | `zonky`      | Enables the zonky embedded-postgres Maven repository; enables `xz` and `zip` | No       |
+//! | `zstd`       | Enables extracting zstd compressed tar archives (`.tar.zst`)                 | No       |
 //!
 //! ## Supported platforms
 //!
@@ -110,11 +115,30 @@ extern crate lazy_static;
 mod archive;
 #[cfg(feature = "blocking")]
 pub mod blocking;
+pub mod cache;
+pub mod download;
 mod error;
-mod github;
+mod extension;
+mod format;
+mod progress;
+pub mod repository;
 mod version;
 
-pub use archive::{extract, get_archive, get_archive_for_target, get_version};
+pub use archive::{
+    archive_asset_name, extract, extract_file, extract_file_with_progress, extract_with_progress,
+    get_archive, get_archive_file, get_archive_file_for_target,
+    get_archive_file_for_target_with_progress, get_archive_file_with_progress,
+    get_archive_for_target, get_archive_for_target_from, get_archive_for_target_with_progress,
+    get_archive_from, get_version, get_version_from, get_versions, ArchiveFile,
+    DEFAULT_POSTGRESQL_URL,
+};
 pub use error::{Error, Result};
+pub use extension::{
+    extension_asset_name, get_extension_archive, get_extension_archive_file,
+    get_extension_archive_file_for_target, get_extension_archive_for_target, install_extension,
+    install_extension_file,
+};
+pub use format::ArchiveFormat;
+pub use progress::{Progress, ProgressEvent};
 #[allow(deprecated)]
 pub use version::{Version, LATEST, V12, V13, V14, V15, V16};
@@ -0,0 +1,131 @@
+//! Progress reporting for downloading and extracting archives
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// Progress of [getting](crate::get_archive_for_target_with_progress) or
+/// [extracting](crate::extract_with_progress) an archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// Resolving the version and asset of the archive in the repository
+    Resolving,
+    /// Downloading the archive; `total` is the size of the archive in bytes, if known
+    Downloading { bytes: u64, total: Option<u64> },
+    /// Verifying the SHA-256 hash of the archive
+    Verifying,
+    /// Extracting the archive; the number of files and bytes extracted so far
+    Extracting { files: u64, bytes: u64 },
+    /// The operation completed successfully
+    Done,
+}
+
+type ProgressFn = dyn Fn(ProgressEvent) + Send + Sync;
+
+/// Receiver of [progress events](ProgressEvent), either a callback or the sending half of a
+/// channel. The default progress discards all events.
+///
+/// ```no_run
+/// use postgresql_archive::{Progress, ProgressEvent};
+///
+/// let progress = Progress::new(|event| {
+///     if let ProgressEvent::Downloading { bytes, total: Some(total) } = event {
+///         println!("Downloaded {bytes} of {total} bytes");
+///     }
+/// });
+/// ```
+///
+/// Events can also be received from a channel, e.g. on another thread:
+///
+/// ```no_run
+/// use postgresql_archive::Progress;
+///
+/// let (progress, receiver) = Progress::channel();
+/// std::thread::spawn(move || {
+///     for event in receiver {
+///         println!("{event:?}");
+///     }
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct Progress {
+    callback: Option<Arc<ProgressFn>>,
+}
+
+impl Progress {
+    /// Creates a new progress that calls the callback for each [event](ProgressEvent). The
+    /// callback is called on the thread doing the work, so it should return quickly.
+    pub fn new<F: Fn(ProgressEvent) + Send + Sync + 'static>(callback: F) -> Self {
+        Self {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    /// Creates a new progress that sends each [event](ProgressEvent) to the returned receiver
+    pub fn channel() -> (Self, Receiver<ProgressEvent>) {
+        let (sender, receiver) = channel();
+        (Self::from(sender), receiver)
+    }
+
+    /// Reports the [event](ProgressEvent)
+    pub fn report(&self, event: ProgressEvent) {
+        if let Some(callback) = &self.callback {
+            callback(event);
+        }
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Progress")
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// Sends each [event](ProgressEvent) to the channel; events sent after the receiver is dropped
+/// are discarded
+impl From<Sender<ProgressEvent>> for Progress {
+    fn from(sender: Sender<ProgressEvent>) -> Self {
+        Self::new(move |event| {
+            let _ = sender.send(event);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_default() {
+        let progress = Progress::default();
+        progress.report(ProgressEvent::Done);
+        assert_eq!("Progress { callback: false }", format!("{progress:?}"));
+    }
+
+    #[test]
+    fn test_callback() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let callback_events = events.clone();
+        let progress = Progress::new(move |event| callback_events.lock().unwrap().push(event));
+        progress.report(ProgressEvent::Resolving);
+        progress.report(ProgressEvent::Done);
+        assert_eq!(
+            vec![ProgressEvent::Resolving, ProgressEvent::Done],
+            *events.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_channel() {
+        let (progress, receiver) = Progress::channel();
+        progress.report(ProgressEvent::Verifying);
+        drop(progress);
+        assert_eq!(
+            vec![ProgressEvent::Verifying],
+            receiver.iter().collect::<Vec<_>>()
+        );
+    }
+}
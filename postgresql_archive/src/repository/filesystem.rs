@@ -0,0 +1,400 @@
+//! Repository of PostgreSQL archives in a local directory
+use crate::archive::{archive_asset_name, parse_hash};
+use crate::download::ArchiveWriter;
+use crate::error::Error::{
+    AssetHashNotFound, AssetNotFound, ReleaseNotFound, UnsupportedRepository,
+};
+use crate::error::Result;
+use crate::format::{split_extension, ArchiveFormat};
+use crate::repository::model::{Asset, Repository};
+use crate::version::Version;
+use async_trait::async_trait;
+use bytes::Bytes;
+use human_bytes::human_bytes;
+use std::fs::{read, read_dir, read_to_string, File};
+use std::io::copy;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{debug, instrument};
+use url::Url;
+
+/// Repository of PostgreSQL archives in a local directory, for machines without internet access.
+/// Archives use the same naming as the GitHub release assets, `postgresql-<version>-<target>.tar.gz`,
+/// and are accompanied by a `.sha256` file containing the hash of the archive. Archives in other
+/// [formats](ArchiveFormat) are named with the extension of the format instead (e.g.
+/// `postgresql-<version>-<target>.zip`); when there are archives in several formats for the same
+/// version, one whose format is supported is preferred. Archives may be in the directory itself or
+/// in subdirectories, such as one directory per version.
+///
+/// The repository is selected with a `file://` URL (e.g. `file:///opt/postgresql/archives`) or the
+/// path of an existing directory.
+#[derive(Debug)]
+pub struct Filesystem {
+    dir: PathBuf,
+}
+
+impl Filesystem {
+    /// Checks if the URL is a `file://` URL or the path of an existing directory
+    pub fn supports(url: &str) -> Result<bool> {
+        Ok(url.starts_with("file://") || Path::new(url).is_dir())
+    }
+
+    /// Creates a new filesystem repository for the `file://` URL or directory path
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(url: &str) -> Result<Box<dyn Repository>> {
+        let dir = if url.starts_with("file://") {
+            Url::parse(url)
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or_else(|| UnsupportedRepository(url.to_string()))?
+        } else {
+            PathBuf::from(url)
+        };
+        Ok(Box::new(Self { dir }))
+    }
+
+    /// Gets the archives in the repository directory and its subdirectories, along with the
+    /// archive version and target
+    fn get_archives(&self) -> Result<Vec<(Version, String, PathBuf)>> {
+        let mut archives = Vec::new();
+        let mut dirs = vec![self.dir.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            for entry in read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let Some((version, target)) = parse_archive_name(file_name) else {
+                    continue;
+                };
+                archives.push((version, target, path));
+            }
+        }
+
+        Ok(archives)
+    }
+}
+
+/// Parses the version and target from an archive name such as
+/// `postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz`, with the extension of any
+/// [archive format](ArchiveFormat)
+fn parse_archive_name(name: &str) -> Option<(Version, String)> {
+    let (name, _) = split_extension(name.strip_prefix("postgresql-")?)?;
+    let (version, target) = name.split_once('-')?;
+    let version = Version::from_str(version).ok()?;
+    if version.minor.is_none() || version.release.is_none() || target.is_empty() {
+        return None;
+    }
+    Some((version, target.to_string()))
+}
+
+/// Checks if the [format](ArchiveFormat) of the archive at the [path](Path) is supported
+fn format_supported(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| ArchiveFormat::from_name(&name.to_string_lossy()))
+        .is_some_and(|format| format.is_supported())
+}
+
+#[async_trait]
+impl Repository for Filesystem {
+    fn name(&self) -> &str {
+        "Filesystem"
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_versions(&self) -> Result<Vec<Version>> {
+        let mut versions: Vec<Version> = Vec::new();
+        for (version, _, _) in self.get_archives()? {
+            if !versions.contains(&version) {
+                versions.push(version);
+            }
+        }
+        Ok(versions)
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_asset(&self, version: &Version, target: &str) -> Result<Asset> {
+        let archive = self
+            .get_archives()?
+            .into_iter()
+            .filter(|(archive_version, archive_target, _)| {
+                archive_target == target && version.matches(archive_version)
+            })
+            .max_by(|(a, _, a_path), (b, _, b_path)| {
+                a.partial_cmp(b)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(format_supported(a_path).cmp(&format_supported(b_path)))
+            });
+        let Some((asset_version, _, path)) = archive else {
+            return match version.minor.is_some() && version.release.is_some() {
+                true => Err(AssetNotFound(archive_asset_name(version, target))),
+                false => Err(ReleaseNotFound(version.to_string())),
+            };
+        };
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| archive_asset_name(&asset_version, target));
+        let hash_path = path.with_file_name(format!("{name}.sha256"));
+        if !hash_path.is_file() {
+            return Err(AssetHashNotFound(name));
+        }
+
+        debug!(
+            "Archive {} found for version {version}",
+            path.to_string_lossy()
+        );
+        Ok(Asset {
+            version: asset_version,
+            name,
+            url: path.to_string_lossy().to_string(),
+            hash_url: hash_path.to_string_lossy().to_string(),
+        })
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_archive(&self, asset: &Asset) -> Result<Bytes> {
+        let archive = Bytes::from(read(&asset.url)?);
+        debug!(
+            "Archive {} read: {}",
+            asset.url,
+            human_bytes(archive.len() as f64)
+        );
+        Ok(archive)
+    }
+
+    #[instrument(level = "debug", skip(writer))]
+    async fn write_archive(&self, asset: &Asset, writer: &mut ArchiveWriter) -> Result<u64> {
+        writer.restart()?;
+        let mut file = File::open(&asset.url)?;
+        writer.set_total(Some(file.metadata()?.len()));
+        let size = copy(&mut file, writer)?;
+        debug!("Archive {} read: {}", asset.url, human_bytes(size as f64));
+        Ok(size)
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_hash(&self, asset: &Asset) -> Result<String> {
+        let text = read_to_string(&asset.hash_url)?;
+        parse_hash(&text, &asset.name)
+    }
+
+    /// Archives are already on disk, so they are not copied into the cache
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::get_archive_for_target_from;
+    use crate::Error::ArchiveHashMismatch;
+    use sha2::{Digest, Sha256};
+    use std::fs::{create_dir_all, write};
+    use test_log::test;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    /// Write an archive and its hash file to the directory
+    fn write_archive(dir: &Path, version: &str, target: &str) -> Result<()> {
+        write_archive_with_extension(dir, version, target, "tar.gz")
+    }
+
+    /// Write an archive with the extension and its hash file to the directory
+    fn write_archive_with_extension(
+        dir: &Path,
+        version: &str,
+        target: &str,
+        extension: &str,
+    ) -> Result<()> {
+        let name = format!("postgresql-{version}-{target}.{extension}");
+        let contents = format!("{version} {target}");
+        write(dir.join(&name), &contents)?;
+        let hash = hex::encode(Sha256::digest(contents.as_bytes()));
+        write(
+            dir.join(format!("{name}.sha256")),
+            format!("{hash}  {name}\n"),
+        )?;
+        Ok(())
+    }
+
+    fn repository() -> Result<(tempfile::TempDir, String)> {
+        let dir = tempfile::tempdir()?;
+        write_archive(dir.path(), "16.1.0", TARGET)?;
+        write_archive(dir.path(), "16.2.0", "aarch64-apple-darwin")?;
+        let version_dir = dir.path().join("15.6.0");
+        create_dir_all(&version_dir)?;
+        write_archive(&version_dir, "15.6.0", TARGET)?;
+        write(dir.path().join("README.md"), "archives")?;
+        let url = Url::from_directory_path(dir.path())
+            .map_err(|_| UnsupportedRepository(dir.path().to_string_lossy().to_string()))?;
+        Ok((dir, url.to_string()))
+    }
+
+    #[test]
+    fn test_supports() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert!(Filesystem::supports("file:///opt/postgresql")?);
+        assert!(Filesystem::supports(&dir.path().to_string_lossy())?);
+        assert!(!Filesystem::supports(
+            "https://github.com/theseus-rs/postgresql-binaries"
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_archive_name() {
+        assert_eq!(
+            Some((Version::new(16, Some(2), Some(0)), TARGET.to_string())),
+            parse_archive_name("postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz")
+        );
+        assert_eq!(
+            None,
+            parse_archive_name("postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz.sha256")
+        );
+        assert_eq!(
+            Some((Version::new(16, Some(2), Some(0)), TARGET.to_string())),
+            parse_archive_name("postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.zst")
+        );
+        assert_eq!(
+            Some((
+                Version::new(16, Some(2), Some(0)),
+                "x86_64-pc-windows-msvc".to_string()
+            )),
+            parse_archive_name("postgresql-16.2.0-x86_64-pc-windows-msvc.zip")
+        );
+        assert_eq!(None, parse_archive_name("postgresql-16-x86_64.tar.gz"));
+        assert_eq!(None, parse_archive_name("postgresql-16.2.0.tar.gz"));
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_versions() -> Result<()> {
+        let (_dir, url) = repository()?;
+        let repository = Filesystem::new(&url)?;
+        let mut versions = repository.get_versions().await?;
+        versions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            vec![
+                Version::new(15, Some(6), Some(0)),
+                Version::new(16, Some(1), Some(0)),
+                Version::new(16, Some(2), Some(0)),
+            ],
+            versions
+        );
+        assert_eq!(
+            Version::new(16, Some(2), Some(0)),
+            repository
+                .get_version(&Version::new(16, None, None))
+                .await?
+        );
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_asset_archive_format() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_archive_with_extension(dir.path(), "16.2.0", TARGET, "tar.zst")?;
+        write_archive_with_extension(dir.path(), "16.2.0", "x86_64-pc-windows-msvc", "zip")?;
+        let repository = Filesystem::new(&dir.path().to_string_lossy())?;
+
+        let asset = repository
+            .get_asset(&Version::new(16, None, None), TARGET)
+            .await?;
+        assert_eq!(Version::new(16, Some(2), Some(0)), asset.version);
+        assert_eq!(format!("postgresql-16.2.0-{TARGET}.tar.zst"), asset.name);
+        let asset = repository
+            .get_asset(&Version::new(16, None, None), "x86_64-pc-windows-msvc")
+            .await?;
+        assert_eq!("postgresql-16.2.0-x86_64-pc-windows-msvc.zip", asset.name);
+
+        // Archives in a supported format are preferred over archives of the same version in an
+        // unsupported format
+        if !ArchiveFormat::TarZst.is_supported() {
+            write_archive(dir.path(), "16.2.0", TARGET)?;
+            let asset = repository
+                .get_asset(&Version::new(16, None, None), TARGET)
+                .await?;
+            assert_eq!(format!("postgresql-16.2.0-{TARGET}.tar.gz"), asset.name);
+        }
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_asset() -> Result<()> {
+        let (dir, url) = repository()?;
+        let repository = Filesystem::new(&url)?;
+
+        let asset = repository
+            .get_asset(&Version::new(16, None, None), TARGET)
+            .await?;
+        assert_eq!(Version::new(16, Some(1), Some(0)), asset.version);
+        assert_eq!(
+            "postgresql-16.1.0-x86_64-unknown-linux-gnu.tar.gz",
+            asset.name
+        );
+
+        let asset = repository
+            .get_asset(&Version::new(15, None, None), TARGET)
+            .await?;
+        assert!(asset
+            .url
+            .starts_with(&*dir.path().join("15.6.0").to_string_lossy()));
+
+        assert!(matches!(
+            repository
+                .get_asset(&Version::new(14, None, None), TARGET)
+                .await,
+            Err(ReleaseNotFound(_))
+        ));
+        assert!(matches!(
+            repository
+                .get_asset(&Version::new(16, Some(2), Some(0)), TARGET)
+                .await,
+            Err(AssetNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive() -> Result<()> {
+        let (_dir, url) = repository()?;
+        let (version, archive) =
+            get_archive_for_target_from(&url, &Version::new(16, None, None), TARGET).await?;
+        assert_eq!(Version::new(16, Some(1), Some(0)), version);
+        assert_eq!(Bytes::from(format!("16.1.0 {TARGET}")), archive);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_hash_not_found() -> Result<()> {
+        let (dir, url) = repository()?;
+        std::fs::remove_file(
+            dir.path()
+                .join("postgresql-16.1.0-x86_64-unknown-linux-gnu.tar.gz.sha256"),
+        )?;
+        let result = get_archive_for_target_from(&url, &Version::new(16, None, None), TARGET).await;
+        assert!(matches!(result, Err(AssetHashNotFound(_))));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_hash_mismatch() -> Result<()> {
+        let (dir, url) = repository()?;
+        write(
+            dir.path()
+                .join("postgresql-16.1.0-x86_64-unknown-linux-gnu.tar.gz"),
+            "modified",
+        )?;
+        let result = get_archive_for_target_from(&url, &Version::new(16, None, None), TARGET).await;
+        assert!(matches!(result, Err(ArchiveHashMismatch { .. })));
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+//! Repository of PostgreSQL archives published as GitHub releases
+mod models;
+mod repository;
+
+pub use repository::GitHub;
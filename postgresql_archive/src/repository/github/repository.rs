@@ -0,0 +1,343 @@
+use crate::archive::{archive_asset_name, get_hash, reqwest_client_builder};
+use crate::download::{download, ArchiveWriter};
+use crate::error::Error::{AssetNotFound, ReleaseNotFound, UnsupportedRepository};
+use crate::error::Result;
+use crate::repository::github::models::Release;
+use crate::repository::model::{Asset, Repository};
+use crate::version::Version;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::Extensions;
+use human_bytes::human_bytes;
+use reqwest::{header, Request, Response};
+use reqwest_middleware::{ClientWithMiddleware, Middleware, Next};
+use std::str::FromStr;
+use tracing::{debug, instrument, warn};
+use url::Url;
+
+const GITHUB_API_VERSION_HEADER: &str = "X-GitHub-Api-Version";
+const GITHUB_API_VERSION: &str = "2022-11-28";
+
+lazy_static! {
+    static ref GITHUB_TOKEN: Option<String> = match std::env::var("GITHUB_TOKEN") {
+        Ok(token) => {
+            debug!("GITHUB_TOKEN environment variable found");
+            Some(token)
+        }
+        Err(_) => None,
+    };
+}
+
+lazy_static! {
+    static ref USER_AGENT: String = format!(
+        "{PACKAGE}/{VERSION}",
+        PACKAGE = env!("CARGO_PKG_NAME"),
+        VERSION = env!("CARGO_PKG_VERSION")
+    );
+}
+
+/// Middleware to add GitHub headers to the request. If a GitHub token is set, then it is added as a
+/// bearer token. This is used to authenticate with the GitHub API to increase the rate limit.
+#[derive(Debug)]
+struct GithubMiddleware;
+
+impl GithubMiddleware {
+    fn add_github_headers(&self, request: &mut Request) -> Result<()> {
+        let headers = request.headers_mut();
+
+        headers.append(
+            GITHUB_API_VERSION_HEADER,
+            GITHUB_API_VERSION.parse().unwrap(),
+        );
+        headers.append(header::USER_AGENT, USER_AGENT.parse().unwrap());
+
+        if let Some(token) = &*GITHUB_TOKEN {
+            headers.append(
+                header::AUTHORIZATION,
+                format!("Bearer {token}").parse().unwrap(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for GithubMiddleware {
+    async fn handle(
+        &self,
+        mut request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        match self.add_github_headers(&mut request) {
+            Ok(_) => next.run(request, extensions).await,
+            Err(error) => Err(reqwest_middleware::Error::Middleware(error.into())),
+        }
+    }
+}
+
+/// Creates a new reqwest client with middleware for tracing, GitHub, and retrying transient errors.
+fn reqwest_client() -> ClientWithMiddleware {
+    reqwest_client_builder().with(GithubMiddleware).build()
+}
+
+/// Repository of PostgreSQL archives published as the assets of GitHub releases, with a release
+/// for each version tagged with the version (e.g. `16.2.0`). Archives are named
+/// `postgresql-<version>-<target>.tar.gz` and are accompanied by a `.sha256` file containing the
+/// hash of the archive.
+#[derive(Debug)]
+pub struct GitHub {
+    releases_url: String,
+}
+
+impl GitHub {
+    /// Checks if the URL is a GitHub repository URL (e.g.
+    /// `https://github.com/theseus-rs/postgresql-binaries`)
+    pub fn supports(url: &str) -> Result<bool> {
+        match Url::parse(url) {
+            Ok(url) => Ok(url.host_str() == Some("github.com")),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Creates a new GitHub repository for the repository URL. If the URL does not name a GitHub
+    /// owner and repository, then an [UnsupportedRepository] error is returned.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(url: &str) -> Result<Box<dyn Repository>> {
+        let parsed_url = Url::parse(url).map_err(|_| UnsupportedRepository(url.to_string()))?;
+        let segments: Vec<&str> = parsed_url
+            .path_segments()
+            .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+            .unwrap_or_default();
+        let [owner, repository] = segments[..] else {
+            return Err(UnsupportedRepository(url.to_string()));
+        };
+        let releases_url = format!("https://api.github.com/repos/{owner}/{repository}/releases");
+        Ok(Box::new(Self { releases_url }))
+    }
+
+    /// Gets all releases from GitHub along with their parsed [versions](Version). Releases with a
+    /// tag that is not a valid version are skipped.
+    #[instrument(level = "debug")]
+    async fn get_releases(&self) -> Result<Vec<(Version, Release)>> {
+        let client = reqwest_client();
+        let mut releases = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let request = client
+                .get(&self.releases_url)
+                .query(&[("page", page.to_string().as_str()), ("per_page", "100")]);
+            let response = request.send().await?.error_for_status()?;
+            let response_releases = response.json::<Vec<Release>>().await?;
+            if response_releases.is_empty() {
+                break;
+            }
+
+            for release in response_releases {
+                match Version::from_str(&release.tag_name) {
+                    Ok(release_version) => releases.push((release_version, release)),
+                    Err(_) => warn!("Failed to parse release version {}", release.tag_name),
+                }
+            }
+
+            page += 1;
+        }
+
+        Ok(releases)
+    }
+
+    /// Gets a release from GitHub for a given [version](Version) of PostgreSQL. If a release for
+    /// the [version](Version) is not found, then a [ReleaseNotFound] error is returned.
+    #[instrument(level = "debug")]
+    async fn get_release(&self, version: &Version) -> Result<Release> {
+        debug!("Attempting to locate release for version {version}");
+
+        if version.minor.is_some() && version.release.is_some() {
+            let client = reqwest_client();
+            let request = client.get(format!("{}/tags/{version}", self.releases_url));
+            let response = request.send().await?.error_for_status()?;
+            let release = response.json::<Release>().await?;
+
+            debug!("Release found for version {version}");
+            return Ok(release);
+        }
+
+        let result = self
+            .get_releases()
+            .await?
+            .into_iter()
+            .filter(|(release_version, _)| version.matches(release_version))
+            .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        match result {
+            Some((release_version, release)) => {
+                debug!("Release {release_version} found for version {version}");
+                Ok(release)
+            }
+            None => Err(ReleaseNotFound(version.to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl Repository for GitHub {
+    fn name(&self) -> &str {
+        "GitHub"
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_versions(&self) -> Result<Vec<Version>> {
+        let versions = self
+            .get_releases()
+            .await?
+            .into_iter()
+            .map(|(release_version, _)| release_version)
+            .collect();
+        Ok(versions)
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_version(&self, version: &Version) -> Result<Version> {
+        let release = self.get_release(version).await?;
+        Version::from_str(&release.tag_name)
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_asset(&self, version: &Version, target: &str) -> Result<Asset> {
+        let release = self.get_release(version).await?;
+        let asset_version = Version::from_str(&release.tag_name)?;
+        let asset_name = archive_asset_name(&asset_version, target);
+        let asset_hash_name = format!("{asset_name}.sha256");
+        let mut url = None;
+        let mut hash_url = None;
+
+        for release_asset in release.assets {
+            if release_asset.name == asset_name {
+                url = Some(release_asset.browser_download_url);
+            } else if release_asset.name == asset_hash_name {
+                hash_url = Some(release_asset.browser_download_url);
+            }
+
+            if url.is_some() && hash_url.is_some() {
+                break;
+            }
+        }
+
+        match (url, hash_url) {
+            (Some(url), Some(hash_url)) => Ok(Asset {
+                version: asset_version,
+                name: asset_name,
+                url,
+                hash_url,
+            }),
+            _ => Err(AssetNotFound(asset_name)),
+        }
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_archive(&self, asset: &Asset) -> Result<Bytes> {
+        debug!("Downloading archive {}", asset.url);
+        let client = reqwest_client();
+        let request = client.get(&asset.url);
+        let response = request.send().await?.error_for_status()?;
+        let archive: Bytes = response.bytes().await?;
+        debug!(
+            "Archive {} downloaded: {}",
+            asset.url,
+            human_bytes(archive.len() as f64)
+        );
+        Ok(archive)
+    }
+
+    #[instrument(level = "debug", skip(writer))]
+    async fn write_archive(&self, asset: &Asset, writer: &mut ArchiveWriter) -> Result<u64> {
+        let client = reqwest_client();
+        download(&client, &asset.url, writer).await
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_hash(&self, asset: &Asset) -> Result<String> {
+        let client = reqwest_client();
+        get_hash(&client, &asset.hash_url, &asset.name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_POSTGRESQL_URL;
+    use test_log::test;
+
+    /// Use a known, fully defined version to speed up test execution
+    const VERSION: Version = Version::new(16, Some(1), Some(0));
+    const INVALID_VERSION: Version = Version::new(1, Some(0), Some(0));
+
+    fn repository() -> Result<Box<dyn Repository>> {
+        GitHub::new(DEFAULT_POSTGRESQL_URL)
+    }
+
+    #[test]
+    fn test_supports() -> Result<()> {
+        assert!(GitHub::supports(DEFAULT_POSTGRESQL_URL)?);
+        assert!(!GitHub::supports("https://example.com/postgresql")?);
+        assert!(!GitHub::supports("not a url")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new() -> Result<()> {
+        let repository = GitHub {
+            releases_url: String::new(),
+        };
+        assert_eq!("GitHub", repository.name());
+        assert!(GitHub::new("https://github.com/theseus-rs/").is_err());
+        assert!(GitHub::new("https://github.com/theseus-rs/postgresql-binaries/releases").is_err());
+        assert!(GitHub::new("https://github.com/theseus-rs/postgresql-binaries/").is_ok());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_version() -> Result<()> {
+        let version = repository()?.get_version(&VERSION).await?;
+        assert_eq!(VERSION, version);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_version_not_found() -> Result<()> {
+        let result = repository()?.get_version(&INVALID_VERSION).await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_asset() -> Result<()> {
+        let target_triple = "x86_64-unknown-linux-musl";
+        let asset = repository()?.get_asset(&VERSION, target_triple).await?;
+        assert!(asset.version.matches(&VERSION));
+        assert!(asset.name.contains(target_triple));
+        assert!(asset.url.ends_with(&asset.name));
+        assert!(asset.hash_url.ends_with(".sha256"));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_asset_version_not_found() -> Result<()> {
+        let target_triple = "x86_64-unknown-linux-musl";
+        let result = repository()?
+            .get_asset(&INVALID_VERSION, target_triple)
+            .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_asset_target_not_found() -> Result<()> {
+        let target_triple = "wasm64-unknown-unknown";
+        let result = repository()?.get_asset(&VERSION, target_triple).await;
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,6 @@
+//! Repository of PostgreSQL archives hosted on a plain HTTP server with a JSON index
+mod models;
+mod repository;
+
+pub use models::{Index, IndexAsset, IndexVersion};
+pub use repository::Http;
@@ -0,0 +1,51 @@
+//! Structs for the JSON index of an HTTP repository
+use crate::version::Version;
+use serde::{Deserialize, Serialize};
+
+/// Index of the archives available in an HTTP repository, e.g.:
+///
+/// ```json
+/// {
+///   "versions": [
+///     {
+///       "version": "16.2.0",
+///       "assets": [
+///         {
+///           "target": "x86_64-unknown-linux-gnu",
+///           "url": "16.2.0/postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz",
+///           "sha256": "9f4d3b9e5e9b8e1b..."
+///         }
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Asset URLs are either absolute or relative to the URL of the index.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Index {
+    /// Versions of PostgreSQL available in the repository
+    pub versions: Vec<IndexVersion>,
+}
+
+/// A version of PostgreSQL in the [index](Index) along with its archives
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct IndexVersion {
+    /// Version of PostgreSQL
+    pub version: Version,
+    /// Archives of the version, one per target
+    #[serde(default)]
+    pub assets: Vec<IndexAsset>,
+}
+
+/// An archive of a [version](IndexVersion) of PostgreSQL for a
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html)
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct IndexAsset {
+    /// Target of the archive (e.g. `x86_64-unknown-linux-gnu`)
+    pub target: String,
+    /// URL of the archive, absolute or relative to the URL of the index
+    pub url: String,
+    /// SHA-256 hash of the archive
+    pub sha256: String,
+}
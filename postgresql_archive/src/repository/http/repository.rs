@@ -0,0 +1,334 @@
+use crate::archive::{parse_hash, reqwest_client};
+use crate::download::{download, ArchiveWriter};
+use crate::error::Error::{AssetHashNotFound, AssetNotFound, ReleaseNotFound};
+use crate::error::Result;
+use crate::repository::http::models::{Index, IndexAsset};
+use crate::repository::model::{Asset, Repository};
+use crate::version::Version;
+use async_trait::async_trait;
+use bytes::Bytes;
+use human_bytes::human_bytes;
+use std::sync::Mutex;
+use tracing::{debug, instrument};
+use url::Url;
+
+/// Repository of PostgreSQL archives hosted on a plain HTTP server (e.g. nginx or S3-compatible
+/// static hosting). The repository is described by a JSON [index](Index) listing the versions,
+/// targets, archive URLs and hashes; the repository URL is the URL of the index (e.g.
+/// `https://mirror.example.com/postgresql/index.json`).
+#[derive(Debug)]
+pub struct Http {
+    index_url: Url,
+    index: Mutex<Option<Index>>,
+}
+
+impl Http {
+    /// Checks if the URL is the URL of a JSON index on an HTTP server (e.g.
+    /// `https://mirror.example.com/postgresql/index.json`)
+    pub fn supports(url: &str) -> Result<bool> {
+        match Url::parse(url) {
+            Ok(url) => {
+                Ok(matches!(url.scheme(), "http" | "https") && url.path().ends_with(".json"))
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Creates a new HTTP repository for the URL of the index
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(url: &str) -> Result<Box<dyn Repository>> {
+        let index_url = Url::parse(url)?;
+        Ok(Box::new(Self {
+            index_url,
+            index: Mutex::new(None),
+        }))
+    }
+
+    /// Gets the index of the repository. The index is downloaded once and reused for subsequent
+    /// calls.
+    #[instrument(level = "debug")]
+    async fn get_index(&self) -> Result<Index> {
+        if let Ok(index) = self.index.lock() {
+            if let Some(index) = index.as_ref() {
+                return Ok(index.clone());
+            }
+        }
+
+        debug!("Downloading index {}", self.index_url);
+        let client = reqwest_client();
+        let request = client.get(self.index_url.as_str());
+        let response = request.send().await?.error_for_status()?;
+        let index = response.json::<Index>().await?;
+
+        if let Ok(mut cached_index) = self.index.lock() {
+            *cached_index = Some(index.clone());
+        }
+        Ok(index)
+    }
+
+    /// Resolves the URL of an index asset, which may be relative to the URL of the index
+    fn asset_url(&self, asset: &IndexAsset) -> Result<Url> {
+        Ok(self.index_url.join(&asset.url)?)
+    }
+}
+
+#[async_trait]
+impl Repository for Http {
+    fn name(&self) -> &str {
+        "HTTP"
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_versions(&self) -> Result<Vec<Version>> {
+        let versions = self
+            .get_index()
+            .await?
+            .versions
+            .into_iter()
+            .map(|index_version| index_version.version)
+            .collect();
+        Ok(versions)
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_asset(&self, version: &Version, target: &str) -> Result<Asset> {
+        let index_version = self
+            .get_index()
+            .await?
+            .versions
+            .into_iter()
+            .filter(|index_version| version.matches(&index_version.version))
+            .max_by(|a, b| {
+                a.version
+                    .partial_cmp(&b.version)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .ok_or_else(|| ReleaseNotFound(version.to_string()))?;
+        let asset_version = index_version.version;
+
+        let Some(index_asset) = index_version
+            .assets
+            .iter()
+            .find(|index_asset| index_asset.target == target)
+        else {
+            return Err(AssetNotFound(format!("{asset_version}/{target}")));
+        };
+
+        let url = self.asset_url(index_asset)?;
+        let name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or_default()
+            .to_string();
+        debug!("Asset {url} found for version {version}");
+
+        Ok(Asset {
+            version: asset_version,
+            name,
+            url: url.to_string(),
+            hash_url: self.index_url.to_string(),
+        })
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_archive(&self, asset: &Asset) -> Result<Bytes> {
+        debug!("Downloading archive {}", asset.url);
+        let client = reqwest_client();
+        let request = client.get(&asset.url);
+        let response = request.send().await?.error_for_status()?;
+        let archive: Bytes = response.bytes().await?;
+        debug!(
+            "Archive {} downloaded: {}",
+            asset.url,
+            human_bytes(archive.len() as f64)
+        );
+        Ok(archive)
+    }
+
+    #[instrument(level = "debug", skip(writer))]
+    async fn write_archive(&self, asset: &Asset, writer: &mut ArchiveWriter) -> Result<u64> {
+        let client = reqwest_client();
+        download(&client, &asset.url, writer).await
+    }
+
+    /// Gets the SHA-256 hash of the archive from the index
+    #[instrument(level = "debug")]
+    async fn get_hash(&self, asset: &Asset) -> Result<String> {
+        let index = self.get_index().await?;
+        for index_version in index.versions {
+            if index_version.version != asset.version {
+                continue;
+            }
+            for index_asset in &index_version.assets {
+                if self.asset_url(index_asset)?.as_str() == asset.url {
+                    return parse_hash(&index_asset.sha256.to_lowercase(), &asset.name);
+                }
+            }
+        }
+
+        Err(AssetHashNotFound(asset.name.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::get_archive_for_target_from;
+    use crate::repository::http::models::IndexVersion;
+    use crate::Error::ArchiveHashMismatch;
+    use sha2::{Digest, Sha256};
+    use test_log::test;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+    const ARCHIVE: &[u8] = b"archive";
+
+    fn index(hash: &str) -> Index {
+        let asset = |version: &str| IndexAsset {
+            target: TARGET.to_string(),
+            url: format!("{version}/postgresql-{version}-{TARGET}.tar.gz"),
+            sha256: hash.to_string(),
+        };
+        Index {
+            versions: vec![
+                IndexVersion {
+                    version: Version::new(15, Some(6), Some(0)),
+                    assets: vec![asset("15.6.0")],
+                },
+                IndexVersion {
+                    version: Version::new(16, Some(2), Some(0)),
+                    assets: vec![asset("16.2.0")],
+                },
+                IndexVersion {
+                    version: Version::new(16, Some(1), Some(0)),
+                    assets: vec![IndexAsset {
+                        target: TARGET.to_string(),
+                        url: "https://archives.example.com/postgresql-16.1.0.tar.gz".to_string(),
+                        sha256: hash.to_string(),
+                    }],
+                },
+            ],
+        }
+    }
+
+    /// Start a server hosting the index and an archive for version 16.2.0
+    async fn server(hash: &str) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/postgresql/index.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(index(hash)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/postgresql/16.2.0/postgresql-16.2.0-{TARGET}.tar.gz"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(ARCHIVE))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn archive_hash() -> String {
+        hex::encode(Sha256::digest(ARCHIVE))
+    }
+
+    #[test]
+    fn test_supports() -> Result<()> {
+        assert!(Http::supports(
+            "https://mirror.example.com/postgresql/index.json"
+        )?);
+        assert!(Http::supports("http://localhost:8080/index.json")?);
+        assert!(!Http::supports("https://mirror.example.com/postgresql")?);
+        assert!(!Http::supports("file:///opt/postgresql/index.json")?);
+        assert!(!Http::supports("not a url")?);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_versions() -> Result<()> {
+        let server = server(&archive_hash()).await;
+        let repository = Http::new(&format!("{}/postgresql/index.json", server.uri()))?;
+        let versions = repository.get_versions().await?;
+        assert_eq!(3, versions.len());
+        assert_eq!(
+            Version::new(16, Some(2), Some(0)),
+            repository
+                .get_version(&Version::new(16, None, None))
+                .await?
+        );
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_asset() -> Result<()> {
+        let server = server(&archive_hash()).await;
+        let repository = Http::new(&format!("{}/postgresql/index.json", server.uri()))?;
+
+        let asset = repository
+            .get_asset(&Version::new(16, None, None), TARGET)
+            .await?;
+        assert_eq!(Version::new(16, Some(2), Some(0)), asset.version);
+        assert_eq!(format!("postgresql-16.2.0-{TARGET}.tar.gz"), asset.name);
+        assert_eq!(
+            format!(
+                "{}/postgresql/16.2.0/postgresql-16.2.0-{TARGET}.tar.gz",
+                server.uri()
+            ),
+            asset.url
+        );
+        assert_eq!(archive_hash(), repository.get_hash(&asset).await?);
+
+        let asset = repository
+            .get_asset(&Version::new(16, Some(1), Some(0)), TARGET)
+            .await?;
+        assert_eq!(
+            "https://archives.example.com/postgresql-16.1.0.tar.gz",
+            asset.url
+        );
+
+        assert!(matches!(
+            repository
+                .get_asset(&Version::new(14, None, None), TARGET)
+                .await,
+            Err(ReleaseNotFound(_))
+        ));
+        assert!(matches!(
+            repository
+                .get_asset(&Version::new(16, None, None), "wasm64-unknown-unknown")
+                .await,
+            Err(AssetNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive() -> Result<()> {
+        let server = server(&archive_hash()).await;
+        let url = format!("{}/postgresql/index.json", server.uri());
+        let (version, archive) =
+            get_archive_for_target_from(&url, &Version::new(16, None, None), TARGET).await?;
+        assert_eq!(Version::new(16, Some(2), Some(0)), version);
+        assert_eq!(Bytes::from(ARCHIVE), archive);
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_hash_mismatch() -> Result<()> {
+        let server = server(&"0".repeat(64)).await;
+        let url = format!("{}/postgresql/index.json", server.uri());
+        let result = get_archive_for_target_from(&url, &Version::new(16, None, None), TARGET).await;
+        assert!(matches!(result, Err(ArchiveHashMismatch { .. })));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_index_not_found() -> Result<()> {
+        let server = MockServer::start().await;
+        let repository = Http::new(&format!("{}/postgresql/index.json", server.uri()))?;
+        assert!(repository.get_versions().await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,15 @@
+//! Repositories of PostgreSQL archives
+pub mod filesystem;
+pub mod github;
+pub mod http;
+mod model;
+pub mod registry;
+#[cfg(feature = "zonky")]
+pub mod zonky;
+
+pub use filesystem::Filesystem;
+pub use github::GitHub;
+pub use http::Http;
+pub use model::{Asset, Repository};
+#[cfg(feature = "zonky")]
+pub use zonky::Zonky;
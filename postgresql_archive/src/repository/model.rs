@@ -0,0 +1,195 @@
+use crate::archive::verify_archive_hash;
+use crate::download::ArchiveWriter;
+use crate::error::Error::{ReleaseNotFound, Unexpected};
+use crate::error::Result;
+use crate::version::Version;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// An archive asset of a [repository](Repository) for a [version](Version) of PostgreSQL and a
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Asset {
+    /// Version of PostgreSQL in the archive
+    pub version: Version,
+    /// Name of the archive
+    pub name: String,
+    /// Location of the archive
+    pub url: String,
+    /// Location of the SHA-256 hash of the archive
+    pub hash_url: String,
+}
+
+/// A repository of PostgreSQL archives, such as the GitHub releases of
+/// [theseus-rs/postgresql-binaries](https://github.com/theseus-rs/postgresql-binaries).
+/// Repositories are selected by URL from the [registry](crate::repository::registry).
+#[async_trait]
+pub trait Repository: Debug + Send + Sync {
+    /// Gets the name of the repository
+    fn name(&self) -> &str;
+
+    /// Gets all versions of PostgreSQL available in the repository
+    async fn get_versions(&self) -> Result<Vec<Version>>;
+
+    /// Gets the version of PostgreSQL for the specified [version](Version). If the version minor
+    /// or release is not specified, then the latest matching version is returned. If no matching
+    /// version is found, then a [ReleaseNotFound] error is returned.
+    async fn get_version(&self, version: &Version) -> Result<Version> {
+        self.get_versions()
+            .await?
+            .into_iter()
+            .filter(|available_version| version.matches(available_version))
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .ok_or_else(|| ReleaseNotFound(version.to_string()))
+    }
+
+    /// Gets the [asset](Asset) for the specified [version](Version) of PostgreSQL and
+    /// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html). If the version
+    /// minor or release is not specified, then the latest matching version is used.
+    async fn get_asset(&self, version: &Version, target: &str) -> Result<Asset>;
+
+    /// Gets the archive bytes of the [asset](Asset)
+    async fn get_archive(&self, asset: &Asset) -> Result<Bytes>;
+
+    /// Writes the archive of the [asset](Asset) to the [writer](ArchiveWriter). Repositories
+    /// override this to stream the archive instead of reading it into memory with
+    /// [get_archive](Repository::get_archive), and to resume a partially written archive. By
+    /// default the archive is written from the beginning.
+    ///
+    /// Returns the number of bytes written.
+    async fn write_archive(&self, asset: &Asset, writer: &mut ArchiveWriter) -> Result<u64> {
+        writer.restart()?;
+        let archive = self.get_archive(asset).await?;
+        writer.set_total(Some(archive.len() as u64));
+        writer.write_all(&archive)?;
+        Ok(archive.len() as u64)
+    }
+
+    /// Gets the SHA-256 hash of the archive of the [asset](Asset)
+    async fn get_hash(&self, asset: &Asset) -> Result<String>;
+
+    /// Verifies the archive of the [asset](Asset) written to the [path](Path) against the
+    /// [hash](Repository::get_hash) of the repository. Repositories that publish hashes other
+    /// than SHA-256 override this; by default the SHA-256 hash computed while the archive was
+    /// written is compared with the hash. If the hashes do not match, then an
+    /// [ArchiveHashMismatch](crate::Error::ArchiveHashMismatch) error is returned.
+    fn verify_archive(
+        &self,
+        _asset: &Asset,
+        _archive: &Path,
+        archive_hash: &str,
+        hash: &str,
+    ) -> Result<()> {
+        verify_archive_hash(archive_hash, hash)
+    }
+
+    /// Checks if the archives of the repository are stored in the [cache](crate::cache). Local
+    /// repositories return `false` since their archives are already on disk.
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    /// Checks if the archive of the [asset](Asset) must be [converted](Repository::convert_archive)
+    /// before it can be [extracted](crate::extract). Repositories that publish archives in a
+    /// container, such as the zonky jars, override this; by default archives are extracted as they
+    /// are.
+    fn converts_archive(&self, _asset: &Asset) -> bool {
+        false
+    }
+
+    /// Converts the verified archive of the [asset](Asset) read from the open [file](File) into an
+    /// archive that can be [extracted](crate::extract), written to the [writer](Write). This is only called if the repository
+    /// [converts the archive](Repository::converts_archive); by default an [Unexpected] error is
+    /// returned.
+    fn convert_archive(
+        &self,
+        asset: &Asset,
+        _archive: &File,
+        _writer: &mut dyn Write,
+    ) -> Result<()> {
+        Err(Unexpected(format!(
+            "{} does not convert archive {}",
+            self.name(),
+            asset.name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[derive(Debug)]
+    struct TestRepository;
+
+    #[async_trait]
+    impl Repository for TestRepository {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        async fn get_versions(&self) -> Result<Vec<Version>> {
+            Ok(vec![
+                Version::new(15, Some(6), Some(0)),
+                Version::new(16, Some(2), Some(0)),
+                Version::new(16, Some(1), Some(0)),
+            ])
+        }
+
+        async fn get_asset(&self, _version: &Version, _target: &str) -> Result<Asset> {
+            unimplemented!()
+        }
+
+        async fn get_archive(&self, _asset: &Asset) -> Result<Bytes> {
+            unimplemented!()
+        }
+
+        async fn get_hash(&self, _asset: &Asset) -> Result<String> {
+            unimplemented!()
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_version() -> Result<()> {
+        let repository = TestRepository;
+        assert_eq!(
+            Version::new(16, Some(2), Some(0)),
+            repository
+                .get_version(&Version::new(16, None, None))
+                .await?
+        );
+        assert_eq!(
+            Version::new(15, Some(6), Some(0)),
+            repository
+                .get_version(&Version::new(15, Some(6), None))
+                .await?
+        );
+        assert!(matches!(
+            repository.get_version(&Version::new(14, None, None)).await,
+            Err(ReleaseNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_archive() -> Result<()> {
+        let repository = TestRepository;
+        let asset = Asset {
+            version: Version::new(16, Some(2), Some(0)),
+            name: "postgresql-16.2.0-x86_64-unknown-linux-gnu.tar.gz".to_string(),
+            url: String::new(),
+            hash_url: String::new(),
+        };
+        assert!(!repository.converts_archive(&asset));
+        assert!(matches!(
+            repository.convert_archive(&asset, &tempfile::tempfile()?, &mut Vec::new()),
+            Err(Unexpected(_))
+        ));
+        Ok(())
+    }
+}
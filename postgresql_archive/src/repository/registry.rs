@@ -0,0 +1,165 @@
+use crate::error::Error::UnsupportedRepository;
+use crate::error::Result;
+use crate::repository::filesystem::Filesystem;
+use crate::repository::github::GitHub;
+use crate::repository::http::Http;
+use crate::repository::model::Repository;
+#[cfg(feature = "zonky")]
+use crate::repository::zonky::Zonky;
+use std::sync::{Arc, Mutex};
+
+/// Function that checks if a repository supports the URL
+pub type SupportsFn = dyn Fn(&str) -> Result<bool> + Send + Sync;
+
+/// Function that creates a repository for the URL
+pub type NewFn = dyn Fn(&str) -> Result<Box<dyn Repository>> + Send + Sync;
+
+lazy_static! {
+    static ref REGISTRY: Mutex<Vec<(Arc<SupportsFn>, Arc<NewFn>)>> =
+        Mutex::new(default_repositories());
+}
+
+/// Gets the repositories that are registered by default
+fn default_repositories() -> Vec<(Arc<SupportsFn>, Arc<NewFn>)> {
+    vec![
+        (Arc::new(GitHub::supports), Arc::new(GitHub::new)),
+        (Arc::new(Filesystem::supports), Arc::new(Filesystem::new)),
+        (Arc::new(Http::supports), Arc::new(Http::new)),
+        #[cfg(feature = "zonky")]
+        (Arc::new(Zonky::supports), Arc::new(Zonky::new)),
+    ]
+}
+
+/// Registers a repository. The `supports` function checks if the repository supports a URL, and
+/// the `new` function creates the repository for a supported URL. Repositories registered later
+/// take precedence over earlier ones, so a registered repository can replace a default one.
+///
+/// ```no_run
+/// use postgresql_archive::repository::registry;
+/// use postgresql_archive::repository::GitHub;
+///
+/// registry::register(
+///     |url| Ok(url.starts_with("https://artifacts.example.com/")),
+///     GitHub::new,
+/// );
+/// ```
+pub fn register<S, N>(supports: S, new: N)
+where
+    S: Fn(&str) -> Result<bool> + Send + Sync + 'static,
+    N: Fn(&str) -> Result<Box<dyn Repository>> + Send + Sync + 'static,
+{
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.push((Arc::new(supports), Arc::new(new)));
+    }
+}
+
+/// Gets the repository for the URL. If no registered repository supports the URL, then an
+/// [UnsupportedRepository] error is returned.
+pub fn get(url: &str) -> Result<Box<dyn Repository>> {
+    let repositories = match REGISTRY.lock() {
+        Ok(registry) => registry.clone(),
+        Err(_) => default_repositories(),
+    };
+
+    for (supports, new) in repositories.iter().rev() {
+        if supports(url)? {
+            return new(url);
+        }
+    }
+
+    Err(UnsupportedRepository(url.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::model::Asset;
+    use crate::Version;
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use test_log::test;
+
+    #[derive(Debug)]
+    struct TestRepository;
+
+    impl TestRepository {
+        #[allow(clippy::new_ret_no_self)]
+        fn new(_url: &str) -> Result<Box<dyn Repository>> {
+            Ok(Box::new(Self))
+        }
+    }
+
+    #[async_trait]
+    impl Repository for TestRepository {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        async fn get_versions(&self) -> Result<Vec<Version>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_asset(&self, _version: &Version, _target: &str) -> Result<Asset> {
+            unimplemented!()
+        }
+
+        async fn get_archive(&self, _asset: &Asset) -> Result<Bytes> {
+            unimplemented!()
+        }
+
+        async fn get_hash(&self, _asset: &Asset) -> Result<String> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_get_github() -> Result<()> {
+        let repository = get("https://github.com/theseus-rs/postgresql-binaries")?;
+        assert_eq!("GitHub", repository.name());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_filesystem() -> Result<()> {
+        assert_eq!("Filesystem", get("file:///opt/postgresql")?.name());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_http() -> Result<()> {
+        assert_eq!(
+            "HTTP",
+            get("https://mirror.example.com/postgresql/index.json")?.name()
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "zonky")]
+    #[test]
+    fn test_get_zonky() -> Result<()> {
+        assert_eq!(
+            "Zonky",
+            get("https://repo1.maven.org/maven2/io/zonky/test/postgres")?.name()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_register() -> Result<()> {
+        register(|url| Ok(url.starts_with("test://")), TestRepository::new);
+        assert_eq!("test", get("test://repository")?.name());
+        assert_eq!(
+            "GitHub",
+            get("https://github.com/theseus-rs/postgresql-binaries")?.name()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_unsupported() {
+        assert!(matches!(
+            get("unsupported://repository"),
+            Err(UnsupportedRepository(_))
+        ));
+    }
+}
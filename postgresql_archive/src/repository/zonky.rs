@@ -0,0 +1,607 @@
+//! Repository of PostgreSQL archives published to Maven as zonky embedded-postgres binaries
+use crate::archive::{parse_hash, reqwest_client, verify_archive_hash};
+use crate::download::{download, ArchiveWriter};
+use crate::error::Error::{AssetHashNotFound, AssetNotFound, ReleaseNotFound, Unexpected};
+use crate::error::Result;
+use crate::repository::model::{Asset, Repository};
+use crate::version::Version;
+use async_trait::async_trait;
+use bytes::Bytes;
+use human_bytes::human_bytes;
+use regex::Regex;
+use reqwest::StatusCode;
+use sha1::{Digest, Sha1};
+use std::fs::File;
+use std::io::{copy, BufReader, Read, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+use tracing::{debug, instrument};
+use url::Url;
+use xz2::write::XzEncoder;
+use zip::ZipArchive;
+
+/// URL of the zonky embedded-postgres binaries on Maven Central
+pub const ZONKY_POSTGRESQL_URL: &str = "https://repo1.maven.org/maven2/io/zonky/test/postgres";
+
+/// Path of the zonky group (`io.zonky.test.postgres`) in a Maven repository
+const ZONKY_GROUP_PATH: &str = "/io/zonky/test/postgres";
+
+/// Gets the zonky platform classifier for a
+/// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html), or `None` if zonky
+/// does not publish binaries for the target. Archives are published as the
+/// `io.zonky.test.postgres:embedded-postgres-binaries-<platform>` artifacts.
+fn zonky_platform(target: &str) -> Option<&'static str> {
+    let platform = match target {
+        "aarch64-apple-darwin" => "darwin-arm64v8",
+        "x86_64-apple-darwin" => "darwin-amd64",
+        "x86_64-pc-windows-msvc" => "windows-amd64",
+        "aarch64-unknown-linux-gnu" => "linux-arm64v8",
+        "aarch64-unknown-linux-musl" => "linux-arm64v8-alpine",
+        "arm-unknown-linux-gnueabihf" => "linux-arm32v6",
+        "arm-unknown-linux-musleabihf" => "linux-arm32v6-alpine",
+        "armv7-unknown-linux-gnueabihf" => "linux-arm32v7",
+        "armv7-unknown-linux-musleabihf" => "linux-arm32v7-alpine",
+        "i686-unknown-linux-gnu" => "linux-i386",
+        "i686-unknown-linux-musl" => "linux-i386-alpine",
+        "powerpc64le-unknown-linux-gnu" => "linux-ppc64le",
+        "powerpc64le-unknown-linux-musl" => "linux-ppc64le-alpine",
+        "x86_64-unknown-linux-gnu" => "linux-amd64",
+        "x86_64-unknown-linux-musl" => "linux-amd64-alpine",
+        _ => return None,
+    };
+    Some(platform)
+}
+
+/// Repository of PostgreSQL archives published to a Maven repository as the zonky
+/// `io.zonky.test.postgres:embedded-postgres-binaries-<platform>` jars, such as Maven Central or
+/// an internal mirror shared with JVM projects. Versions are resolved from the
+/// `maven-metadata.xml` of the artifact for the target, and each jar is verified with its
+/// `.sha256` checksum before the embedded `.txz` archive is extracted. Maven repositories are only
+/// required to publish `.sha1` checksums, so jars without a `.sha256` checksum are verified with
+/// their `.sha1` checksum instead.
+///
+/// The repository URL is the URL of the `io/zonky/test/postgres` group directory (e.g.
+/// [ZONKY_POSTGRESQL_URL]).
+#[derive(Debug)]
+pub struct Zonky {
+    url: String,
+}
+
+impl Zonky {
+    /// Checks if the URL is the URL of the zonky group directory in a Maven repository (e.g.
+    /// `https://repo1.maven.org/maven2/io/zonky/test/postgres`)
+    pub fn supports(url: &str) -> Result<bool> {
+        match Url::parse(url) {
+            Ok(url) => Ok(matches!(url.scheme(), "http" | "https")
+                && url.path().trim_end_matches('/').ends_with(ZONKY_GROUP_PATH)),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Creates a new zonky repository for the URL of the zonky group directory
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(url: &str) -> Result<Box<dyn Repository>> {
+        Ok(Box::new(Self {
+            url: url.trim_end_matches('/').to_string(),
+        }))
+    }
+
+    /// Gets the URL of the artifact directory for the
+    /// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html). If zonky does not
+    /// publish binaries for the target, then an [AssetNotFound] error is returned.
+    fn artifact_url(&self, target: &str) -> Result<(String, String)> {
+        let platform = zonky_platform(target).ok_or_else(|| AssetNotFound(target.to_string()))?;
+        let artifact_id = format!("embedded-postgres-binaries-{platform}");
+        let url = format!("{}/{artifact_id}", self.url);
+        Ok((artifact_id, url))
+    }
+
+    /// Gets the versions of the artifact for the
+    /// [target](https://doc.rust-lang.org/nightly/rustc/platform-support.html) from its
+    /// `maven-metadata.xml`
+    #[instrument(level = "debug")]
+    async fn get_target_versions(&self, target: &str) -> Result<Vec<Version>> {
+        let (_, artifact_url) = self.artifact_url(target)?;
+        let metadata_url = format!("{artifact_url}/maven-metadata.xml");
+        debug!("Downloading metadata {metadata_url}");
+        let client = reqwest_client();
+        let request = client.get(&metadata_url);
+        let response = request.send().await?.error_for_status()?;
+        let metadata = response.text().await?;
+        parse_metadata_versions(&metadata)
+    }
+}
+
+/// Parses the versions listed in a Maven `maven-metadata.xml`. Versions that are not valid
+/// PostgreSQL versions (e.g. snapshots) are skipped.
+fn parse_metadata_versions(metadata: &str) -> Result<Vec<Version>> {
+    let re = Regex::new(r"<version>\s*([^<\s]+)\s*</version>")?;
+    let mut versions: Vec<Version> = Vec::new();
+    for captures in re.captures_iter(metadata) {
+        let Ok(version) = Version::from_str(&captures[1]) else {
+            continue;
+        };
+        if version.minor.is_some() && version.release.is_some() && !versions.contains(&version) {
+            versions.push(version);
+        }
+    }
+    Ok(versions)
+}
+
+/// Parses the SHA-1 hash for the asset with the given name from the contents of a `.sha1` checksum
+/// file. If the hash is not found, then an [AssetHashNotFound] error is returned.
+fn parse_sha1_hash(text: &str, asset_name: &str) -> Result<String> {
+    let re = Regex::new(r"\b[0-9a-f]{40}\b")?;
+    match re.find(text) {
+        Some(hash) => Ok(hash.as_str().to_string()),
+        None => Err(AssetHashNotFound(asset_name.to_string())),
+    }
+}
+
+/// Extracts the `.txz` archive embedded in a zonky [jar](Read) to the [writer](Write) without
+/// decompressing it. Zonky archives contain the installation at their root rather than in a
+/// top-level directory, so the archive is preceded by an xz stream holding a single `./` directory
+/// entry; [extraction](crate::extract) keeps the entry paths of archives that start with a `./`
+/// entry.
+fn convert_jar<R: Read + Seek>(jar: R, writer: &mut dyn Write) -> Result<()> {
+    let mut zip = ZipArchive::new(jar)?;
+    let txz_name = zip
+        .file_names()
+        .find(|name| name.ends_with(".txz"))
+        .map(ToString::to_string)
+        .ok_or_else(|| Unexpected("Failed to find .txz archive in jar".to_string()))?;
+    let mut txz = zip.by_name(&txz_name)?;
+    debug!("Extracting {txz_name}: {}", human_bytes(txz.size() as f64));
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_path("./")?;
+    header.set_size(0);
+    header.set_mode(0o755);
+    header.set_cksum();
+    let mut encoder = XzEncoder::new(&mut *writer, 6);
+    encoder.write_all(header.as_bytes())?;
+    encoder.finish()?;
+
+    copy(&mut txz, writer)?;
+    Ok(())
+}
+
+#[async_trait]
+impl Repository for Zonky {
+    fn name(&self) -> &str {
+        "Zonky"
+    }
+
+    /// Gets the versions published for the current target
+    #[instrument(level = "debug")]
+    async fn get_versions(&self) -> Result<Vec<Version>> {
+        self.get_target_versions(target_triple::TARGET).await
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_asset(&self, version: &Version, target: &str) -> Result<Asset> {
+        let asset_version = self
+            .get_target_versions(target)
+            .await?
+            .into_iter()
+            .filter(|available_version| version.matches(available_version))
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .ok_or_else(|| ReleaseNotFound(version.to_string()))?;
+
+        let (artifact_id, artifact_url) = self.artifact_url(target)?;
+        let name = format!("{artifact_id}-{asset_version}.jar");
+        let url = format!("{artifact_url}/{asset_version}/{name}");
+        debug!("Asset {url} found for version {version}");
+
+        Ok(Asset {
+            version: asset_version,
+            name,
+            hash_url: format!("{url}.sha256"),
+            url,
+        })
+    }
+
+    #[instrument(level = "debug")]
+    async fn get_archive(&self, asset: &Asset) -> Result<Bytes> {
+        debug!("Downloading archive {}", asset.url);
+        let client = reqwest_client();
+        let request = client.get(&asset.url);
+        let response = request.send().await?.error_for_status()?;
+        let archive: Bytes = response.bytes().await?;
+        debug!(
+            "Archive {} downloaded: {}",
+            asset.url,
+            human_bytes(archive.len() as f64)
+        );
+        Ok(archive)
+    }
+
+    #[instrument(level = "debug", skip(writer))]
+    async fn write_archive(&self, asset: &Asset, writer: &mut ArchiveWriter) -> Result<u64> {
+        let client = reqwest_client();
+        download(&client, &asset.url, writer).await
+    }
+
+    /// Gets the SHA-256 hash of the jar from its `.sha256` checksum. If the repository does not
+    /// publish a `.sha256` checksum, then the SHA-1 hash of the jar is returned from its `.sha1`
+    /// checksum instead.
+    #[instrument(level = "debug")]
+    async fn get_hash(&self, asset: &Asset) -> Result<String> {
+        let client = reqwest_client();
+        debug!("Downloading archive hash {}", asset.hash_url);
+        let response = client.get(&asset.hash_url).send().await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            let text = response.error_for_status()?.text().await?;
+            return parse_hash(&text, &asset.name);
+        }
+
+        let sha1_url = format!("{}.sha1", asset.url);
+        debug!(
+            "Archive hash {} not found; downloading {sha1_url}",
+            asset.hash_url
+        );
+        let response = client.get(&sha1_url).send().await?.error_for_status()?;
+        let text = response.text().await?;
+        parse_sha1_hash(&text, &asset.name)
+    }
+
+    /// Verifies the jar against its SHA-1 hash if the repository only published a `.sha1`
+    /// checksum, otherwise against its SHA-256 hash
+    fn verify_archive(
+        &self,
+        _asset: &Asset,
+        archive: &Path,
+        archive_hash: &str,
+        hash: &str,
+    ) -> Result<()> {
+        if hash.len() != archive_hash.len() {
+            let mut hasher = Sha1::new();
+            copy(&mut File::open(archive)?, &mut hasher)?;
+            return verify_archive_hash(&hex::encode(hasher.finalize()), hash);
+        }
+        verify_archive_hash(archive_hash, hash)
+    }
+
+    /// Jars are not archives that can be extracted, so they are always converted
+    fn converts_archive(&self, _asset: &Asset) -> bool {
+        true
+    }
+
+    /// Extracts the `.txz` archive embedded in the jar, which is extracted as an xz compressed tar
+    /// archive
+    fn convert_archive(
+        &self,
+        _asset: &Asset,
+        archive: &File,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        let jar = BufReader::new(archive);
+        convert_jar(jar, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::{extract, get_archive_for_target_from, get_cached_archive};
+    use crate::cache::Cache;
+    use crate::Error::ArchiveHashMismatch;
+    use sha2::{Digest, Sha256};
+    use std::io::Cursor;
+    use test_log::test;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use xz2::write::XzEncoder;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+    const ARTIFACT_PATH: &str =
+        "/maven2/io/zonky/test/postgres/embedded-postgres-binaries-linux-amd64";
+    const METADATA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>io.zonky.test.postgres</groupId>
+  <artifactId>embedded-postgres-binaries-linux-amd64</artifactId>
+  <versioning>
+    <latest>16.2.0</latest>
+    <release>16.2.0</release>
+    <versions>
+      <version>15.6.0</version>
+      <version>16.1.0</version>
+      <version>16.2.0</version>
+      <version>16.2.0-SNAPSHOT</version>
+    </versions>
+  </versioning>
+</metadata>"#;
+
+    /// Create a jar containing a `.txz` archive of a minimal installation with its entries at the
+    /// root, like the zonky jars
+    fn jar() -> Result<Bytes> {
+        let mut builder = tar::Builder::new(XzEncoder::new(Vec::new(), 6));
+        for name in ["share/postgresql/postgres.bki", "bin/postgres"] {
+            let contents = name.as_bytes();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents)?;
+        }
+        let txz = builder.into_inner()?.finish()?;
+        Ok(Bytes::from(zip_jar(&txz)?.into_inner()))
+    }
+
+    /// Create a jar containing the `.txz` archive
+    fn zip_jar(txz: &[u8]) -> Result<Cursor<Vec<u8>>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("postgres-linux-x86_64.txz", SimpleFileOptions::default())?;
+        zip.write_all(txz)?;
+        Ok(zip.finish()?)
+    }
+
+    /// Start a server hosting the metadata and jar for version 16.2.0 of the linux-amd64 artifact
+    async fn server(jar: &Bytes, hash: &str) -> MockServer {
+        server_with_checksum(jar, "sha256", hash).await
+    }
+
+    /// Start a server hosting the metadata and jar for version 16.2.0 of the linux-amd64 artifact,
+    /// with the hash of the jar published as the checksum with the extension
+    async fn server_with_checksum(jar: &Bytes, extension: &str, hash: &str) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{ARTIFACT_PATH}/maven-metadata.xml")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(METADATA))
+            .mount(&server)
+            .await;
+        let jar_path =
+            format!("{ARTIFACT_PATH}/16.2.0/embedded-postgres-binaries-linux-amd64-16.2.0.jar");
+        Mock::given(method("GET"))
+            .and(path(jar_path.clone()))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jar.to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{jar_path}.{extension}")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(hash))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn url(server: &MockServer) -> String {
+        format!("{}/maven2/io/zonky/test/postgres", server.uri())
+    }
+
+    #[test]
+    fn test_supports() -> Result<()> {
+        assert!(Zonky::supports(ZONKY_POSTGRESQL_URL)?);
+        assert!(Zonky::supports(
+            "https://artifacts.example.com/maven-remote/io/zonky/test/postgres/"
+        )?);
+        assert!(!Zonky::supports("https://repo1.maven.org/maven2")?);
+        assert!(!Zonky::supports("file:///maven2/io/zonky/test/postgres")?);
+        assert!(!Zonky::supports("not a url")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_zonky_platform() {
+        assert_eq!(Some("linux-amd64"), zonky_platform(TARGET));
+        assert_eq!(
+            Some("linux-amd64-alpine"),
+            zonky_platform("x86_64-unknown-linux-musl")
+        );
+        assert_eq!(
+            Some("darwin-arm64v8"),
+            zonky_platform("aarch64-apple-darwin")
+        );
+        assert_eq!(None, zonky_platform("s390x-unknown-linux-gnu"));
+    }
+
+    #[test]
+    fn test_parse_metadata_versions() -> Result<()> {
+        assert_eq!(
+            vec![
+                Version::new(15, Some(6), Some(0)),
+                Version::new(16, Some(1), Some(0)),
+                Version::new(16, Some(2), Some(0)),
+            ],
+            parse_metadata_versions(METADATA)?
+        );
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_asset() -> Result<()> {
+        let jar = jar()?;
+        let server = server(&jar, &hex::encode(Sha256::digest(&jar))).await;
+        let repository = Zonky::new(&url(&server))?;
+
+        let asset = repository
+            .get_asset(&Version::new(16, None, None), TARGET)
+            .await?;
+        assert_eq!(Version::new(16, Some(2), Some(0)), asset.version);
+        assert_eq!(
+            "embedded-postgres-binaries-linux-amd64-16.2.0.jar",
+            asset.name
+        );
+        assert_eq!(
+            format!("{}{ARTIFACT_PATH}/16.2.0/{}", server.uri(), asset.name),
+            asset.url
+        );
+        assert_eq!(format!("{}.sha256", asset.url), asset.hash_url);
+        assert!(repository.converts_archive(&asset));
+
+        assert!(matches!(
+            repository
+                .get_asset(&Version::new(14, None, None), TARGET)
+                .await,
+            Err(ReleaseNotFound(_))
+        ));
+        assert!(matches!(
+            repository
+                .get_asset(&Version::new(16, None, None), "s390x-unknown-linux-gnu")
+                .await,
+            Err(AssetNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive() -> Result<()> {
+        let jar = jar()?;
+        let server = server(&jar, &hex::encode(Sha256::digest(&jar))).await;
+        let (version, archive) =
+            get_archive_for_target_from(&url(&server), &Version::new(16, None, None), TARGET)
+                .await?;
+        assert_eq!(Version::new(16, Some(2), Some(0)), version);
+
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path().join("16.2.0");
+        extract(&archive, &out_dir).await?;
+        assert_eq!(
+            "bin/postgres",
+            std::fs::read_to_string(out_dir.join("bin").join("postgres"))?
+        );
+        assert!(out_dir.join("share").join("postgresql").is_dir());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_hash_mismatch() -> Result<()> {
+        let jar = jar()?;
+        let server = server(&jar, &"0".repeat(64)).await;
+        let result =
+            get_archive_for_target_from(&url(&server), &Version::new(16, None, None), TARGET).await;
+        assert!(matches!(result, Err(ArchiveHashMismatch { .. })));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_sha1() -> Result<()> {
+        let jar = jar()?;
+        let hash = hex::encode(Sha1::digest(&jar));
+        let server = server_with_checksum(&jar, "sha1", &hash).await;
+        let repository = Zonky::new(&url(&server))?;
+        let asset = repository
+            .get_asset(&Version::new(16, None, None), TARGET)
+            .await?;
+        assert_eq!(hash, repository.get_hash(&asset).await?);
+
+        let (version, archive) =
+            get_archive_for_target_from(&url(&server), &Version::new(16, None, None), TARGET)
+                .await?;
+        assert_eq!(Version::new(16, Some(2), Some(0)), version);
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path().join("16.2.0");
+        extract(&archive, &out_dir).await?;
+        assert_eq!(
+            "bin/postgres",
+            std::fs::read_to_string(out_dir.join("bin").join("postgres"))?
+        );
+        assert!(out_dir.join("share").join("postgresql").is_dir());
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_get_archive_sha1_mismatch() -> Result<()> {
+        let jar = jar()?;
+        let server = server_with_checksum(&jar, "sha1", &"0".repeat(40)).await;
+        let result =
+            get_archive_for_target_from(&url(&server), &Version::new(16, None, None), TARGET).await;
+        assert!(matches!(result, Err(ArchiveHashMismatch { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_sha1_hash() -> Result<()> {
+        let hash = "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12";
+        assert_eq!(
+            hash,
+            parse_sha1_hash(&format!("{hash}  postgres.jar"), "jar")?
+        );
+        assert!(matches!(
+            parse_sha1_hash("invalid", "jar"),
+            Err(AssetHashNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test(tokio::test)]
+    async fn test_convert_jar_links() -> Result<()> {
+        let mut builder = tar::Builder::new(XzEncoder::new(Vec::new(), 6));
+        let contents = b"postgres";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(&mut header, "./bin/postgres", &contents[..])?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Link);
+        header.set_size(0);
+        header.set_mode(0o755);
+        builder.append_link(&mut header, "./bin/postmaster", "./bin/postgres")?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o755);
+        builder.append_link(&mut header, "./bin/pg", "postgres")?;
+        let txz = builder.into_inner()?.finish()?;
+
+        let mut converted = Vec::new();
+        convert_jar(zip_jar(&txz)?, &mut converted)?;
+        // The embedded archive is copied as it is, after the `./` entry
+        assert!(converted.ends_with(&txz));
+
+        let dir = tempfile::tempdir()?;
+        let out_dir = dir.path().join("16.2.0");
+        extract(&Bytes::from(converted), &out_dir).await?;
+        let bin = out_dir.join("bin");
+        assert_eq!("postgres", std::fs::read_to_string(bin.join("postgres"))?);
+        assert_eq!("postgres", std::fs::read_to_string(bin.join("postmaster"))?);
+        #[cfg(unix)]
+        assert_eq!(
+            std::path::PathBuf::from("postgres"),
+            std::fs::read_link(bin.join("pg"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_cached_archive_sha1() -> Result<()> {
+        let jar = jar()?;
+        let dir = tempfile::tempdir()?;
+        let cache = Cache::new(dir.path());
+        let version = Version::new(16, Some(2), Some(0));
+        let sha256 = hex::encode(Sha256::digest(&jar));
+        let path = cache.put(&version, TARGET, &sha256, &jar)?;
+
+        let repository = Zonky::new(ZONKY_POSTGRESQL_URL)?;
+        let asset = Asset {
+            version,
+            name: "embedded-postgres-binaries-linux-amd64-16.2.0.jar".to_string(),
+            url: String::new(),
+            hash_url: String::new(),
+        };
+        let sha1 = hex::encode(Sha1::digest(&jar));
+        let cached = get_cached_archive(&cache, &*repository, &asset, TARGET, &sha1);
+        assert_eq!(
+            Some((path, sha256)),
+            cached.map(|(path, archive_hash, _)| (path, archive_hash))
+        );
+        assert!(
+            get_cached_archive(&cache, &*repository, &asset, TARGET, &"0".repeat(40)).is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_jar_without_txz() -> Result<()> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("META-INF/MANIFEST.MF", SimpleFileOptions::default())?;
+        let jar = zip.finish()?;
+        let result = convert_jar(jar, &mut Vec::new());
+        assert!(matches!(result, Err(Unexpected(_))));
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
+use postgresql_archive::DEFAULT_POSTGRESQL_URL;
 #[allow(deprecated)]
-use postgresql_archive::{extract, Version, LATEST, V12, V13, V14, V15, V16};
-use postgresql_archive::{get_archive, get_archive_for_target, get_version};
+use postgresql_archive::{extract, extract_file, Version, LATEST, V12, V13, V14, V15, V16};
+use postgresql_archive::{
+    get_archive, get_archive_file, get_archive_for_target, get_version, get_versions,
+};
 use std::fs::{create_dir_all, remove_dir_all};
 use test_log::test;
 
@@ -59,6 +62,18 @@ async fn test_get_version() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test(tokio::test)]
+async fn test_get_versions() -> anyhow::Result<()> {
+    let version = &LATEST;
+    let versions = get_versions(DEFAULT_POSTGRESQL_URL, version).await?;
+
+    assert!(!versions.is_empty());
+    assert!(versions.iter().all(|v| v.major == version.major));
+    assert_eq!(get_version(version).await?, versions[0]);
+
+    Ok(())
+}
+
 #[test(tokio::test)]
 async fn test_get_archive_and_extract() -> anyhow::Result<()> {
     let version = &LATEST;
@@ -74,6 +89,22 @@ async fn test_get_archive_and_extract() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test(tokio::test)]
+async fn test_get_archive_file_and_extract() -> anyhow::Result<()> {
+    let version = &LATEST;
+    let archive = get_archive_file(DEFAULT_POSTGRESQL_URL, version).await?;
+
+    assert!(archive.version().matches(version));
+    assert!(archive.path().is_file());
+
+    let out_dir = tempfile::tempdir()?.path().join("postgresql");
+    extract_file(&archive, &out_dir).await?;
+    assert!(out_dir.join("bin").is_dir());
+    remove_dir_all(&out_dir)?;
+
+    Ok(())
+}
+
 #[test(tokio::test)]
 async fn test_get_archive_version_not_found() -> postgresql_archive::Result<()> {
     let invalid_version = Version::new(1, Some(0), Some(0));
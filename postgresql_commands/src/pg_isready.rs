@@ -4,6 +4,43 @@ use std::convert::AsRef;
 use std::ffi::{OsStr, OsString};
 use std::path::PathBuf;
 
+/// Connection status of the server, reported by pg_isready through its exit code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionStatus {
+    /// The server is accepting connections (exit code 0)
+    Accepting,
+    /// The server is rejecting connections, for example during startup (exit code 1)
+    Rejecting,
+    /// There was no response to the connection attempt (exit code 2)
+    NoResponse,
+    /// No attempt was made, for example due to invalid parameters (exit code 3)
+    NoAttempt,
+}
+
+impl ConnectionStatus {
+    /// Get the connection status for a pg_isready exit code, or `None` if the exit code is not
+    /// one that pg_isready returns
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(ConnectionStatus::Accepting),
+            1 => Some(ConnectionStatus::Rejecting),
+            2 => Some(ConnectionStatus::NoResponse),
+            3 => Some(ConnectionStatus::NoAttempt),
+            _ => None,
+        }
+    }
+
+    /// Get the pg_isready exit code for the connection status
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ConnectionStatus::Accepting => 0,
+            ConnectionStatus::Rejecting => 1,
+            ConnectionStatus::NoResponse => 2,
+            ConnectionStatus::NoAttempt => 3,
+        }
+    }
+}
+
 /// pg_isready issues a connection check to a PostgreSQL database.
 #[derive(Clone, Debug, Default)]
 pub struct PgIsReadyBuilder {
@@ -151,6 +188,23 @@ mod tests {
     use crate::TestSettings;
     use test_log::test;
 
+    #[test]
+    fn test_connection_status() {
+        for status in [
+            ConnectionStatus::Accepting,
+            ConnectionStatus::Rejecting,
+            ConnectionStatus::NoResponse,
+            ConnectionStatus::NoAttempt,
+        ] {
+            assert_eq!(
+                Some(status),
+                ConnectionStatus::from_exit_code(status.exit_code())
+            );
+        }
+        assert_eq!(None, ConnectionStatus::from_exit_code(4));
+        assert_eq!(None, ConnectionStatus::from_exit_code(-1));
+    }
+
     #[test]
     fn test_builder_new() {
         let command = PgIsReadyBuilder::new().program_dir(".").build();
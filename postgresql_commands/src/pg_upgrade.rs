@@ -28,6 +28,7 @@ pub struct PgUpgradeBuilder {
     clone: bool,
     copy: bool,
     help: bool,
+    pg_password: Option<OsString>,
 }
 
 impl PgUpgradeBuilder {
@@ -166,6 +167,12 @@ impl PgUpgradeBuilder {
         self.help = true;
         self
     }
+
+    /// user password
+    pub fn pg_password<S: AsRef<OsStr>>(mut self, pg_password: S) -> Self {
+        self.pg_password = Some(pg_password.as_ref().to_os_string());
+        self
+    }
 }
 
 impl CommandBuilder for PgUpgradeBuilder {
@@ -276,6 +283,17 @@ impl CommandBuilder for PgUpgradeBuilder {
 
         args
     }
+
+    /// Get the environment variables for the command
+    fn get_envs(&self) -> Vec<(OsString, OsString)> {
+        let mut envs: Vec<(OsString, OsString)> = Vec::new();
+
+        if let Some(password) = &self.pg_password {
+            envs.push(("PGPASSWORD".into(), password.into()));
+        }
+
+        envs
+    }
 }
 
 #[cfg(test)]
@@ -323,10 +341,11 @@ mod tests {
             .clone()
             .copy()
             .help()
+            .pg_password("password")
             .build();
 
         assert_eq!(
-            r#""pg_upgrade" "--old-bindir" "old" "--new-bindir" "new" "--check" "--old-datadir" "old_data" "--new-datadir" "new_data" "--jobs" "10" "--link" "--no-sync" "--old-options" "old" "--new-options" "new" "--old-port" "5432" "--new-port" "5433" "--retain" "--socketdir" "socket" "--username" "user" "--verbose" "--version" "--clone" "--copy" "--help""#,
+            r#"PGPASSWORD="password" "pg_upgrade" "--old-bindir" "old" "--new-bindir" "new" "--check" "--old-datadir" "old_data" "--new-datadir" "new_data" "--jobs" "10" "--link" "--no-sync" "--old-options" "old" "--new-options" "new" "--old-port" "5432" "--new-port" "5433" "--retain" "--socketdir" "socket" "--username" "user" "--verbose" "--version" "--clone" "--copy" "--help""#,
             command.to_command_string()
         );
     }
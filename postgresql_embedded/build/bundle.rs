@@ -1,8 +1,8 @@
 #![allow(dead_code)]
 
 use anyhow::Result;
-use postgresql_archive::get_archive;
-use postgresql_archive::{Version, LATEST};
+use postgresql_archive::get_archive_from;
+use postgresql_archive::{Version, DEFAULT_POSTGRESQL_URL, LATEST};
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
@@ -14,6 +14,11 @@ use std::{env, fs};
 /// self-contained binary that does not require the PostgreSQL archive to be
 /// downloaded at runtime.
 pub(crate) async fn stage_postgresql_archive() -> Result<()> {
+    println!("cargo:rerun-if-env-changed=POSTGRESQL_RELEASES_URL");
+    println!("cargo:rerun-if-env-changed=POSTGRESQL_VERSION");
+    let releases_url =
+        env::var("POSTGRESQL_RELEASES_URL").unwrap_or(DEFAULT_POSTGRESQL_URL.to_string());
+    println!("PostgreSQL releases URL: {releases_url}");
     let postgres_version = env::var("POSTGRESQL_VERSION").unwrap_or(LATEST.to_string());
     let version = Version::from_str(postgres_version.as_str())?;
     println!("PostgreSQL version: {postgres_version}");
@@ -25,15 +30,22 @@ pub(crate) async fn stage_postgresql_archive() -> Result<()> {
     archive_version_file.push("postgresql.version");
     let mut archive_file = out_dir.clone();
     archive_file.push("postgresql.tar.gz");
-
-    if archive_version_file.exists() && archive_file.exists() {
+    let mut archive_source_file = out_dir.clone();
+    archive_source_file.push("postgresql.source");
+    let archive_source = format!("{releases_url}\n{postgres_version}\n");
+
+    if archive_version_file.exists()
+        && archive_file.exists()
+        && fs::read_to_string(&archive_source_file).ok().as_deref() == Some(archive_source.as_str())
+    {
         println!("PostgreSQL archive exists: {:?}", archive_file);
         return Ok(());
     }
 
-    let (asset_version, archive) = get_archive(&version).await?;
+    let (asset_version, archive) = get_archive_from(&releases_url, &version).await?;
 
     fs::write(archive_version_file.clone(), asset_version.to_string())?;
+    fs::write(archive_source_file, archive_source)?;
     let mut file = File::create(archive_file.clone())?;
     file.write_all(&archive)?;
     file.sync_data()?;
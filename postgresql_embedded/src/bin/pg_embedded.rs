@@ -0,0 +1,538 @@
+//! Command line interface for managing embedded PostgreSQL instances.
+//!
+//! The instance is identified by its data directory, so the same installation and data
+//! directory can be managed across invocations, and shared with Rust services using
+//! [`PostgreSQL`].
+
+#![forbid(unsafe_code)]
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use postgresql_archive::{get_versions, Version};
+use postgresql_commands::pg_dump::PgDumpBuilder;
+use postgresql_commands::pg_restore::PgRestoreBuilder;
+use postgresql_commands::psql::PsqlBuilder;
+use postgresql_commands::CommandBuilder;
+use postgresql_embedded::{PostgreSQL, Progress, ProgressEvent, Settings, Status};
+use std::fs::{read_dir, read_to_string, remove_dir_all, remove_file};
+use std::io::ErrorKind;
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Install and manage embedded PostgreSQL instances
+#[derive(Debug, Parser)]
+#[command(name = "pg-embedded", version)]
+struct Cli {
+    /// Settings file (TOML, JSON or YAML)
+    #[arg(long, global = true, env = "PG_EMBEDDED_CONFIG")]
+    config: Option<PathBuf>,
+    /// Settings URL (e.g. postgresql://postgres@localhost:5432?installation_dir=/opt/postgresql)
+    #[arg(long, global = true, env = "PG_EMBEDDED_URL")]
+    url: Option<String>,
+    /// Data directory of the instance [default: <installation_dir>/data]
+    #[arg(long, global = true, env = "PG_EMBEDDED_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// PostgreSQL version of the instance [default: the version of the data directory, or the
+    /// latest installed version]
+    #[arg(long = "pg-version", global = true, env = "PG_EMBEDDED_VERSION")]
+    pg_version: Option<Version>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Install a PostgreSQL version
+    Install {
+        /// Version to install (e.g. 16, 16.2 or 16.2.0)
+        version: Version,
+    },
+    /// List the installed PostgreSQL versions
+    ListVersions {
+        /// List the versions available to install instead
+        #[arg(long)]
+        remote: bool,
+        /// Only list versions matching this version (e.g. 16)
+        version: Option<Version>,
+    },
+    /// Install PostgreSQL and initialize the data directory
+    Init,
+    /// Start the server, initializing the data directory if needed
+    Start {
+        /// Port to listen on [default: a random available port]
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Stop the server
+    Stop,
+    /// Show the status of the instance
+    Status,
+    /// Print the connection URL of the running server
+    Url {
+        /// Database name
+        #[arg(default_value = "postgres")]
+        database: String,
+    },
+    /// Run psql against the running server
+    Psql {
+        /// Database name
+        #[arg(default_value = "postgres")]
+        database: String,
+        /// Additional arguments passed to psql
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Dump a database of the running server to a file in the pg_dump custom format
+    Dump {
+        /// Database name
+        database: String,
+        /// Dump file
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// Restore a database of the running server from a pg_dump file
+    Restore {
+        /// Database name; the database is created if it does not exist
+        database: String,
+        /// Dump file
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// Stop the server and remove the data directory
+    Clean {
+        /// Also remove all installed PostgreSQL versions
+        #[arg(long)]
+        installations: bool,
+        /// Also remove the cache of downloaded archives
+        #[arg(long)]
+        cache: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let settings = settings(&cli)?;
+    let version = match cli.pg_version {
+        Some(version) => version,
+        None => default_version(&settings),
+    };
+
+    match cli.command {
+        Command::Install { version } => {
+            let mut postgresql = postgresql(version, settings);
+            postgresql.install().await?;
+            println!(
+                "Installed PostgreSQL {} to {}",
+                postgresql.version(),
+                postgresql.settings().installation_dir.to_string_lossy()
+            );
+        }
+        Command::ListVersions { remote, version } => {
+            let versions = if remote {
+                let version = version.unwrap_or(PostgreSQL::default_version());
+                get_versions(&settings.releases_url, &version).await?
+            } else {
+                installed_versions(&settings.installation_dir)
+                    .into_iter()
+                    .filter(|installed| match &version {
+                        Some(version) => version.matches(installed),
+                        None => true,
+                    })
+                    .collect()
+            };
+            for version in versions {
+                println!("{version}");
+            }
+        }
+        Command::Init => {
+            let mut postgresql = postgresql(version, settings);
+            postgresql.setup().await?;
+            println!(
+                "Initialized PostgreSQL {} data directory {}",
+                postgresql.version(),
+                postgresql.settings().data_dir.to_string_lossy()
+            );
+        }
+        Command::Start { port } => {
+            let mut settings = settings;
+            if let Some(port) = port {
+                settings.port = port;
+            }
+            let mut postgresql = postgresql(version, settings);
+            if postgresql.status() == Status::Started {
+                println!("Server is already running");
+            } else {
+                postgresql.setup().await?;
+                postgresql.start().await?;
+            }
+            println!("{}", postgresql.settings().url("postgres"));
+        }
+        Command::Stop => {
+            let postgresql = postgresql(version, settings);
+            if postgresql.status() == Status::Started {
+                postgresql.stop().await?;
+                println!("Server stopped");
+            } else {
+                println!("Server is not running");
+            }
+        }
+        Command::Status => {
+            let postgresql = postgresql(version, settings);
+            let status = postgresql.status();
+            println!("status: {status:?}");
+            println!("version: {}", postgresql.version());
+            println!(
+                "installation directory: {}",
+                postgresql.settings().installation_dir.to_string_lossy()
+            );
+            println!(
+                "data directory: {}",
+                postgresql.settings().data_dir.to_string_lossy()
+            );
+            if status == Status::Started {
+                println!("url: {}", postgresql.settings().url("postgres"));
+            }
+        }
+        Command::Url { database } => {
+            let postgresql = started(version, settings)?;
+            println!("{}", postgresql.settings().url(database));
+        }
+        Command::Psql { database, args } => {
+            let postgresql = started(version, settings)?;
+            let mut psql = PsqlBuilder::from(postgresql.settings())
+                .dbname(database)
+                .build();
+            let status = psql.args(args).status()?;
+            exit(status.code().unwrap_or(1));
+        }
+        Command::Dump { database, file } => {
+            let postgresql = started(version, settings)?;
+            let status = PgDumpBuilder::from(postgresql.settings())
+                .dbname(&database)
+                .format("custom")
+                .file(&file)
+                .build()
+                .status()?;
+            if !status.success() {
+                bail!("Failed to dump database {database}");
+            }
+            println!("Dumped database {database} to {}", file.to_string_lossy());
+        }
+        Command::Restore { database, file } => {
+            let postgresql = started(version, settings)?;
+            if !postgresql.database_exists(&database).await? {
+                postgresql.create_database(&database).await?;
+            }
+            let status = PgRestoreBuilder::from(postgresql.settings())
+                .dbname(&database)
+                .no_owner()
+                .build()
+                .arg(&file)
+                .status()?;
+            if !status.success() {
+                bail!("Failed to restore database {database}");
+            }
+            println!(
+                "Restored database {database} from {}",
+                file.to_string_lossy()
+            );
+        }
+        Command::Clean {
+            installations,
+            cache,
+        } => {
+            let installation_dir = settings.installation_dir.clone();
+            let postgresql = postgresql(version, settings);
+            if postgresql.status() == Status::Started {
+                postgresql.stop().await?;
+            }
+            let settings = postgresql.settings();
+            if settings.data_dir.exists() {
+                remove_dir_all(&settings.data_dir)?;
+                println!("Removed {}", settings.data_dir.to_string_lossy());
+            }
+            if settings.password_file.exists() {
+                remove_file(&settings.password_file)?;
+            }
+            if installations && installation_dir.exists() {
+                remove_dir_all(&installation_dir)?;
+                println!("Removed {}", installation_dir.to_string_lossy());
+            }
+            if let Some(archive_cache) =
+                cache.then(postgresql_archive::cache::get_default).flatten()
+            {
+                archive_cache.clean()?;
+                println!("Removed {}", archive_cache.dir().to_string_lossy());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the settings from the settings file, environment variables and URL. The data directory
+/// and password file are persistent, and the port of a running server is read from its
+/// `postmaster.pid` file so that the instance can be managed across invocations. Every command
+/// other than `clean` requires the password file of an initialized data directory.
+fn settings(cli: &Cli) -> Result<Settings> {
+    let mut builder = Settings::builder();
+    if let Some(config) = &cli.config {
+        builder = builder.file(config);
+    }
+    builder = builder.env();
+    if let Some(url) = &cli.url {
+        builder = builder.url(url);
+    }
+
+    let mut settings = builder.build()?;
+    settings.data_dir = match &cli.data_dir {
+        Some(data_dir) => data_dir.clone(),
+        None => settings.installation_dir.join("data"),
+    };
+    settings.password_file = password_file(&settings.data_dir);
+    settings.temporary = false;
+    if !matches!(cli.command, Command::Clean { .. }) {
+        read_password(&mut settings)?;
+    }
+    if let Some(port) = running_port(&settings.data_dir) {
+        settings.port = port;
+    }
+    Ok(settings)
+}
+
+/// Create the [`PostgreSQL`] instance. The server is managed across invocations, so it must not
+/// be stopped when the instance is dropped.
+fn postgresql(version: Version, settings: Settings) -> ManuallyDrop<PostgreSQL> {
+    let mut postgresql = PostgreSQL::new(version, settings);
+    postgresql.set_progress(progress());
+    ManuallyDrop::new(postgresql)
+}
+
+/// Create a [`Progress`] that prints the download and extraction progress to stderr; downloading
+/// and extracting are updated in place on a single line
+fn progress() -> Progress {
+    let in_place = AtomicBool::new(false);
+    Progress::new(move |event| {
+        let line = match event {
+            ProgressEvent::Resolving => Some("Resolving PostgreSQL version".to_string()),
+            ProgressEvent::Downloading {
+                bytes,
+                total: Some(total),
+            } => Some(format!(
+                "Downloading {} of {}",
+                mebibytes(bytes),
+                mebibytes(total)
+            )),
+            ProgressEvent::Downloading { bytes, total: None } => {
+                Some(format!("Downloading {}", mebibytes(bytes)))
+            }
+            ProgressEvent::Verifying => Some("Verifying archive".to_string()),
+            ProgressEvent::Extracting { files, bytes } => {
+                Some(format!("Extracting {files} files ({})", mebibytes(bytes)))
+            }
+            ProgressEvent::Done => None,
+        };
+
+        if matches!(
+            event,
+            ProgressEvent::Downloading { .. } | ProgressEvent::Extracting { .. }
+        ) {
+            eprint!("\r{}", line.unwrap_or_default());
+            in_place.store(true, Ordering::Relaxed);
+            return;
+        }
+        if in_place.swap(false, Ordering::Relaxed) {
+            eprintln!();
+        }
+        if let Some(line) = line {
+            eprintln!("{line}");
+        }
+    })
+}
+
+/// Format the bytes in mebibytes
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Create the [`PostgreSQL`] instance, returning an error if the server is not running
+fn started(version: Version, settings: Settings) -> Result<ManuallyDrop<PostgreSQL>> {
+    let postgresql = postgresql(version, settings);
+    if postgresql.status() != Status::Started {
+        bail!("Server is not running; start it with `pg-embedded start`");
+    }
+    Ok(postgresql)
+}
+
+/// Get the version of the data directory if it is initialized, otherwise the latest installed
+/// version, otherwise the default version. A major version is resolved to the latest installed
+/// version matching it.
+fn default_version(settings: &Settings) -> Version {
+    let installed_versions = installed_versions(&settings.installation_dir);
+    let data_version = read_to_string(settings.data_dir.join("PG_VERSION"))
+        .ok()
+        .and_then(|version| Version::from_str(version.trim()).ok());
+
+    match data_version {
+        Some(data_version) => installed_versions
+            .into_iter()
+            .find(|installed| data_version.matches(installed))
+            .unwrap_or(data_version),
+        None => installed_versions
+            .into_iter()
+            .next()
+            .unwrap_or(PostgreSQL::default_version()),
+    }
+}
+
+/// Get the fully qualified versions installed in the installation directory, newest first
+fn installed_versions(installation_dir: &Path) -> Vec<Version> {
+    let Ok(entries) = read_dir(installation_dir) else {
+        return Vec::new();
+    };
+    let mut versions: Vec<Version> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| Version::from_str(&entry.file_name().to_string_lossy()).ok())
+        .filter(|version| version.minor.is_some() && version.release.is_some())
+        .collect();
+    versions.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    versions
+}
+
+/// Get the password file stored next to the data directory
+fn password_file(data_dir: &Path) -> PathBuf {
+    let name = data_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "data".to_string());
+    data_dir.with_file_name(format!("{name}.pgpass"))
+}
+
+/// Read the password from the password file. If the data directory has been initialized but the
+/// password file is missing, an error is returned, since the password of the server is unknown.
+fn read_password(settings: &mut Settings) -> Result<()> {
+    match read_to_string(&settings.password_file) {
+        Ok(password) => settings.password = password,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            if settings.data_dir.join("PG_VERSION").exists() {
+                bail!(
+                    "Password file {} of the data directory {} is missing; restore it or run \
+                     `pg_embedded clean` to remove the data directory",
+                    settings.password_file.to_string_lossy(),
+                    settings.data_dir.to_string_lossy()
+                );
+            }
+        }
+        Err(error) => return Err(error.into()),
+    }
+    Ok(())
+}
+
+/// Get the port of the server running in the data directory from the `postmaster.pid` file
+fn running_port(data_dir: &Path) -> Option<u16> {
+    let pid_file = read_to_string(data_dir.join("postmaster.pid")).ok()?;
+    pid_file.lines().nth(3)?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use std::fs::{create_dir_all, write};
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_installed_versions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["15.6.0", "16.2.0", "16.10.0", "data", "16"] {
+            create_dir_all(dir.path().join(name))?;
+        }
+        write(dir.path().join("17.0.0"), "")?;
+
+        let versions = installed_versions(dir.path());
+        assert_eq!(
+            vec![
+                Version::new(16, Some(10), Some(0)),
+                Version::new(16, Some(2), Some(0)),
+                Version::new(15, Some(6), Some(0)),
+            ],
+            versions
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_version() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        create_dir_all(dir.path().join("15.6.0"))?;
+        create_dir_all(dir.path().join("16.2.0"))?;
+        let settings = Settings {
+            installation_dir: dir.path().to_path_buf(),
+            data_dir: dir.path().join("data"),
+            ..Default::default()
+        };
+        assert_eq!(
+            Version::new(16, Some(2), Some(0)),
+            default_version(&settings)
+        );
+
+        create_dir_all(&settings.data_dir)?;
+        write(settings.data_dir.join("PG_VERSION"), "15\n")?;
+        assert_eq!(
+            Version::new(15, Some(6), Some(0)),
+            default_version(&settings)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_file() {
+        assert_eq!(
+            PathBuf::from("/var/lib/postgresql/data.pgpass"),
+            password_file(Path::new("/var/lib/postgresql/data"))
+        );
+    }
+
+    #[test]
+    fn test_read_password() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut settings = Settings {
+            data_dir: dir.path().join("data"),
+            password_file: password_file(&dir.path().join("data")),
+            password: "random".to_string(),
+            ..Default::default()
+        };
+        read_password(&mut settings)?;
+        assert_eq!("random", settings.password);
+
+        create_dir_all(&settings.data_dir)?;
+        write(settings.data_dir.join("PG_VERSION"), "16\n")?;
+        assert!(read_password(&mut settings).is_err());
+
+        write(&settings.password_file, "secret")?;
+        read_password(&mut settings)?;
+        assert_eq!("secret", settings.password);
+        Ok(())
+    }
+
+    #[test]
+    fn test_running_port() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert_eq!(None, running_port(dir.path()));
+        write(
+            dir.path().join("postmaster.pid"),
+            "1234\n/data\n1700000000\n5433\n/tmp\nlocalhost\n",
+        )?;
+        assert_eq!(Some(5433), running_port(dir.path()));
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! Lifecycle hooks for the blocking API
+
+use crate::blocking::PostgreSQL;
+
+/// Custom code run at a [lifecycle event](crate::HookEvent) of a blocking [PostgreSQL] server. If
+/// the hook returns an error, the operation that triggered the event fails with a
+/// [HookError](crate::Error::HookError).
+///
+/// The hook receives the blocking server, so it can call the blocking methods of the server
+/// without a runtime of its own. Closures taking a reference to the server are hooks:
+///
+/// ```no_run
+/// use postgresql_embedded::blocking::PostgreSQL;
+/// use postgresql_embedded::HookEvent;
+///
+/// let mut postgresql = PostgreSQL::default();
+/// postgresql.add_hook(HookEvent::AfterStart, |postgresql: &PostgreSQL| {
+///     postgresql.create_database("app")?;
+///     Ok(())
+/// });
+/// ```
+pub trait Hook: Send + Sync {
+    /// Run the hook
+    fn run(&self, postgresql: &PostgreSQL) -> anyhow::Result<()>;
+}
+
+impl<F> Hook for F
+where
+    F: Fn(&PostgreSQL) -> anyhow::Result<()> + Send + Sync,
+{
+    fn run(&self, postgresql: &PostgreSQL) -> anyhow::Result<()> {
+        self(postgresql)
+    }
+}
+
+/// Adapts a blocking [Hook] to the [Hook](crate::Hook) run by the asynchronous server
+pub(crate) struct BlockingHook<H> {
+    hook: H,
+}
+
+impl<H: Hook> BlockingHook<H> {
+    /// Create a new [BlockingHook] for the blocking hook
+    pub(crate) fn new(hook: H) -> Self {
+        Self { hook }
+    }
+}
+
+#[async_trait::async_trait]
+impl<H: Hook> crate::Hook for BlockingHook<H> {
+    async fn run(&self, postgresql: &crate::postgresql::PostgreSQL) -> anyhow::Result<()> {
+        let postgresql = PostgreSQL::from_handle(postgresql.handle());
+        // Hooks run while the blocking server is blocked on the runtime; leaving the runtime lets
+        // the hook call the blocking methods of the server
+        tokio::task::block_in_place(|| self.hook.run(&postgresql))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocking::RUNTIME;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_blocking_hook() -> anyhow::Result<()> {
+        let postgresql = crate::postgresql::PostgreSQL::default();
+        // The temporary data directory is only created by setup
+        std::fs::create_dir_all(&postgresql.settings().data_dir)?;
+        let ran = Arc::new(AtomicBool::new(false));
+        let hook_ran = ran.clone();
+        let hook = BlockingHook::new(move |postgresql: &PostgreSQL| {
+            // Blocking methods can be called from the hook
+            let _ = postgresql.database_exists("test");
+            hook_ran.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+
+        RUNTIME
+            .handle()
+            .block_on(async { crate::Hook::run(&hook, &postgresql).await })?;
+
+        assert!(ran.load(Ordering::SeqCst));
+        // Dropping the handle passed to the hook leaves the temporary data directory in place
+        assert!(postgresql.settings().data_dir.exists());
+        Ok(())
+    }
+}
@@ -1,6 +1,18 @@
-use crate::{Result, Settings, Status};
+mod hook;
+
+use crate::blocking::hook::BlockingHook;
+pub use crate::blocking::hook::Hook;
+pub use crate::SignalGuard;
+use crate::{
+    Backoff, ConnectionStatus, HookEvent, Migration, Migrations, Progress, Result, Settings, Stats,
+    Status, UpgradeMode,
+};
 use lazy_static::lazy_static;
 use postgresql_archive::Version;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::Path;
+use std::time::Duration;
 use tokio::runtime::Runtime;
 
 lazy_static! {
@@ -22,6 +34,11 @@ impl PostgreSQL {
         }
     }
 
+    /// Create a blocking server for a [handle](crate::postgresql::PostgreSQL::handle) to a server
+    fn from_handle(inner: crate::postgresql::PostgreSQL) -> Self {
+        Self { inner }
+    }
+
     /// Get the [status](Status) of the PostgreSQL server
     pub fn status(&self) -> Status {
         self.inner.status()
@@ -37,6 +54,26 @@ impl PostgreSQL {
         self.inner.settings()
     }
 
+    /// Add a [hook](Hook) run at the given [lifecycle event](HookEvent). Hooks for the same event
+    /// are run in the order they were added; if a hook fails, the operation that triggered the
+    /// event fails with a [HookError](crate::Error::HookError).
+    pub fn add_hook<H: Hook + 'static>(&mut self, event: HookEvent, hook: H) {
+        self.inner.add_hook(event, BlockingHook::new(hook))
+    }
+
+    /// Apply the [migrations](Migrations) to the database with the given name each time the server
+    /// is started, as an [AfterStart](HookEvent::AfterStart) hook.
+    pub fn add_migrations<S: Into<String>>(&mut self, database_name: S, migrations: Migrations) {
+        self.inner.add_migrations(database_name, migrations)
+    }
+
+    /// Set the [progress](Progress) that [events](crate::ProgressEvent) are reported to while the
+    /// archive is downloaded and extracted by [setup](PostgreSQL::setup) or
+    /// [install](PostgreSQL::install), either a callback or the sending half of a channel.
+    pub fn set_progress<P: Into<Progress>>(&mut self, progress: P) {
+        self.inner.set_progress(progress)
+    }
+
     /// Set up the database by extracting the archive and initializing the database.
     /// If the installation directory already exists, the archive will not be extracted.
     /// If the data directory already exists, the database will not be initialized.
@@ -46,6 +83,15 @@ impl PostgreSQL {
             .block_on(async move { self.inner.setup().await })
     }
 
+    /// Install the PostgreSQL server from the archive. If the version minor and/or release are not
+    /// set, the latest version will be determined dynamically during the installation process. If
+    /// the installation directory already exists, the archive will not be extracted.
+    pub fn install(&mut self) -> Result<()> {
+        RUNTIME
+            .handle()
+            .block_on(async move { self.inner.install().await })
+    }
+
     /// Start the database and wait for the startup to complete.
     /// If the port is set to `0`, the database will be started on a random port.
     pub fn start(&mut self) -> Result<()> {
@@ -61,6 +107,42 @@ impl PostgreSQL {
             .block_on(async move { self.inner.stop().await })
     }
 
+    /// Upgrade the data directory to the given [version](Version).
+    ///
+    /// The new version is installed and, when the major version changes, a new cluster is
+    /// initialized with the same settings and the data is migrated with `pg_upgrade` using the
+    /// given [mode](UpgradeMode). If the upgrade fails, the original data directory is left in place.
+    pub fn upgrade_to(&mut self, version: Version, mode: UpgradeMode) -> Result<()> {
+        RUNTIME
+            .handle()
+            .block_on(async move { self.inner.upgrade_to(version, mode).await })
+    }
+
+    /// Check whether the server is accepting connections with `pg_isready`.
+    pub fn health(&self) -> Result<ConnectionStatus> {
+        RUNTIME
+            .handle()
+            .block_on(async move { self.inner.health().await })
+    }
+
+    /// Wait until the server is accepting connections, checking with `pg_isready` using the
+    /// [default backoff](Backoff::default).
+    pub fn wait_until_ready(&self, timeout: Duration) -> Result<()> {
+        RUNTIME
+            .handle()
+            .block_on(async move { self.inner.wait_until_ready(timeout).await })
+    }
+
+    /// Wait until the server is accepting connections, checking with `pg_isready` using the
+    /// given [backoff](Backoff) between checks.
+    pub fn wait_until_ready_with_backoff(&self, timeout: Duration, backoff: Backoff) -> Result<()> {
+        RUNTIME.handle().block_on(async move {
+            self.inner
+                .wait_until_ready_with_backoff(timeout, backoff)
+                .await
+        })
+    }
+
     /// Create a new database with the given name.
     pub fn create_database<S: AsRef<str>>(&self, database_name: S) -> Result<()> {
         RUNTIME
@@ -81,6 +163,60 @@ impl PostgreSQL {
             .handle()
             .block_on(async move { self.inner.drop_database(database_name).await })
     }
+
+    /// Write a connection service entry with the given name for the server to the connection
+    /// service file at the given path, and the password to a `.pgpass` password file in the same
+    /// directory. Returns the environment variables that point libpq clients at the server.
+    pub fn write_service_file<P: AsRef<Path> + Debug>(
+        &self,
+        path: P,
+        service_name: &str,
+    ) -> Result<HashMap<String, String>> {
+        self.inner.write_service_file(path, service_name)
+    }
+
+    /// Get a snapshot of the runtime statistics of the server.
+    pub fn stats(&self) -> Result<Stats> {
+        RUNTIME
+            .handle()
+            .block_on(async move { self.inner.stats().await })
+    }
+
+    /// Install an extension from the repository at the given URL into the installation directory.
+    pub fn install_extension(&self, url: &str, name: &str, extension_version: &str) -> Result<()> {
+        RUNTIME.handle().block_on(async move {
+            self.inner
+                .install_extension(url, name, extension_version)
+                .await
+        })
+    }
+
+    /// Create an extension in the database with the given name. If the extension version is not
+    /// specified, the default version of the extension is created.
+    pub fn create_extension<S: AsRef<str>>(
+        &self,
+        database_name: S,
+        name: &str,
+        extension_version: Option<&str>,
+    ) -> Result<()> {
+        RUNTIME.handle().block_on(async move {
+            self.inner
+                .create_extension(database_name, name, extension_version)
+                .await
+        })
+    }
+
+    /// Apply the migrations to the database with the given name. Returns the migrations that were
+    /// applied.
+    pub fn migrate<S: AsRef<str>>(
+        &self,
+        database_name: S,
+        migrations: &Migrations,
+    ) -> Result<Vec<Migration>> {
+        RUNTIME
+            .handle()
+            .block_on(async move { self.inner.migrate(database_name, migrations).await })
+    }
 }
 
 #[cfg(test)]
@@ -95,4 +231,10 @@ mod test {
         assert!(initial_statuses.contains(&postgresql.status()));
         assert_eq!(postgresql.version(), &version);
     }
+
+    #[test]
+    fn test_signal_guard() -> Result<()> {
+        let _guard = SignalGuard::install()?;
+        Ok(())
+    }
 }
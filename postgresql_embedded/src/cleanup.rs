@@ -0,0 +1,317 @@
+//! Clean up orphaned temporary servers and data directories
+
+use crate::error::Result;
+use crate::settings::Settings;
+use postgresql_commands::pg_ctl::Mode::Stop;
+use postgresql_commands::pg_ctl::PgCtlBuilder;
+use postgresql_commands::pg_ctl::ShutdownMode::Immediate;
+use postgresql_commands::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use std::fs::{read_dir, read_to_string, remove_dir, remove_dir_all, remove_file, write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use sysinfo::{Pid, System};
+use tracing::{debug, warn};
+
+/// Name of the sentinel file written to temporary data directories
+pub(crate) const SENTINEL_FILE_NAME: &str = ".postgresql_embedded.json";
+
+/// The default minimum age of a data directory before it is removed
+pub const DEFAULT_CLEANUP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Sentinel written to temporary data directories to record the process that owns the server
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Sentinel {
+    /// Process id of the owning process
+    pid: u32,
+    /// PostgreSQL installation directory used to stop the server
+    installation_dir: PathBuf,
+    /// PostgreSQL password file
+    password_file: PathBuf,
+}
+
+/// Write the sentinel file to the data directory of a temporary server, recording the current
+/// process as the owner
+pub(crate) fn write_sentinel(settings: &Settings) -> Result<()> {
+    if !settings.temporary {
+        return Ok(());
+    }
+
+    let sentinel = Sentinel {
+        pid: std::process::id(),
+        installation_dir: settings.installation_dir.clone(),
+        password_file: settings.password_file.clone(),
+    };
+    let contents = serde_json::to_string(&sentinel).map_err(std::io::Error::from)?;
+    write(settings.data_dir.join(SENTINEL_FILE_NAME), contents)?;
+    Ok(())
+}
+
+/// Options for [cleanup]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CleanupOptions {
+    temp_dir: PathBuf,
+    older_than: Duration,
+    dry_run: bool,
+}
+
+impl CleanupOptions {
+    /// Create new [CleanupOptions] that scan the system temporary directory
+    pub fn new() -> Self {
+        Self {
+            temp_dir: std::env::temp_dir(),
+            older_than: DEFAULT_CLEANUP_AGE,
+            dry_run: false,
+        }
+    }
+
+    /// Directory to scan for data directories; defaults to the system temporary directory
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.temp_dir = temp_dir.into();
+        self
+    }
+
+    /// Minimum age of a data directory before it is removed; defaults to one hour
+    pub fn older_than(mut self, older_than: Duration) -> Self {
+        self.older_than = older_than;
+        self
+    }
+
+    /// Report what would be stopped and removed without changing anything
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// Default implementation for [`CleanupOptions`]
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Result of [cleanup]; for a dry run, what would have been done
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CleanupReport {
+    /// Data directories of the servers that were stopped
+    pub stopped: Vec<PathBuf>,
+    /// Data directories that were removed
+    pub removed: Vec<PathBuf>,
+}
+
+/// Clean up temporary servers left behind by processes that exited without stopping them, such
+/// as after a crash.
+///
+/// Data directories of temporary servers are marked with a sentinel file when they are
+/// initialized, recording the process that owns the server. The temporary directory is scanned
+/// for these data directories and, when the owning process is no longer running, the server is
+/// stopped in immediate mode. Data directories whose sentinel is older than the
+/// [threshold](CleanupOptions::older_than) are then removed along with their password files.
+/// Servers owned by a running process are never touched.
+pub fn cleanup(options: &CleanupOptions) -> Result<CleanupReport> {
+    let mut report = CleanupReport::default();
+    let mut system = System::new();
+
+    for entry in read_dir(&options.temp_dir)? {
+        let data_dir = entry?.path();
+        let sentinel_file = data_dir.join(SENTINEL_FILE_NAME);
+        let sentinel = match read_to_string(&sentinel_file) {
+            Ok(contents) => match serde_json::from_str::<Sentinel>(&contents) {
+                Ok(sentinel) => sentinel,
+                Err(error) => {
+                    warn!(
+                        "Ignoring invalid sentinel {}: {error}",
+                        sentinel_file.to_string_lossy()
+                    );
+                    continue;
+                }
+            },
+            Err(_) => continue,
+        };
+
+        if system.refresh_process(Pid::from_u32(sentinel.pid)) {
+            debug!(
+                "Skipping {}; owning process {} is running",
+                data_dir.to_string_lossy(),
+                sentinel.pid
+            );
+            continue;
+        }
+
+        if is_running(&mut system, &data_dir) {
+            if !options.dry_run {
+                stop(&sentinel, &data_dir);
+                if is_running(&mut system, &data_dir) {
+                    warn!("Failed to stop database {}", data_dir.to_string_lossy());
+                    continue;
+                }
+            }
+            report.stopped.push(data_dir.clone());
+        }
+
+        let age = sentinel_file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if age < options.older_than {
+            continue;
+        }
+
+        if !options.dry_run {
+            remove(&sentinel, &data_dir)?;
+        }
+        report.removed.push(data_dir);
+    }
+
+    Ok(report)
+}
+
+/// Check if the postmaster recorded in the `postmaster.pid` file of the data directory is running
+fn is_running(system: &mut System, data_dir: &Path) -> bool {
+    let pid = read_to_string(data_dir.join("postmaster.pid"))
+        .ok()
+        .and_then(|contents| contents.lines().next()?.trim().parse::<u32>().ok());
+    match pid {
+        Some(pid) => system.refresh_process(Pid::from_u32(pid)),
+        None => false,
+    }
+}
+
+/// Stop the server running in the data directory in immediate mode
+fn stop(sentinel: &Sentinel, data_dir: &Path) {
+    debug!("Stopping orphaned database {}", data_dir.to_string_lossy());
+    let mut pg_ctl = PgCtlBuilder::new()
+        .program_dir(sentinel.installation_dir.join("bin"))
+        .mode(Stop)
+        .pgdata(data_dir)
+        .shutdown_mode(Immediate)
+        .wait()
+        .build();
+    if let Err(error) = pg_ctl.output() {
+        warn!(
+            "Failed to stop database {}: {error}",
+            data_dir.to_string_lossy()
+        );
+    }
+}
+
+/// Remove the data directory and password file, and the directory of the password file if it
+/// is empty
+fn remove(sentinel: &Sentinel, data_dir: &Path) -> Result<()> {
+    debug!("Removing orphaned database {}", data_dir.to_string_lossy());
+    remove_dir_all(data_dir)?;
+    if sentinel.password_file.exists() {
+        remove_file(&sentinel.password_file)?;
+    }
+    if let Some(password_dir) = sentinel.password_file.parent() {
+        // Only succeeds when the directory is empty
+        let _ = remove_dir(password_dir);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+    use test_log::test;
+
+    /// Process id that is not in use
+    const DEAD_PID: u32 = u32::MAX - 1;
+
+    fn data_dir(temp_dir: &Path, name: &str, pid: u32) -> Result<PathBuf> {
+        let data_dir = temp_dir.join(name);
+        let password_dir = temp_dir.join(format!("{name}-password"));
+        create_dir_all(&data_dir)?;
+        create_dir_all(&password_dir)?;
+        let password_file = password_dir.join(".pgpass");
+        write(&password_file, "password")?;
+        let sentinel = Sentinel {
+            pid,
+            installation_dir: temp_dir.join("installation"),
+            password_file,
+        };
+        let contents = serde_json::to_string(&sentinel).map_err(std::io::Error::from)?;
+        write(data_dir.join(SENTINEL_FILE_NAME), contents)?;
+        Ok(data_dir)
+    }
+
+    #[test]
+    fn test_write_sentinel() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let settings = Settings {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        write_sentinel(&settings)?;
+        let contents = read_to_string(temp_dir.path().join(SENTINEL_FILE_NAME))?;
+        let sentinel: Sentinel = serde_json::from_str(&contents).map_err(std::io::Error::from)?;
+        assert_eq!(std::process::id(), sentinel.pid);
+        assert_eq!(settings.password_file, sentinel.password_file);
+
+        let persistent_dir = tempfile::tempdir()?;
+        let settings = Settings {
+            data_dir: persistent_dir.path().to_path_buf(),
+            temporary: false,
+            ..settings
+        };
+        write_sentinel(&settings)?;
+        assert!(!persistent_dir.path().join(SENTINEL_FILE_NAME).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let orphaned = data_dir(temp_dir.path(), "orphaned", DEAD_PID)?;
+        let owned = data_dir(temp_dir.path(), "owned", std::process::id())?;
+        create_dir_all(temp_dir.path().join("unrelated"))?;
+
+        let options = CleanupOptions::new()
+            .temp_dir(temp_dir.path())
+            .older_than(Duration::ZERO);
+        let report = cleanup(&options.clone().dry_run(true))?;
+        assert_eq!(vec![orphaned.clone()], report.removed);
+        assert!(report.stopped.is_empty());
+        assert!(orphaned.exists());
+
+        let report = cleanup(&options)?;
+        assert_eq!(vec![orphaned.clone()], report.removed);
+        assert!(!orphaned.exists());
+        assert!(!temp_dir.path().join("orphaned-password").exists());
+        assert!(owned.exists());
+        assert!(temp_dir.path().join("unrelated").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_stale_postmaster() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let orphaned = data_dir(temp_dir.path(), "orphaned", DEAD_PID)?;
+        write(orphaned.join("postmaster.pid"), format!("{DEAD_PID}\n"))?;
+
+        let options = CleanupOptions::new()
+            .temp_dir(temp_dir.path())
+            .older_than(Duration::ZERO);
+        let report = cleanup(&options)?;
+        assert!(report.stopped.is_empty());
+        assert_eq!(vec![orphaned.clone()], report.removed);
+        assert!(!orphaned.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_threshold() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let orphaned = data_dir(temp_dir.path(), "orphaned", DEAD_PID)?;
+
+        let options = CleanupOptions::new().temp_dir(temp_dir.path());
+        let report = cleanup(&options)?;
+        assert!(report.removed.is_empty());
+        assert!(orphaned.exists());
+        Ok(())
+    }
+}
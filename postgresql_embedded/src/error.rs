@@ -1,4 +1,8 @@
+use crate::hook::HookEvent;
+use postgresql_archive::Version;
+use postgresql_commands::pg_isready::ConnectionStatus;
 use std::string::FromUtf8Error;
+use std::time::Duration;
 
 /// PostgreSQL embedded result type
 pub type Result<T, E = Error> = core::result::Result<T, E>;
@@ -15,27 +19,76 @@ pub enum Error {
     /// Error when the database could not be created
     #[error(transparent)]
     CreateDatabaseError(anyhow::Error),
+    /// Error when the extension could not be created
+    #[error(transparent)]
+    CreateExtensionError(anyhow::Error),
     /// Error when determining if the database exists
     #[error(transparent)]
     DatabaseExistsError(anyhow::Error),
     /// Error when the database could not be initialized
     #[error(transparent)]
     DatabaseInitializationError(anyhow::Error),
+    /// Error when the database is not accepting connections within the timeout
+    #[error("Database not ready after {timeout:?}; connection status {status:?}")]
+    DatabaseNotReady {
+        status: ConnectionStatus,
+        timeout: Duration,
+    },
     /// Error when the database could not be started
     #[error(transparent)]
     DatabaseStartError(anyhow::Error),
     /// Error when the database could not be stopped
     #[error(transparent)]
     DatabaseStopError(anyhow::Error),
+    /// Error when the database could not be upgraded
+    #[error(transparent)]
+    DatabaseUpgradeError(anyhow::Error),
     /// Error when the database could not be dropped
     #[error(transparent)]
     DropDatabaseError(anyhow::Error),
+    /// Error when the health of the database could not be checked
+    #[error(transparent)]
+    HealthCheckError(anyhow::Error),
+    /// Error when a lifecycle hook fails
+    #[error("{event} hook failed: {error}")]
+    HookError {
+        event: HookEvent,
+        error: anyhow::Error,
+    },
+    /// Error when the data directory was initialized by an incompatible major version
+    #[error("Data directory version {data_version} is incompatible with PostgreSQL version {binary_version}")]
+    IncompatibleDataDirectory {
+        data_version: Version,
+        binary_version: Version,
+    },
+    /// Error when an invalid libpq connection string is provided
+    #[error("Invalid connection string: {dsn}; {message}")]
+    InvalidDsn { dsn: String, message: String },
+    /// Error when a setting has an invalid value
+    #[error("Invalid setting {key}: {message}")]
+    InvalidSetting { key: String, message: String },
+    /// Error when a settings file cannot be parsed
+    #[error("Invalid settings file {file}: {message}")]
+    InvalidSettingsFile { file: String, message: String },
     /// Error when an invalid URL is provided
     #[error("Invalid URL: {url}; {message}")]
     InvalidUrl { url: String, message: String },
     /// Error when IO operations fail
     #[error(transparent)]
     IoError(anyhow::Error),
+    /// Error when an applied migration file has been modified
+    #[error("Migration {script} has changed since it was applied; expected checksum {expected}, found {actual}")]
+    MigrationChecksumMismatch {
+        script: String,
+        expected: String,
+        actual: String,
+    },
+    /// Error when the migrations could not be applied
+    #[error(transparent)]
+    MigrationError(anyhow::Error),
+    /// Error when the statistics could not be queried
+    #[error(transparent)]
+    StatsError(anyhow::Error),
 }
 
 /// Convert PostgreSQL [archive errors](postgresql_archive::Error) to an [embedded errors](Error::ArchiveError)
@@ -72,9 +125,34 @@ mod test {
         assert_eq!(error.to_string(), "release not found for version [test]");
     }
 
+    #[test]
+    fn test_incompatible_data_directory_error() {
+        let error = Error::IncompatibleDataDirectory {
+            data_version: Version::new(15, None, None),
+            binary_version: Version::new(16, Some(2), Some(0)),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Data directory version 15 is incompatible with PostgreSQL version 16.2.0"
+        );
+    }
+
+    #[test]
+    fn test_migration_checksum_mismatch_error() {
+        let error = Error::MigrationChecksumMismatch {
+            script: "V1__init.sql".to_string(),
+            expected: "abc".to_string(),
+            actual: "def".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Migration V1__init.sql has changed since it was applied; expected checksum abc, found def"
+        );
+    }
+
     #[test]
     fn test_from_io_error() {
-        let io_error = std::io::Error::new(std::io::ErrorKind::Other, "test");
+        let io_error = std::io::Error::other("test");
         let error = Error::from(io_error);
         assert_eq!(error.to_string(), "test");
     }
@@ -0,0 +1,64 @@
+//! Server health checks
+
+use std::time::Duration;
+
+/// Delays between connection checks while waiting for the server to be ready. The delay starts
+/// at the initial delay and is multiplied after each check, up to the maximum delay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    /// Delay after the first check
+    pub initial_delay: Duration,
+    /// Maximum delay between checks
+    pub max_delay: Duration,
+    /// Factor the delay is multiplied by after each check
+    pub multiplier: u32,
+}
+
+impl Backoff {
+    /// Get the delay after the given check, starting at `0`
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.checked_pow(attempt).unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+/// Default implementation for [`Backoff`]; 50 milliseconds doubling up to 1 second
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_backoff_delay() {
+        let backoff = Backoff::default();
+        assert_eq!(Duration::from_millis(50), backoff.delay(0));
+        assert_eq!(Duration::from_millis(100), backoff.delay(1));
+        assert_eq!(Duration::from_millis(800), backoff.delay(4));
+        assert_eq!(Duration::from_secs(1), backoff.delay(5));
+        assert_eq!(Duration::from_secs(1), backoff.delay(100));
+    }
+
+    #[test]
+    fn test_backoff_constant() {
+        let backoff = Backoff {
+            initial_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(1),
+            multiplier: 1,
+        };
+        assert_eq!(Duration::from_millis(200), backoff.delay(0));
+        assert_eq!(Duration::from_millis(200), backoff.delay(10));
+    }
+}
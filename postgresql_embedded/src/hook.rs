@@ -0,0 +1,116 @@
+//! Lifecycle hooks
+
+use crate::postgresql::PostgreSQL;
+use std::fmt;
+use std::sync::Arc;
+
+/// Points in the [PostgreSQL] lifecycle where [hooks](Hook) are run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HookEvent {
+    /// After the archive has been installed; only when the installation directory did not
+    /// already exist
+    AfterInstall,
+    /// After the data directory has been initialized with `initdb`, before the first start
+    AfterInitialize,
+    /// After the server has started
+    AfterStart,
+    /// Before the server is stopped
+    BeforeStop,
+    /// After the server has stopped
+    AfterStop,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookEvent::AfterInstall => write!(formatter, "after install"),
+            HookEvent::AfterInitialize => write!(formatter, "after initialize"),
+            HookEvent::AfterStart => write!(formatter, "after start"),
+            HookEvent::BeforeStop => write!(formatter, "before stop"),
+            HookEvent::AfterStop => write!(formatter, "after stop"),
+        }
+    }
+}
+
+/// Custom code run at a [lifecycle event](HookEvent) of a [PostgreSQL] server. If the hook
+/// returns an error, the operation that triggered the event fails with a
+/// [HookError](crate::Error::HookError).
+///
+/// Closures taking a reference to the server are hooks, which is convenient for hooks that
+/// do not need to await:
+///
+/// ```no_run
+/// use postgresql_embedded::{HookEvent, PostgreSQL};
+///
+/// let mut postgresql = PostgreSQL::default();
+/// postgresql.add_hook(HookEvent::AfterInitialize, |postgresql: &PostgreSQL| {
+///     println!("Initialized {}", postgresql.settings().data_dir.to_string_lossy());
+///     Ok(())
+/// });
+/// ```
+///
+/// Hooks that need to await, for example to create a database after the server starts,
+/// implement the trait:
+///
+/// ```no_run
+/// use postgresql_embedded::{Hook, HookEvent, PostgreSQL};
+///
+/// struct CreateDatabase;
+///
+/// #[async_trait::async_trait]
+/// impl Hook for CreateDatabase {
+///     async fn run(&self, postgresql: &PostgreSQL) -> anyhow::Result<()> {
+///         postgresql.create_database("app").await?;
+///         Ok(())
+///     }
+/// }
+///
+/// let mut postgresql = PostgreSQL::default();
+/// postgresql.add_hook(HookEvent::AfterStart, CreateDatabase);
+/// ```
+#[async_trait::async_trait]
+pub trait Hook: Send + Sync {
+    /// Run the hook
+    async fn run(&self, postgresql: &PostgreSQL) -> anyhow::Result<()>;
+}
+
+#[async_trait::async_trait]
+impl<F> Hook for F
+where
+    F: Fn(&PostgreSQL) -> anyhow::Result<()> + Send + Sync,
+{
+    async fn run(&self, postgresql: &PostgreSQL) -> anyhow::Result<()> {
+        self(postgresql)
+    }
+}
+
+/// Hooks registered on a [PostgreSQL] server, run in the order they were added
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    hooks: Vec<(HookEvent, Arc<dyn Hook>)>,
+}
+
+impl Hooks {
+    /// Add a hook for the event
+    pub(crate) fn add(&mut self, event: HookEvent, hook: Arc<dyn Hook>) {
+        self.hooks.push((event, hook));
+    }
+
+    /// Get the hooks for the event
+    pub(crate) fn get(&self, event: HookEvent) -> Vec<Arc<dyn Hook>> {
+        self.hooks
+            .iter()
+            .filter(|(hook_event, _)| *hook_event == event)
+            .map(|(_, hook)| hook.clone())
+            .collect()
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_list()
+            .entries(self.hooks.iter().map(|(event, _)| event))
+            .finish()
+    }
+}
@@ -63,7 +63,12 @@
 //! downloaded and included in the resulting binary. The version of the PostgreSQL binaries is
 //! determined by the `POSTGRESQL_VERSION` environment variable. If the `POSTGRESQL_VERSION`
 //! environment variable is not set, then `postgresql_archive::LATEST` will be used to determine the
-//! version of the PostgreSQL binaries to download.
+//! version of the PostgreSQL binaries to download. The binaries are downloaded from the repository at
+//! the `POSTGRESQL_RELEASES_URL` environment variable, or from
+//! `postgresql_archive::DEFAULT_POSTGRESQL_URL` if it is not set. At runtime, the repository is set
+//! with the `releases_url` setting, which also defaults to the `POSTGRESQL_RELEASES_URL` environment
+//! variable. For machines without internet access, the URL can be a local directory of archives
+//! (e.g. `file:///opt/postgresql`).
 //!
 //! When downloading the PostgreSQL binaries, either during build, or at runtime, the `GITHUB_TOKEN`
 //! environment variable can be set to a GitHub personal access token to increase the rate limit for
@@ -74,6 +79,11 @@
 //! - Unix: `$HOME/.theseus/postgresql`
 //! - Windows: `%USERPROFILE%\.theseus\postgresql`
 //!
+//! Downloaded archives are also kept in the `postgresql_archive` cache (`$HOME/.theseus/cache` or
+//! `%USERPROFILE%\.theseus\cache`, or the `POSTGRESQL_ARCHIVE_CACHE_DIR` environment variable), so
+//! that new installation directories, and the build script of the `bundled` feature, reuse archives
+//! that were already downloaded.
+//!
 //! ## Feature flags
 //!
 //! postgresql_embedded uses feature flags to address compile time and binary size
@@ -85,6 +95,7 @@
 //! |------------|-----------------------------------------------------------|----------|
 //! | `bundled`  | Bundles the PostgreSQL archive into the resulting binary  | No      |
 //! | `blocking` | Enables the blocking API; requires `tokio`                | No       |
+//! | `cli`      | Builds the `pg-embedded` command line tool                | No       |
 //! | `tokio`    | Enables using tokio for async                             | No       |
 //!
 //! ## Safety
@@ -111,10 +122,28 @@
 
 #[cfg(feature = "blocking")]
 pub mod blocking;
+mod cleanup;
 mod error;
+mod health;
+mod hook;
+mod migration;
 mod postgresql;
+mod resources;
+mod service;
 mod settings;
+mod signal;
+mod stats;
 
+pub use cleanup::{cleanup, CleanupOptions, CleanupReport, DEFAULT_CLEANUP_AGE};
 pub use error::{Error, Result};
-pub use postgresql::{PostgreSQL, Status};
-pub use settings::Settings;
+pub use health::Backoff;
+pub use hook::{Hook, HookEvent};
+pub use migration::{Migration, Migrations, DEFAULT_SCHEMA_HISTORY_TABLE};
+pub use postgresql::{PostgreSQL, Status, UpgradeMode};
+pub use postgresql_archive::{Progress, ProgressEvent};
+pub use postgresql_commands::pg_isready::ConnectionStatus;
+pub use resources::{ResourceLimits, ResourceProfile};
+pub use settings::{InitOptions, Settings, SettingsBuilder};
+#[cfg(any(unix, feature = "tokio"))]
+pub use signal::SignalGuard;
+pub use stats::{Activity, BgWriterStats, ConnectionStats, DatabaseStats, Stats};
@@ -0,0 +1,489 @@
+//! SQL migrations applied with psql
+
+use crate::error::Error::MigrationError;
+use crate::error::Result;
+use crate::hook::Hook;
+use crate::postgresql::PostgreSQL;
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The default name of the table used to track applied migrations
+pub const DEFAULT_SCHEMA_HISTORY_TABLE: &str = "schema_history";
+
+/// A SQL migration file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Migration {
+    /// Version of the migration; `1.1` for `V1_1__add_users.sql`, or the file stem for other files
+    pub version: String,
+    /// Description of the migration; `add users` for `V1_1__add_users.sql`
+    pub description: String,
+    /// File name of the migration
+    pub script: String,
+    /// Path of the migration file
+    pub path: PathBuf,
+    /// Hex encoded SHA-256 checksum of the migration file contents
+    pub checksum: String,
+}
+
+impl Migration {
+    /// Create a new [Migration] from the file at the given path
+    fn from_path(path: &Path) -> Result<Self> {
+        let script = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_string(),
+            None => {
+                return Err(MigrationError(anyhow!(
+                    "Invalid migration file {}",
+                    path.to_string_lossy()
+                )))
+            }
+        };
+        let stem = script.trim_end_matches(".sql");
+        let (version, description) = match parse_versioned_name(stem) {
+            Some((version, description)) => (version, description),
+            None => (stem.to_string(), stem.to_string()),
+        };
+        let checksum = checksum(path)?;
+
+        Ok(Self {
+            version,
+            description,
+            script,
+            path: path.to_path_buf(),
+            checksum,
+        })
+    }
+
+    /// Check that the migration file has not changed since it was loaded, so that the file that is
+    /// applied is the file covered by the checksum
+    pub(crate) fn verify(&self) -> Result<()> {
+        let checksum = checksum(&self.path)?;
+        if checksum != self.checksum {
+            return Err(MigrationError(anyhow!(
+                "Migration {} has changed since it was loaded; expected checksum {}, found {checksum}",
+                self.script,
+                self.checksum
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether the migration file is named `V<version>__<description>.sql`
+    fn is_versioned(&self) -> bool {
+        parse_versioned_name(self.script.trim_end_matches(".sql")).is_some()
+    }
+}
+
+/// A directory of SQL migration files applied in order to a database.
+///
+/// Files named `V<version>__<description>.sql` (e.g. `V1__init.sql`, `V1_1__add_users.sql`) are
+/// versioned migrations and are applied in version order, so `V2__b.sql` is applied before
+/// `V10__a.sql`. Any other `.sql` file is versioned by its file name and applied afterwards in
+/// natural order of the names, so `001_a.sql` is applied before `002_b.sql`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Migrations {
+    dir: PathBuf,
+    table: String,
+    pub(crate) transactional: bool,
+}
+
+impl Migrations {
+    /// Create a new [Migrations] for the `.sql` files in the given directory
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            table: DEFAULT_SCHEMA_HISTORY_TABLE.to_string(),
+            transactional: true,
+        }
+    }
+
+    /// Name of the table used to track applied migrations; defaults to `schema_history`
+    pub fn table<S: Into<String>>(mut self, table: S) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Run each migration file in its own transaction; defaults to `true`. Disable this for
+    /// migrations that cannot run inside a transaction block (e.g. `CREATE INDEX CONCURRENTLY`).
+    pub fn transactional(mut self, transactional: bool) -> Self {
+        self.transactional = transactional;
+        self
+    }
+
+    /// Load the migration files from the directory, in the order they should be applied
+    pub fn load(&self) -> Result<Vec<Migration>> {
+        let mut migrations: Vec<Migration> = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|extension| extension == "sql") {
+                migrations.push(Migration::from_path(&path)?);
+            }
+        }
+        migrations.sort_by(|a, b| match (a.is_versioned(), b.is_versioned()) {
+            (true, true) => natural_cmp(&a.version, &b.version),
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => natural_cmp(&a.script, &b.script),
+        });
+
+        for (index, migration) in migrations.iter().enumerate() {
+            if let Some(existing) = migrations[..index]
+                .iter()
+                .find(|m| m.version == migration.version)
+            {
+                return Err(MigrationError(anyhow!(
+                    "Migrations {} and {} have the same version {}",
+                    existing.script,
+                    migration.script,
+                    migration.version
+                )));
+            }
+        }
+
+        Ok(migrations)
+    }
+
+    /// SQL to create the schema history table if it does not exist
+    pub(crate) fn create_table_sql(&self) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (\
+             version TEXT PRIMARY KEY, \
+             description TEXT NOT NULL, \
+             script TEXT NOT NULL, \
+             checksum TEXT NOT NULL, \
+             installed_on TIMESTAMPTZ NOT NULL DEFAULT now())",
+            quote_identifier(&self.table)
+        )
+    }
+
+    /// SQL to select the version and checksum of the applied migrations
+    pub(crate) fn applied_sql(&self) -> String {
+        format!(
+            "SELECT version, checksum FROM {} ORDER BY installed_on, version",
+            quote_identifier(&self.table)
+        )
+    }
+
+    /// SQL to record the migration in the schema history table
+    pub(crate) fn insert_sql(&self, migration: &Migration) -> String {
+        format!(
+            "INSERT INTO {} (version, description, script, checksum) VALUES ({}, {}, {}, {})",
+            quote_identifier(&self.table),
+            quote_literal(&migration.version),
+            quote_literal(&migration.description),
+            quote_literal(&migration.script),
+            quote_literal(&migration.checksum),
+        )
+    }
+}
+
+/// Parse the `version` and `checksum` of the applied migrations from the output of
+/// [applied_sql](Migrations::applied_sql) run with `psql --csv --tuples-only`
+pub(crate) fn parse_applied(output: &str) -> Vec<(String, String)> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = output.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.next_if_eq(&'"').is_some() => field.push('"'),
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows.into_iter()
+        .filter_map(|row| match <[String; 2]>::try_from(row) {
+            Ok([version, checksum]) => Some((version, checksum)),
+            Err(_) => None,
+        })
+        .collect()
+}
+
+/// Check that every pending versioned migration sorts after the applied versioned migrations. A
+/// versioned migration that sorts before the last applied one was added out of order and is not
+/// applied. Non-versioned migrations are applied after the versioned migrations in any case, so
+/// they are not checked.
+pub(crate) fn check_order(migrations: &[Migration], applied_versions: &[&str]) -> Result<()> {
+    let migrations: Vec<&Migration> = migrations
+        .iter()
+        .filter(|migration| migration.is_versioned())
+        .collect();
+    let Some(last_applied) = migrations
+        .iter()
+        .rposition(|migration| applied_versions.contains(&migration.version.as_str()))
+    else {
+        return Ok(());
+    };
+
+    match migrations[..last_applied]
+        .iter()
+        .find(|migration| !applied_versions.contains(&migration.version.as_str()))
+    {
+        Some(migration) => Err(MigrationError(anyhow!(
+            "Migration {} with version {} sorts before the applied migration {}",
+            migration.script,
+            migration.version,
+            migrations[last_applied].script
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// [Hook] that applies [migrations](Migrations) to a database each time the server is started;
+/// added with [PostgreSQL::add_migrations]
+pub(crate) struct MigrationHook {
+    database_name: String,
+    migrations: Migrations,
+}
+
+impl MigrationHook {
+    /// Create a new [MigrationHook] for the database with the given name
+    pub(crate) fn new(database_name: String, migrations: Migrations) -> Self {
+        Self {
+            database_name,
+            migrations,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Hook for MigrationHook {
+    async fn run(&self, postgresql: &PostgreSQL) -> anyhow::Result<()> {
+        postgresql
+            .migrate(&self.database_name, &self.migrations)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Parse the version and description from a `V<version>__<description>` file stem
+fn parse_versioned_name(stem: &str) -> Option<(String, String)> {
+    let (version, description) = stem.strip_prefix('V')?.split_once("__")?;
+    let parts: Vec<&str> = version.split(['_', '.']).collect();
+    if parts
+        .iter()
+        .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    Some((parts.join("."), description.replace('_', " ")))
+}
+
+/// Compare two file names, treating runs of digits as numbers
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x = String::new();
+                while let Some(c) = a.next_if(char::is_ascii_digit) {
+                    x.push(c);
+                }
+                let mut y = String::new();
+                while let Some(c) = b.next_if(char::is_ascii_digit) {
+                    y.push(c);
+                }
+                let x_digits = x.trim_start_matches('0');
+                let y_digits = y.trim_start_matches('0');
+                let ordering = x_digits
+                    .len()
+                    .cmp(&y_digits.len())
+                    .then_with(|| x_digits.cmp(y_digits))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Hex encoded SHA-256 checksum of the contents of the file
+fn checksum(path: &Path) -> Result<String> {
+    let contents = fs::read(path)?;
+    Ok(hex::encode(Sha256::digest(contents)))
+}
+
+/// Quote a SQL identifier
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Quote a SQL string literal
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn write_migrations(files: &[&str]) -> Result<tempfile::TempDir> {
+        let dir = tempfile::tempdir()?;
+        for file in files {
+            fs::write(dir.path().join(file), format!("-- {file}"))?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_load_versioned() -> Result<()> {
+        let dir = write_migrations(&[
+            "V10__ten.sql",
+            "V2__add_users.sql",
+            "V1__init.sql",
+            "V1_1__one_one.sql",
+            "README.md",
+        ])?;
+        let migrations = Migrations::new(dir.path()).load()?;
+        let versions: Vec<&str> = migrations.iter().map(|m| m.version.as_str()).collect();
+        assert_eq!(vec!["1", "1.1", "2", "10"], versions);
+        assert_eq!("add users", migrations[2].description);
+        assert_eq!("V2__add_users.sql", migrations[2].script);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_ordered() -> Result<()> {
+        let dir = write_migrations(&["002_users.sql", "001_init.sql", "010_roles.sql"])?;
+        let migrations = Migrations::new(dir.path()).load()?;
+        let versions: Vec<&str> = migrations.iter().map(|m| m.version.as_str()).collect();
+        assert_eq!(vec!["001_init", "002_users", "010_roles"], versions);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_duplicate_version() -> Result<()> {
+        let dir = write_migrations(&["V1__init.sql", "V1__other.sql"])?;
+        assert!(Migrations::new(dir.path()).load().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_order() -> Result<()> {
+        let dir = write_migrations(&["V1__init.sql", "V2__users.sql", "V3__roles.sql"])?;
+        let migrations = Migrations::new(dir.path()).load()?;
+        assert!(check_order(&migrations, &[]).is_ok());
+        assert!(check_order(&migrations, &["1"]).is_ok());
+        assert!(check_order(&migrations, &["1", "2", "3"]).is_ok());
+        assert!(check_order(&migrations, &["1", "3"]).is_err());
+        assert!(check_order(&migrations, &["2"]).is_err());
+
+        let dir = write_migrations(&["V1__init.sql", "V2__users.sql", "001_seed.sql"])?;
+        let migrations = Migrations::new(dir.path()).load()?;
+        assert!(check_order(&migrations, &["001_seed"]).is_ok());
+        assert!(check_order(&migrations, &["1", "001_seed"]).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("V1__init.sql");
+        fs::write(&path, "")?;
+        let migration = Migration::from_path(&path)?;
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            migration.checksum
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_versioned_name() {
+        assert_eq!(
+            Some(("1.2".to_string(), "init db".to_string())),
+            parse_versioned_name("V1_2__init_db")
+        );
+        assert_eq!(None, parse_versioned_name("V1a__init"));
+        assert_eq!(None, parse_versioned_name("V__init"));
+        assert_eq!(None, parse_versioned_name("V1_init"));
+        assert_eq!(None, parse_versioned_name("001_init"));
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(Ordering::Less, natural_cmp("V2__b.sql", "V10__a.sql"));
+        assert_eq!(Ordering::Less, natural_cmp("001_a.sql", "002_a.sql"));
+        assert_eq!(Ordering::Less, natural_cmp("1", "1.1"));
+        assert_eq!(Ordering::Greater, natural_cmp("1.10", "1.9"));
+        assert_eq!(Ordering::Equal, natural_cmp("V1__a.sql", "V1__a.sql"));
+    }
+
+    #[test]
+    fn test_insert_sql() {
+        let migrations = Migrations::new("migrations").table("history");
+        let migration = Migration {
+            version: "1".to_string(),
+            description: "o'brien".to_string(),
+            script: "V1__o'brien.sql".to_string(),
+            path: PathBuf::from("C:\\migrations\\V1__o'brien.sql"),
+            checksum: "abc".to_string(),
+        };
+        assert_eq!(
+            "INSERT INTO \"history\" (version, description, script, checksum) VALUES ('1', 'o''brien', 'V1__o''brien.sql', 'abc')",
+            migrations.insert_sql(&migration)
+        );
+    }
+
+    #[test]
+    fn test_parse_applied() {
+        let output = "1,abc\n\"a,\"\"b\"\"\nc\",def\r\ninvalid\n";
+        assert_eq!(
+            vec![
+                ("1".to_string(), "abc".to_string()),
+                ("a,\"b\"\nc".to_string(), "def".to_string()),
+            ],
+            parse_applied(output)
+        );
+        assert!(parse_applied("").is_empty());
+    }
+
+    #[test]
+    fn test_verify() -> Result<()> {
+        let dir = write_migrations(&["V1__init.sql"])?;
+        let path = dir.path().join("V1__init.sql");
+        let migration = Migration::from_path(&path)?;
+        assert!(migration.verify().is_ok());
+        fs::write(&path, "SELECT 1")?;
+        assert!(migration.verify().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_table_sql() {
+        let migrations = Migrations::new("migrations");
+        assert!(migrations
+            .create_table_sql()
+            .starts_with("CREATE TABLE IF NOT EXISTS \"schema_history\" ("));
+    }
+}
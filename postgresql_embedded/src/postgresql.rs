@@ -1,28 +1,58 @@
-use crate::error::Error::{DatabaseInitializationError, DatabaseStartError, DatabaseStopError};
+use crate::cleanup;
+use crate::error::Error::{
+    DatabaseInitializationError, DatabaseNotReady, DatabaseStartError, DatabaseStopError,
+    DatabaseUpgradeError, HealthCheckError, HookError, IncompatibleDataDirectory,
+    MigrationChecksumMismatch, MigrationError, StatsError,
+};
 use crate::error::Result;
+use crate::health::Backoff;
+use crate::hook::{Hook, HookEvent, Hooks};
+use crate::migration::{check_order, parse_applied, Migration, MigrationHook, Migrations};
+#[cfg(target_os = "linux")]
+use crate::resources::cgroup;
+use crate::service;
 use crate::settings::{Settings, BOOTSTRAP_SUPERUSER};
-use postgresql_archive::{extract, get_archive};
-use postgresql_archive::{get_version, Version};
+use crate::signal;
+use crate::stats::{self, Stats};
+use anyhow::anyhow;
+#[cfg(feature = "bundled")]
+use postgresql_archive::extract_with_progress;
+use postgresql_archive::{
+    extract_file_with_progress, get_archive_file_with_progress, get_extension_archive_file,
+    install_extension_file, Progress, ProgressEvent,
+};
+use postgresql_archive::{get_version_from, Version};
 use postgresql_commands::initdb::InitDbBuilder;
 use postgresql_commands::pg_ctl::Mode::{Start, Stop};
 use postgresql_commands::pg_ctl::PgCtlBuilder;
 use postgresql_commands::pg_ctl::ShutdownMode::Fast;
+use postgresql_commands::pg_isready::{ConnectionStatus, PgIsReadyBuilder};
+use postgresql_commands::pg_upgrade::PgUpgradeBuilder;
 use postgresql_commands::psql::PsqlBuilder;
+use postgresql_commands::traits::CommandToString;
 #[cfg(feature = "tokio")]
 use postgresql_commands::AsyncCommandExecutor;
 use postgresql_commands::CommandBuilder;
 #[cfg(not(feature = "tokio"))]
 use postgresql_commands::CommandExecutor;
-use std::fs::{remove_dir_all, remove_file};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Debug;
+use std::fs::{remove_dir_all, remove_file, rename};
 use std::io::prelude::*;
 use std::net::TcpListener;
 #[cfg(feature = "bundled")]
 use std::ops::Deref;
-#[cfg(feature = "bundled")]
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use tracing::{debug, instrument};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument, warn};
 
-use crate::Error::{CreateDatabaseError, DatabaseExistsError, DropDatabaseError};
+use crate::Error::{
+    CreateDatabaseError, CreateExtensionError, DatabaseExistsError, DropDatabaseError,
+};
 
 #[cfg(feature = "bundled")]
 lazy_static::lazy_static! {
@@ -48,6 +78,21 @@ pub enum Status {
     Started,
     /// Server initialized and stopped
     Stopped,
+    /// Server initialized by an incompatible major version; the data directory must be upgraded
+    Incompatible,
+}
+
+/// File transfer mode used by `pg_upgrade` when upgrading to a new major version
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UpgradeMode {
+    /// Copy the data files to the new cluster
+    #[default]
+    Copy,
+    /// Hard link the data files to the new cluster; faster, but the old cluster can no longer be
+    /// used once the new cluster has been started
+    Link,
+    /// Clone (reflink) the data files to the new cluster; requires file system support
+    Clone,
 }
 
 /// PostgreSQL server
@@ -55,13 +100,24 @@ pub enum Status {
 pub struct PostgreSQL {
     version: Version,
     settings: Settings,
+    hooks: Hooks,
+    progress: Progress,
+    /// Whether this is a handle to a server owned by another instance; a handle does not stop the
+    /// server or remove its data directory when it is dropped
+    handle: bool,
 }
 
 /// PostgreSQL server methods
 impl PostgreSQL {
     /// Create a new [`PostgreSQL`] instance
     pub fn new(version: Version, settings: Settings) -> Self {
-        let mut postgresql = PostgreSQL { version, settings };
+        let mut postgresql = PostgreSQL {
+            version,
+            settings,
+            hooks: Hooks::default(),
+            progress: Progress::default(),
+            handle: false,
+        };
 
         // If the minor and release version are set, append the version to the installation directory
         // to avoid conflicts with other versions.  This will also facilitate setting the status
@@ -99,7 +155,11 @@ impl PostgreSQL {
         if self.is_running() {
             Status::Started
         } else if self.is_initialized() {
-            Status::Stopped
+            if self.is_compatible() {
+                Status::Stopped
+            } else {
+                Status::Incompatible
+            }
         } else if self.is_installed() {
             Status::Installed
         } else {
@@ -117,6 +177,52 @@ impl PostgreSQL {
         &self.settings
     }
 
+    /// Add a [hook](Hook) run at the given [lifecycle event](HookEvent). Hooks for the same event
+    /// are run in the order they were added; if a hook fails, the operation that triggered the
+    /// event fails with a [HookError](crate::Error::HookError).
+    pub fn add_hook<H: Hook + 'static>(&mut self, event: HookEvent, hook: H) {
+        self.hooks.add(event, Arc::new(hook));
+    }
+
+    /// Set the [progress](Progress) that [events](ProgressEvent) are reported to while the archive
+    /// is downloaded and extracted by [setup](PostgreSQL::setup) or [install](PostgreSQL::install),
+    /// either a callback or the sending half of a channel.
+    pub fn set_progress<P: Into<Progress>>(&mut self, progress: P) {
+        self.progress = progress.into();
+    }
+
+    /// Apply the [migrations](Migrations) to the database with the given name each time the server
+    /// is started, as an [AfterStart](HookEvent::AfterStart) hook; see [migrate](Self::migrate).
+    /// The migrations run in the order they were added relative to other hooks, and if they fail,
+    /// [start](Self::start) fails with a [HookError](crate::Error::HookError) wrapping the
+    /// migration error.
+    pub fn add_migrations<S: Into<String>>(&mut self, database_name: S, migrations: Migrations) {
+        self.add_hook(
+            HookEvent::AfterStart,
+            MigrationHook::new(database_name.into(), migrations),
+        );
+    }
+
+    /// Get a handle to the server, which does not stop the server or remove its data directory
+    /// when it is dropped
+    #[cfg(feature = "blocking")]
+    pub(crate) fn handle(&self) -> Self {
+        let mut handle = self.clone();
+        handle.handle = true;
+        handle
+    }
+
+    /// Run the hooks for the lifecycle event
+    async fn run_hooks(&self, event: HookEvent) -> Result<()> {
+        for hook in self.hooks.get(event) {
+            debug!("Running {event} hook");
+            if let Err(error) = hook.run(self).await {
+                return Err(HookError { event, error });
+            }
+        }
+        Ok(())
+    }
+
     /// Check if the PostgreSQL server is installed
     fn is_installed(&self) -> bool {
         if self.version.minor.is_none() || self.version.release.is_none() {
@@ -138,16 +244,73 @@ impl PostgreSQL {
         pid_file.exists()
     }
 
+    /// Check if the data directory was initialized by the same major version as the PostgreSQL
+    /// binaries. A data directory that has not been initialized is considered compatible.
+    fn is_compatible(&self) -> bool {
+        self.check_data_dir_version().is_ok()
+    }
+
+    /// Get the version recorded in the `PG_VERSION` file of the data directory, or `None` if the
+    /// data directory has not been initialized.
+    fn data_dir_version(&self) -> Result<Option<Version>> {
+        let version_file = self.settings.data_dir.join("PG_VERSION");
+        if !version_file.exists() {
+            return Ok(None);
+        }
+
+        let version = std::fs::read_to_string(version_file)?;
+        Ok(Some(Version::from_str(version.trim())?))
+    }
+
+    /// Verify that the data directory was initialized by the same major version as the
+    /// PostgreSQL binaries. PostgreSQL is unable to start a data directory created by a different
+    /// major version; the data directory must be upgraded with [`upgrade_to`](Self::upgrade_to).
+    fn check_data_dir_version(&self) -> Result<()> {
+        match self.data_dir_version()? {
+            Some(data_version) if !same_major_version(&data_version, &self.version) => {
+                Err(IncompatibleDataDirectory {
+                    data_version,
+                    binary_version: self.version,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the installation directory without the version specific suffix
+    fn installation_base_dir(&self) -> PathBuf {
+        let path = &self.settings.installation_dir;
+        if self.version.minor.is_some()
+            && self.version.release.is_some()
+            && path.ends_with(self.version.to_string())
+        {
+            if let Some(parent) = path.parent() {
+                return parent.to_path_buf();
+            }
+        }
+
+        path.clone()
+    }
+
     /// Set up the database by extracting the archive and initializing the database.
     /// If the installation directory already exists, the archive will not be extracted.
-    /// If the data directory already exists, the database will not be initialized.
+    /// If the data directory already exists, the database will not be initialized; if it was
+    /// initialized by a different major version, an
+    /// [IncompatibleDataDirectory](crate::Error::IncompatibleDataDirectory) error is returned.
+    /// The data directory of a temporary database is created here, readable only by the owner.
     #[instrument]
     pub async fn setup(&mut self) -> Result<()> {
         if !self.is_installed() {
             self.install().await?;
         }
 
-        if !self.is_initialized() {
+        if self.settings.temporary {
+            create_private_dir(&self.settings.data_dir)?;
+        }
+
+        if self.is_initialized() {
+            self.check_data_dir_version()?;
+        } else {
             self.initialize().await?;
         }
 
@@ -160,14 +323,15 @@ impl PostgreSQL {
     /// already exists, the archive will not be extracted. If the archive is not found, an error will be
     /// returned.
     #[instrument]
-    async fn install(&mut self) -> Result<()> {
+    pub async fn install(&mut self) -> Result<()> {
         debug!("Starting installation process for version {}", self.version);
+        self.progress.report(ProgressEvent::Resolving);
 
         // If the minor and release version are not set, determine the latest version and update the
         // version and installation directory accordingly. This is an optimization to avoid downloading
         // the archive if the latest version is already installed.
         if self.version.minor.is_none() || self.version.release.is_none() {
-            let version = get_version(&self.version).await?;
+            let version = get_version_from(&self.settings.releases_url, &self.version).await?;
             self.version = version;
             self.settings.installation_dir = self
                 .settings
@@ -177,25 +341,51 @@ impl PostgreSQL {
 
         if self.settings.installation_dir.exists() {
             debug!("Installation directory already exists");
+            self.progress.report(ProgressEvent::Done);
             return Ok(());
         }
 
+        // Resolving and completion are reported once for the whole installation rather than for
+        // each of the download and the extraction
+        let progress = self.progress.clone();
+        let download_progress = Progress::new(move |event| {
+            if !matches!(event, ProgressEvent::Resolving | ProgressEvent::Done) {
+                progress.report(event);
+            }
+        });
+
         #[cfg(feature = "bundled")]
         // If the requested version is the same as the version of the bundled archive, use the bundled
         // archive. This avoids downloading the archive in environments where internet access is
         // restricted or undesirable.
-        let (version, bytes) = if ARCHIVE_VERSION.deref() == &self.version {
+        if ARCHIVE_VERSION.deref() == &self.version {
             debug!("Using bundled installation archive");
-            (self.version, bytes::Bytes::copy_from_slice(ARCHIVE))
+            let bytes = bytes::Bytes::from_static(ARCHIVE);
+            extract_with_progress(&bytes, &self.settings.installation_dir, &self.progress).await?;
         } else {
-            get_archive(&self.version).await?
-        };
+            let archive = get_archive_file_with_progress(
+                &self.settings.releases_url,
+                &self.version,
+                &download_progress,
+            )
+            .await?;
+            self.version = *archive.version();
+            extract_file_with_progress(&archive, &self.settings.installation_dir, &self.progress)
+                .await?;
+        }
 
         #[cfg(not(feature = "bundled"))]
-        let (version, bytes) = { get_archive(&self.version).await? };
-
-        self.version = version;
-        extract(&bytes, &self.settings.installation_dir).await?;
+        {
+            let archive = get_archive_file_with_progress(
+                &self.settings.releases_url,
+                &self.version,
+                &download_progress,
+            )
+            .await?;
+            self.version = *archive.version();
+            extract_file_with_progress(&archive, &self.settings.installation_dir, &self.progress)
+                .await?;
+        }
 
         debug!(
             "Installed PostgreSQL version {} to {}",
@@ -203,7 +393,7 @@ impl PostgreSQL {
             self.settings.installation_dir.to_string_lossy()
         );
 
-        Ok(())
+        self.run_hooks(HookEvent::AfterInstall).await
     }
 
     /// Initialize the database in the data directory. This will create the necessary files and
@@ -211,8 +401,10 @@ impl PostgreSQL {
     #[instrument]
     async fn initialize(&mut self) -> Result<()> {
         if !self.settings.password_file.exists() {
-            let mut file = std::fs::File::create(&self.settings.password_file)?;
-            file.write_all(self.settings.password.as_bytes())?;
+            if let Some(password_dir) = self.settings.password_file.parent() {
+                create_private_dir(password_dir)?;
+            }
+            write_password_file(&self.settings.password_file, &self.settings.password)?;
         }
 
         debug!(
@@ -220,29 +412,83 @@ impl PostgreSQL {
             self.settings.data_dir.to_string_lossy()
         );
 
-        let initdb = InitDbBuilder::from(&self.settings)
-            .pgdata(&self.settings.data_dir)
-            .username(BOOTSTRAP_SUPERUSER)
-            .auth("password")
-            .pwfile(&self.settings.password_file)
-            .encoding("UTF8");
+        let initdb = self.initdb();
 
         match self.execute_command(initdb).await {
             Ok((_stdout, _stderr)) => {
+                cleanup::write_sentinel(&self.settings)?;
                 debug!(
                     "Initialized database {}",
                     self.settings.data_dir.to_string_lossy()
                 );
-                Ok(())
+                self.run_hooks(HookEvent::AfterInitialize).await
             }
             Err(error) => Err(DatabaseInitializationError(error.into())),
         }
     }
 
+    /// Create the `initdb` command for the data directory, applying the
+    /// [init options](crate::InitOptions) of the settings.
+    fn initdb(&self) -> ExtendedCommand<InitDbBuilder> {
+        let options = &self.settings.init_options;
+        let mut initdb = InitDbBuilder::from(&self.settings)
+            .pgdata(&self.settings.data_dir)
+            .username(BOOTSTRAP_SUPERUSER)
+            .auth("password")
+            .pwfile(&self.settings.password_file);
+
+        if let Some(encoding) = &options.encoding {
+            initdb = initdb.encoding(encoding);
+        }
+        if let Some(locale) = &options.locale {
+            initdb = initdb.locale(locale);
+        }
+        if let Some(lc_collate) = &options.lc_collate {
+            initdb = initdb.lc_collate(lc_collate);
+        }
+        if let Some(lc_ctype) = &options.lc_ctype {
+            initdb = initdb.lc_ctype(lc_ctype);
+        }
+        if let Some(locale_provider) = &options.locale_provider {
+            initdb = initdb.locale_provider(locale_provider);
+        }
+        if let Some(icu_locale) = &options.icu_locale {
+            initdb = initdb.icu_locale(icu_locale);
+        }
+        if options.data_checksums {
+            initdb = initdb.data_checksums();
+        }
+        if let Some(wal_segment_size) = options.wal_segment_size {
+            initdb = initdb.wal_segsize(wal_segment_size.to_string());
+        }
+        if let Some(auth_local) = &options.auth_local {
+            initdb = initdb.auth_local(auth_local);
+        }
+        if let Some(auth_host) = &options.auth_host {
+            initdb = initdb.auth_host(auth_host);
+        }
+
+        ExtendedCommand {
+            builder: initdb,
+            args: options.args.iter().map(OsString::from).collect(),
+        }
+    }
+
     /// Start the database and wait for the startup to complete.
     /// If the port is set to `0`, the database will be started on a random port.
+    /// If the data directory was initialized by a different major version, an
+    /// [IncompatibleDataDirectory](crate::Error::IncompatibleDataDirectory) error is returned.
     #[instrument]
     pub async fn start(&mut self) -> Result<()> {
+        self.start_server().await?;
+        self.run_hooks(HookEvent::AfterStart).await
+    }
+
+    /// Start the database and wait for the startup to complete, without running the
+    /// [AfterStart](HookEvent::AfterStart) hooks
+    async fn start_server(&mut self) -> Result<()> {
+        self.check_data_dir_version()?;
+
         if self.settings.port == 0 {
             let listener = TcpListener::bind(("0.0.0.0", 0))?;
             self.settings.port = listener.local_addr()?.port();
@@ -254,16 +500,25 @@ impl PostgreSQL {
             self.settings.port
         );
         let start_log = self.settings.data_dir.join("start.log");
-        let options = format!("-F -p {}", self.settings.port);
         let pg_ctl = PgCtlBuilder::from(&self.settings)
             .mode(Start)
             .pgdata(&self.settings.data_dir)
             .log(start_log)
-            .options(options)
+            .options(self.start_options())
             .wait();
 
-        match self.execute_command(pg_ctl).await {
+        let result = match self.limit_resources() {
+            #[cfg(target_os = "linux")]
+            Some(cgroup) => {
+                self.execute_command(cgroup::CgroupCommand::new(pg_ctl, &cgroup))
+                    .await
+            }
+            _ => self.execute_command(pg_ctl).await,
+        };
+
+        match result {
             Ok((_stdout, _stderr)) => {
+                signal::register(&self.settings);
                 debug!(
                     "Started database {} on port {}",
                     self.settings.data_dir.to_string_lossy(),
@@ -271,13 +526,88 @@ impl PostgreSQL {
                 );
                 Ok(())
             }
-            Err(error) => Err(DatabaseStartError(error.into())),
+            Err(error) => {
+                self.remove_resource_limits();
+                Err(DatabaseStartError(error.into()))
+            }
+        }
+    }
+
+    /// Get the server options passed to `pg_ctl start`
+    fn start_options(&self) -> String {
+        let mut options = format!("-F -p {}", self.settings.port);
+        if !self.settings.preload_libraries.is_empty() {
+            options.push_str(&format!(
+                " -c shared_preload_libraries={}",
+                self.settings.preload_libraries.join(",")
+            ));
+        }
+        for (name, value) in self.settings.resource_profile.parameters() {
+            options.push_str(&format!(" -c {name}={value}"));
+        }
+        options
+    }
+
+    /// Create a cgroup with the [resource limits](Settings::resource_limits) to start the server
+    /// in, and return its path. If the cgroup cannot be set up, a warning is logged and `None` is
+    /// returned, so that the server runs without limits.
+    fn limit_resources(&self) -> Option<PathBuf> {
+        let limits = &self.settings.resource_limits;
+        if limits.is_empty() {
+            return None;
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let result = cgroup::path(self.settings.port).and_then(|cgroup| {
+                cgroup::create(&cgroup, limits)?;
+                Ok(cgroup)
+            });
+            match result {
+                Ok(cgroup) => {
+                    debug!(
+                        "Limiting resources of database {} with cgroup {}",
+                        self.settings.data_dir.to_string_lossy(),
+                        cgroup.to_string_lossy()
+                    );
+                    Some(cgroup)
+                }
+                Err(error) => {
+                    warn!(
+                        "Unable to limit resources of database {}; running without limits: {error}",
+                        self.settings.data_dir.to_string_lossy()
+                    );
+                    None
+                }
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            warn!("Resource limits are only supported on Linux; running without limits");
+            None
+        }
+    }
+
+    /// Remove the cgroup used to limit the resources of the stopped server
+    fn remove_resource_limits(&self) {
+        #[cfg(target_os = "linux")]
+        if !self.settings.resource_limits.is_empty() {
+            cgroup::remove(self.settings.port);
         }
     }
 
     /// Stop the database gracefully (smart mode) and wait for the shutdown to complete.
     #[instrument]
     pub async fn stop(&self) -> Result<()> {
+        self.run_hooks(HookEvent::BeforeStop).await?;
+        self.stop_server().await?;
+        self.run_hooks(HookEvent::AfterStop).await
+    }
+
+    /// Stop the database and wait for the shutdown to complete, without running the
+    /// [BeforeStop](HookEvent::BeforeStop) and [AfterStop](HookEvent::AfterStop) hooks
+    async fn stop_server(&self) -> Result<()> {
         debug!(
             "Stopping database {}",
             self.settings.data_dir.to_string_lossy()
@@ -290,6 +620,8 @@ impl PostgreSQL {
 
         match self.execute_command(pg_ctl).await {
             Ok((_stdout, _stderr)) => {
+                signal::unregister(&self.settings.data_dir);
+                self.remove_resource_limits();
                 debug!(
                     "Stopped database {}",
                     self.settings.data_dir.to_string_lossy()
@@ -300,6 +632,278 @@ impl PostgreSQL {
         }
     }
 
+    /// Check whether the server is accepting connections with `pg_isready`.
+    #[instrument(level = "debug")]
+    pub async fn health(&self) -> Result<ConnectionStatus> {
+        self.pg_isready(None).await
+    }
+
+    /// Wait until the server is accepting connections, checking with `pg_isready` using the
+    /// [default backoff](Backoff::default). This is useful after a restart or when attaching to a
+    /// server started elsewhere; [start](Self::start) already waits for the server to start.
+    ///
+    /// Returns a [DatabaseNotReady](crate::Error::DatabaseNotReady) error with the last
+    /// connection status if the server is not ready within the timeout, or if `pg_isready` did
+    /// not attempt to connect. Each check is limited to the time remaining before the timeout;
+    /// a check that does not finish in time is killed and returns a
+    /// [HealthCheckError](crate::Error::HealthCheckError).
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<()> {
+        self.wait_until_ready_with_backoff(timeout, Backoff::default())
+            .await
+    }
+
+    /// Wait until the server is accepting connections, checking with `pg_isready` using the
+    /// given [backoff](Backoff) between checks. See [wait_until_ready](Self::wait_until_ready).
+    #[instrument(level = "debug")]
+    pub async fn wait_until_ready_with_backoff(
+        &self,
+        timeout: Duration,
+        backoff: Backoff,
+    ) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let mut attempt = 0;
+
+        loop {
+            let status = self.pg_isready(Some(deadline)).await?;
+            debug!("Database connection status {status:?}");
+            if status == ConnectionStatus::Accepting {
+                return Ok(());
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if status == ConnectionStatus::NoAttempt || remaining.is_zero() {
+                return Err(DatabaseNotReady { status, timeout });
+            }
+
+            sleep(backoff.delay(attempt).min(remaining)).await;
+            if Instant::now() >= deadline {
+                return Err(DatabaseNotReady { status, timeout });
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Check the connection status with `pg_isready`. When a deadline is set, the check is
+    /// limited to the time remaining until the deadline, and a
+    /// [HealthCheckError](crate::Error::HealthCheckError) is returned if the deadline has passed
+    /// or `pg_isready` does not finish before it.
+    async fn pg_isready(&self, deadline: Option<Instant>) -> Result<ConnectionStatus> {
+        let mut pg_isready = PgIsReadyBuilder::from(&self.settings)
+            .dbname(BOOTSTRAP_SUPERUSER)
+            .quiet();
+        let remaining = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(HealthCheckError(anyhow!(
+                        "deadline passed before pg_isready could run"
+                    )));
+                }
+                // pg_isready only accepts whole seconds and waits indefinitely with a timeout of
+                // 0 seconds; the command itself is limited to the remaining time below
+                let seconds = remaining
+                    .as_secs()
+                    .saturating_add(u64::from(remaining.subsec_nanos() > 0))
+                    .clamp(1, u64::from(u16::MAX));
+                pg_isready = pg_isready.timeout(seconds as u16);
+                Some(remaining)
+            }
+            None => None,
+        };
+
+        let exit_code = self.execute_command_status(pg_isready, remaining).await?;
+        match exit_code.and_then(ConnectionStatus::from_exit_code) {
+            Some(status) => Ok(status),
+            None => Err(HealthCheckError(anyhow!(
+                "pg_isready exited with unexpected status {exit_code:?}"
+            ))),
+        }
+    }
+
+    /// Upgrade the data directory to the given [version](Version).
+    ///
+    /// The new version is installed and, when the major version changes, a new cluster is
+    /// initialized with the same settings and the data is migrated with `pg_upgrade` using the
+    /// given [mode](UpgradeMode). The migration is verified with `pg_upgrade --check` before any
+    /// data is changed. If the server is running, it is stopped for the upgrade and started again
+    /// afterwards, without running [hooks](Self::add_hook) or [migrations](Self::add_migrations).
+    /// Upgrades within the same major version only update the binaries.
+    ///
+    /// The upgraded cluster replaces the original data directory. If the upgrade fails, the
+    /// original data directory is left in place and the new cluster is removed.
+    #[instrument]
+    pub async fn upgrade_to(&mut self, version: Version, mode: UpgradeMode) -> Result<()> {
+        let data_dir = self.settings.data_dir.clone();
+        let data_version = match self.data_dir_version()? {
+            Some(data_version) => data_version,
+            None => {
+                return Err(DatabaseUpgradeError(anyhow!(
+                    "data directory {} is not initialized",
+                    data_dir.to_string_lossy()
+                )))
+            }
+        };
+        let upgrade_dir = sibling_dir(&data_dir, "upgrade");
+        let mut postgresql = self.installed_instance(version, &upgrade_dir).await?;
+
+        if same_major_version(&postgresql.version, &data_version) {
+            debug!(
+                "Data directory version {data_version} is compatible with {}; updating binaries",
+                postgresql.version
+            );
+            self.version = postgresql.version;
+            self.settings.installation_dir = postgresql.settings.installation_dir.clone();
+            return Ok(());
+        } else if major_version(&postgresql.version) < major_version(&data_version) {
+            return Err(DatabaseUpgradeError(anyhow!(
+                "cannot downgrade data directory version {data_version} to {}",
+                postgresql.version
+            )));
+        }
+
+        let old_binary_dir = if same_major_version(&self.version, &data_version) {
+            if !self.is_installed() {
+                self.install().await?;
+            }
+            self.settings.binary_dir()
+        } else {
+            let old_version = major_version(&data_version);
+            self.installed_instance(old_version, &upgrade_dir)
+                .await?
+                .settings
+                .binary_dir()
+        };
+
+        let was_running = self.status() == Status::Started;
+        // The server is stopped and started without running hooks or migrations, since the data
+        // directory is being upgraded
+        if was_running {
+            self.stop_server().await?;
+        }
+
+        debug!(
+            "Upgrading database {} from version {data_version} to {}",
+            data_dir.to_string_lossy(),
+            postgresql.version
+        );
+
+        if upgrade_dir.exists() {
+            remove_dir_all(&upgrade_dir)?;
+        }
+
+        let result = match postgresql.initialize().await {
+            Ok(_) => self.pg_upgrade(&old_binary_dir, &postgresql, mode).await,
+            Err(error) => Err(error),
+        };
+        let result = match result {
+            Ok(_) => swap_data_dirs(&data_dir, &upgrade_dir, &data_version),
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            warn!(
+                "Upgrade failed; restoring database {}",
+                data_dir.to_string_lossy()
+            );
+            // Cleanup errors are logged so that the original upgrade error is returned
+            if mode == UpgradeMode::Link {
+                if let Err(cleanup_error) = restore_pg_control(&data_dir) {
+                    warn!("Failed to restore pg_control: {cleanup_error}");
+                }
+            }
+            if upgrade_dir.exists() {
+                if let Err(cleanup_error) = remove_dir_all(&upgrade_dir) {
+                    warn!(
+                        "Failed to remove upgrade directory {}: {cleanup_error}",
+                        upgrade_dir.to_string_lossy()
+                    );
+                }
+            }
+            if was_running {
+                if let Err(cleanup_error) = self.start_server().await {
+                    warn!("Failed to restart the server: {cleanup_error}");
+                }
+            }
+            return Err(error);
+        }
+
+        self.version = postgresql.version;
+        self.settings.installation_dir = postgresql.settings.installation_dir.clone();
+        debug!(
+            "Upgraded database {} to version {}",
+            data_dir.to_string_lossy(),
+            self.version
+        );
+
+        if was_running {
+            self.start_server().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Create an installed instance of PostgreSQL for the given [version](Version) that shares
+    /// the settings of this instance, with the given data directory.
+    async fn installed_instance(&self, version: Version, data_dir: &Path) -> Result<PostgreSQL> {
+        let mut settings = self.settings.clone();
+        settings.installation_dir = self.installation_base_dir();
+        settings.data_dir = data_dir.to_path_buf();
+        settings.temporary = false;
+
+        let mut postgresql = PostgreSQL::new(version, settings);
+        if !postgresql.is_installed() {
+            postgresql.install().await?;
+        }
+        Ok(postgresql)
+    }
+
+    /// Migrate the data directory to the data directory of the target [PostgreSQL] instance using
+    /// `pg_upgrade`. The migration is verified with `pg_upgrade --check` before it is performed.
+    async fn pg_upgrade(
+        &self,
+        old_binary_dir: &Path,
+        target: &PostgreSQL,
+        mode: UpgradeMode,
+    ) -> Result<()> {
+        let data_dir = &self.settings.data_dir;
+        let parent_dir = data_dir.parent().unwrap_or(data_dir);
+        // pg_upgrade writes log and script files to the current directory and uses it for sockets
+        let work_dir = tempfile::tempdir_in(parent_dir)?;
+        let port = TcpListener::bind(("0.0.0.0", 0))?.local_addr()?.port();
+        let pg_upgrade = PgUpgradeBuilder::from(&target.settings)
+            .old_bindir(old_binary_dir)
+            .new_bindir(target.settings.binary_dir())
+            .old_datadir(data_dir)
+            .new_datadir(&target.settings.data_dir)
+            .old_port(port)
+            .new_port(port)
+            .socketdir(work_dir.path())
+            .username(BOOTSTRAP_SUPERUSER)
+            .pg_password(&self.settings.password);
+
+        // Use the fully qualified call; the builder's inherent `clone` method enables clone mode
+        let check = Clone::clone(&pg_upgrade).check();
+        if let Err(error) = self
+            .execute_long_running_command(check, work_dir.path())
+            .await
+        {
+            return Err(DatabaseUpgradeError(error.into()));
+        }
+
+        let pg_upgrade = match mode {
+            UpgradeMode::Copy => pg_upgrade,
+            UpgradeMode::Link => pg_upgrade.link(),
+            UpgradeMode::Clone => pg_upgrade.clone(),
+        };
+        match self
+            .execute_long_running_command(pg_upgrade, work_dir.path())
+            .await
+        {
+            Ok((_stdout, _stderr)) => Ok(()),
+            Err(error) => Err(DatabaseUpgradeError(error.into())),
+        }
+    }
+
     /// Create a new database with the given name.
     #[instrument(skip(database_name))]
     pub async fn create_database<S: AsRef<str>>(&self, database_name: S) -> Result<()> {
@@ -388,6 +992,286 @@ impl PostgreSQL {
         }
     }
 
+    /// Get a snapshot of the runtime statistics of the server: the statistics and size of each
+    /// database, the activity of each server process, the background writer statistics and the
+    /// client connection counts. The connection used to collect the statistics is included in
+    /// the activity and connection counts.
+    #[instrument]
+    pub async fn stats(&self) -> Result<Stats> {
+        let databases = self.query_stats(stats::DATABASES_SQL).await?;
+        let activity = self.query_stats(stats::ACTIVITY_SQL).await?;
+        let bgwriter = self
+            .query_stats(stats::BGWRITER_SQL)
+            .await?
+            .pop()
+            .unwrap_or_default();
+        let connections = self
+            .query_stats(stats::CONNECTIONS_SQL)
+            .await?
+            .pop()
+            .unwrap_or_default();
+        Ok(Stats {
+            databases,
+            activity,
+            bgwriter,
+            connections,
+        })
+    }
+
+    /// Run a statistics query with `psql` and parse the CSV output into rows
+    async fn query_stats<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>> {
+        let psql = PsqlBuilder::from(&self.settings)
+            .program_dir(self.settings.binary_dir())
+            .command(sql)
+            .username(BOOTSTRAP_SUPERUSER)
+            .no_psqlrc()
+            .csv();
+
+        match self.execute_command(psql).await {
+            Ok((stdout, _stderr)) => stats::parse_csv(&stdout).map_err(StatsError),
+            Err(error) => Err(StatsError(error.into())),
+        }
+    }
+
+    /// Write a connection service entry with the given name for the server to the
+    /// [connection service file](https://www.postgresql.org/docs/current/libpq-pgservice.html) at
+    /// the given path, and the password to a `.pgpass`
+    /// [password file](https://www.postgresql.org/docs/current/libpq-pgpass.html) in the same
+    /// directory. Existing entries for other services and servers are preserved. The server port
+    /// is only known once the server is started, so this should be called after
+    /// [start](Self::start).
+    ///
+    /// Returns the environment variables that point libpq clients such as `psql` at the server:
+    /// `PGHOST`, `PGPORT`, `PGUSER`, `PGPASSWORD`, `PGPASSFILE`, `PGSERVICEFILE` and `PGSERVICE`.
+    #[instrument]
+    pub fn write_service_file<P: AsRef<Path> + Debug>(
+        &self,
+        path: P,
+        service_name: &str,
+    ) -> Result<HashMap<String, String>> {
+        let path = path.as_ref();
+        let passfile = service::write_service_file(&self.settings, path, service_name)?;
+        debug!("Wrote service {service_name} to {}", path.to_string_lossy());
+
+        let mut environment = self.settings.environment();
+        environment.insert(
+            "PGPASSFILE".to_string(),
+            passfile.to_string_lossy().to_string(),
+        );
+        environment.insert(
+            "PGSERVICEFILE".to_string(),
+            path.to_string_lossy().to_string(),
+        );
+        environment.insert("PGSERVICE".to_string(), service_name.to_string());
+        Ok(environment)
+    }
+
+    /// Install an extension from the repository at the given URL into the installation directory.
+    /// The extension archive is selected for the PostgreSQL version and the current target, and
+    /// its hash is verified before the extension files are installed into the `lib` and
+    /// `share/extension` directories. Libraries that must be loaded at server start should also be
+    /// added to [preload_libraries](Settings::preload_libraries).
+    #[instrument]
+    pub async fn install_extension(
+        &self,
+        url: &str,
+        name: &str,
+        extension_version: &str,
+    ) -> Result<()> {
+        debug!(
+            "Installing extension {name} {extension_version} to {}",
+            self.settings.installation_dir.to_string_lossy()
+        );
+        let archive =
+            get_extension_archive_file(url, name, extension_version, &self.version).await?;
+        let files = install_extension_file(&archive, &self.settings.installation_dir)?;
+        debug!(
+            "Installed extension {name} {extension_version}; {} files installed",
+            files.len()
+        );
+        Ok(())
+    }
+
+    /// Create an extension in the database with the given name. If the extension version is not
+    /// specified, the default version of the extension is created.
+    #[instrument(skip(database_name, name, extension_version))]
+    pub async fn create_extension<S: AsRef<str>>(
+        &self,
+        database_name: S,
+        name: &str,
+        extension_version: Option<&str>,
+    ) -> Result<()> {
+        debug!(
+            "Creating extension {} in database {} for {}:{}",
+            name,
+            database_name.as_ref(),
+            self.settings.host,
+            self.settings.port
+        );
+        let mut command = format!(
+            "CREATE EXTENSION IF NOT EXISTS \"{}\"",
+            name.replace('"', "\"\"")
+        );
+        if let Some(extension_version) = extension_version {
+            command.push_str(&format!(
+                " VERSION '{}'",
+                extension_version.replace('\'', "''")
+            ));
+        }
+        let psql = PsqlBuilder::from(&self.settings)
+            .command(command)
+            .dbname(database_name.as_ref())
+            .username(BOOTSTRAP_SUPERUSER)
+            .no_psqlrc();
+
+        match self.execute_command(psql).await {
+            Ok((_stdout, _stderr)) => {
+                debug!(
+                    "Created extension {} in database {} for {}:{}",
+                    name,
+                    database_name.as_ref(),
+                    self.settings.host,
+                    self.settings.port
+                );
+                Ok(())
+            }
+            Err(error) => Err(CreateExtensionError(error.into())),
+        }
+    }
+
+    /// Apply the migrations to the database with the given name, creating the database if it does
+    /// not exist. Applied migrations are recorded in the schema history table of the database
+    /// along with the checksum of the migration file. If the contents of an applied migration file
+    /// have changed, a [MigrationChecksumMismatch](crate::Error::MigrationChecksumMismatch) error
+    /// is returned before any pending migrations are applied. A pending migration that sorts
+    /// before the last applied migration is rejected with a
+    /// [MigrationError](crate::Error::MigrationError) rather than applied out of order. Each
+    /// migration file is run from its own directory, so relative `\i` includes are resolved
+    /// against it. The applied migrations are read with `psql --csv`, which requires PostgreSQL 12
+    /// or later. Returns the migrations that were applied.
+    #[instrument(skip(database_name))]
+    pub async fn migrate<S: AsRef<str>>(
+        &self,
+        database_name: S,
+        migrations: &Migrations,
+    ) -> Result<Vec<Migration>> {
+        let database_name = database_name.as_ref();
+        let pending = migrations.load()?;
+        debug!(
+            "Migrating database {database_name} for {}:{}",
+            self.settings.host, self.settings.port
+        );
+
+        if !self.database_exists(database_name).await? {
+            self.create_database(database_name).await?;
+        }
+
+        let psql = self
+            .migration_psql(database_name)
+            .command(migrations.create_table_sql());
+        if let Err(error) = self.execute_command(psql).await {
+            return Err(MigrationError(error.into()));
+        }
+
+        let psql = self
+            .migration_psql(database_name)
+            .command(migrations.applied_sql())
+            .tuples_only()
+            .csv();
+        let stdout = match self.execute_command(psql).await {
+            Ok((stdout, _stderr)) => stdout,
+            Err(error) => return Err(MigrationError(error.into())),
+        };
+        let applied = parse_applied(&stdout);
+
+        for migration in &pending {
+            if let Some((_version, checksum)) = applied
+                .iter()
+                .find(|(version, _checksum)| *version == migration.version)
+            {
+                if *checksum != migration.checksum {
+                    return Err(MigrationChecksumMismatch {
+                        script: migration.script.clone(),
+                        expected: checksum.to_string(),
+                        actual: migration.checksum.clone(),
+                    });
+                }
+            }
+        }
+
+        let applied_versions: Vec<&str> = applied
+            .iter()
+            .map(|(version, _checksum)| version.as_str())
+            .collect();
+        check_order(&pending, &applied_versions)?;
+
+        let mut applied_migrations = Vec::new();
+        for migration in pending {
+            if applied
+                .iter()
+                .any(|(version, _checksum)| *version == migration.version)
+            {
+                continue;
+            }
+
+            debug!(
+                "Applying migration {} to database {database_name}",
+                migration.script
+            );
+            migration.verify()?;
+
+            // The migration file is run from its directory, so that `\i` and `\ir` includes
+            // are resolved relative to the file. The history is recorded by a command that runs
+            // after the file, in the same transaction when the migrations are transactional.
+            let dir = match migration.path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let file = migration
+                .path
+                .file_name()
+                .unwrap_or(migration.path.as_os_str());
+            let mut builder = self.migration_psql(database_name);
+            if migrations.transactional {
+                builder = builder.single_transaction();
+            }
+            let psql = ExtendedCommand {
+                builder,
+                args: vec![
+                    "--file".into(),
+                    file.to_os_string(),
+                    "--command".into(),
+                    migrations.insert_sql(&migration).into(),
+                ],
+            };
+            if let Err(error) = self.execute_long_running_command(psql, dir).await {
+                return Err(MigrationError(anyhow!(
+                    "Failed to apply migration {}: {error}",
+                    migration.script
+                )));
+            }
+            applied_migrations.push(migration);
+        }
+
+        debug!(
+            "Migrated database {database_name} for {}:{}; applied {} migrations",
+            self.settings.host,
+            self.settings.port,
+            applied_migrations.len()
+        );
+        Ok(applied_migrations)
+    }
+
+    /// Create a psql command builder that stops on the first error in the given database.
+    fn migration_psql(&self, database_name: &str) -> PsqlBuilder {
+        PsqlBuilder::from(&self.settings)
+            .dbname(database_name)
+            .variable(("ON_ERROR_STOP", "1"))
+            .username(BOOTSTRAP_SUPERUSER)
+            .no_psqlrc()
+            .quiet()
+    }
+
     #[cfg(not(feature = "tokio"))]
     /// Execute a command and return the stdout and stderr as strings.
     async fn execute_command<B: CommandBuilder>(
@@ -408,6 +1292,228 @@ impl PostgreSQL {
         let mut command = command_builder.build_tokio();
         command.execute(self.settings.timeout).await
     }
+
+    #[cfg(not(feature = "tokio"))]
+    /// Execute a command and return the exit code, or `None` if the command was terminated by a
+    /// signal. The command is killed and a [HealthCheckError](crate::Error::HealthCheckError) is
+    /// returned if it does not finish within the timeout.
+    async fn execute_command_status<B: CommandBuilder>(
+        &self,
+        command_builder: B,
+        timeout: Option<Duration>,
+    ) -> Result<Option<i32>> {
+        let mut command = command_builder.build();
+        debug!("Executing command: {}", command.to_command_string());
+        let Some(timeout) = timeout else {
+            return Ok(command.output()?.status.code());
+        };
+
+        let deadline = Instant::now() + timeout;
+        let mut child = command
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status.code());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(HealthCheckError(anyhow!(
+                    "{} did not finish within {timeout:?}",
+                    command.get_program().to_string_lossy()
+                )));
+            }
+            std::thread::sleep(remaining.min(Duration::from_millis(10)));
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Execute a command and return the exit code, or `None` if the command was terminated by a
+    /// signal. The command is killed and a [HealthCheckError](crate::Error::HealthCheckError) is
+    /// returned if it does not finish within the timeout.
+    #[instrument(level = "debug")]
+    async fn execute_command_status<B: CommandBuilder>(
+        &self,
+        command_builder: B,
+        timeout: Option<Duration>,
+    ) -> Result<Option<i32>> {
+        let mut command = command_builder.build_tokio();
+        command.kill_on_drop(true);
+        debug!("Executing command: {}", command.to_command_string());
+        let output = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, command.output())
+                .await
+                .map_err(|_| {
+                    HealthCheckError(anyhow!(
+                        "{} did not finish within {timeout:?}",
+                        command.as_std().get_program().to_string_lossy()
+                    ))
+                })??,
+            None => command.output().await?,
+        };
+        Ok(output.status.code())
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    /// Execute a command in the working directory without a timeout and return the stdout and
+    /// stderr as strings.
+    async fn execute_long_running_command<B: CommandBuilder>(
+        &self,
+        command_builder: B,
+        working_dir: &Path,
+    ) -> postgresql_commands::Result<(String, String)> {
+        let mut command = command_builder.build();
+        command.current_dir(working_dir);
+        command.execute()
+    }
+
+    #[cfg(feature = "tokio")]
+    /// Execute a command in the working directory without a timeout and return the stdout and
+    /// stderr as strings.
+    #[instrument(level = "debug")]
+    async fn execute_long_running_command<B: CommandBuilder>(
+        &self,
+        command_builder: B,
+        working_dir: &Path,
+    ) -> postgresql_commands::Result<(String, String)> {
+        let mut command = command_builder.build_tokio();
+        command.current_dir(working_dir);
+        command.execute(None).await
+    }
+}
+
+/// Command with additional arguments appended to the arguments of a [CommandBuilder]
+#[derive(Debug)]
+struct ExtendedCommand<B: CommandBuilder> {
+    builder: B,
+    args: Vec<OsString>,
+}
+
+impl<B: CommandBuilder> CommandBuilder for ExtendedCommand<B> {
+    /// Get the program name
+    fn get_program(&self) -> &'static OsStr {
+        self.builder.get_program()
+    }
+
+    /// Location of the program binary
+    fn get_program_dir(&self) -> &Option<PathBuf> {
+        self.builder.get_program_dir()
+    }
+
+    /// Get the arguments for the command
+    fn get_args(&self) -> Vec<OsString> {
+        let mut args = self.builder.get_args();
+        args.extend(self.args.iter().cloned());
+        args
+    }
+
+    /// Get the environment variables for the command
+    fn get_envs(&self) -> Vec<(OsString, OsString)> {
+        self.builder.get_envs()
+    }
+}
+
+/// Create the directory and its missing parents. On unix a new directory is created readable
+/// only by the owner, as it holds the data or the password file of the database.
+fn create_private_dir(path: &Path) -> Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path)?;
+    Ok(())
+}
+
+/// Write the password used by initdb to the password file. On unix the file is created readable
+/// only by the owner, as the password is the superuser password.
+fn write_password_file(path: &Path, password: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(password.as_bytes())?;
+    Ok(())
+}
+
+/// Get the major version of PostgreSQL of the [version](Version). Before PostgreSQL 10, the major
+/// version consists of the first two numbers (e.g. `9.6`), so the minor version is kept.
+fn major_version(version: &Version) -> Version {
+    if version.major < 10 {
+        Version::new(version.major, version.minor, None)
+    } else {
+        Version::new(version.major, None, None)
+    }
+}
+
+/// Check if the [versions](Version) are the same major version of PostgreSQL. A version without a
+/// minor version matches any major version before PostgreSQL 10 with the same first number.
+fn same_major_version(version: &Version, other: &Version) -> bool {
+    major_version(version).matches(&major_version(other))
+}
+
+/// Get a directory next to the given directory, with the given suffix appended to the name.
+fn sibling_dir(dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{suffix}"));
+    dir.with_file_name(name)
+}
+
+/// Replace the data directory with the upgraded data directory. The original data directory is
+/// moved aside first so that it can be restored if the upgraded data directory cannot be moved
+/// into place; it is removed once the swap has succeeded. When the upgrade used hard links, removing
+/// the original data directory only removes the links.
+fn swap_data_dirs(data_dir: &Path, upgrade_dir: &Path, data_version: &Version) -> Result<()> {
+    let backup_dir = sibling_dir(data_dir, &data_version.to_string());
+    if backup_dir.exists() {
+        remove_dir_all(&backup_dir)?;
+    }
+
+    debug!(
+        "Replacing data directory {} with {}",
+        data_dir.to_string_lossy(),
+        upgrade_dir.to_string_lossy()
+    );
+    rename(data_dir, &backup_dir)?;
+    if let Err(error) = rename(upgrade_dir, data_dir) {
+        rename(&backup_dir, data_dir)?;
+        return Err(DatabaseUpgradeError(error.into()));
+    }
+
+    remove_dir_all(&backup_dir)?;
+    Ok(())
+}
+
+/// Restore the control file that `pg_upgrade --link` renames to prevent the old cluster from being
+/// started after the new cluster has taken ownership of the data files.
+fn restore_pg_control(data_dir: &Path) -> Result<()> {
+    let pg_control = data_dir.join("global").join("pg_control");
+    let pg_control_old = data_dir.join("global").join("pg_control.old");
+    if pg_control_old.exists() && !pg_control.exists() {
+        rename(pg_control_old, pg_control)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tokio"))]
+/// Sleep for the duration
+async fn sleep(duration: Duration) {
+    std::thread::sleep(duration);
+}
+
+#[cfg(feature = "tokio")]
+/// Sleep for the duration
+async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
 }
 
 /// Default PostgreSQL server
@@ -422,6 +1528,10 @@ impl Default for PostgreSQL {
 /// Stop the PostgreSQL server and remove the data directory if it is marked as temporary.
 impl Drop for PostgreSQL {
     fn drop(&mut self) {
+        if self.handle {
+            return;
+        }
+
         if self.status() == Status::Started {
             let mut pg_ctl = PgCtlBuilder::from(&self.settings)
                 .mode(Stop)
@@ -431,20 +1541,260 @@ impl Drop for PostgreSQL {
                 .build();
 
             let _ = pg_ctl.output();
+            signal::unregister(&self.settings.data_dir);
+            self.remove_resource_limits();
         }
 
         if self.settings.temporary {
             let _ = remove_dir_all(&self.settings.data_dir);
             let _ = remove_file(&self.settings.password_file);
+            if let Some(password_dir) = self.settings.password_file.parent() {
+                // Only succeeds when the directory is empty
+                let _ = std::fs::remove_dir(password_dir);
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::{Error, InitOptions, ResourceProfile};
+    use postgresql_archive::{V15, V16};
+
     #[test]
     #[cfg(feature = "bundled")]
     fn test_archive_version() {
         assert!(!super::ARCHIVE_VERSION.to_string().is_empty());
     }
+
+    /// Create an instance with a data directory that appears to be initialized by `data_version`
+    fn initialized_instance(data_version: &str, version: Version) -> Result<PostgreSQL> {
+        let postgresql = PostgreSQL::new(version, Settings::default());
+        let data_dir = &postgresql.settings().data_dir;
+        create_private_dir(data_dir)?;
+        std::fs::write(data_dir.join("postgresql.conf"), "")?;
+        std::fs::write(data_dir.join("PG_VERSION"), format!("{data_version}\n"))?;
+        Ok(postgresql)
+    }
+
+    #[test]
+    fn test_write_password_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".pgpass");
+        write_password_file(&path, "password")?;
+        assert_eq!("password", std::fs::read_to_string(&path)?);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_private_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("parent").join("data");
+        create_private_dir(&path)?;
+        create_private_dir(&path)?;
+        assert!(path.is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(0o700, mode & 0o777);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_major_version() {
+        assert!(same_major_version(
+            &Version::new(16, Some(2), Some(0)),
+            &Version::new(16, None, None)
+        ));
+        assert!(!same_major_version(
+            &Version::new(16, Some(2), Some(0)),
+            &Version::new(15, Some(6), Some(0))
+        ));
+        assert!(same_major_version(
+            &Version::new(9, Some(6), Some(24)),
+            &Version::new(9, Some(6), None)
+        ));
+        assert!(!same_major_version(
+            &Version::new(9, Some(6), Some(24)),
+            &Version::new(9, Some(5), None)
+        ));
+        assert!(
+            major_version(&Version::new(9, Some(5), None))
+                < major_version(&Version::new(9, Some(6), Some(24)))
+        );
+    }
+
+    #[test]
+    fn test_status_incompatible_pre_10() -> Result<()> {
+        let postgresql = initialized_instance("9.5", Version::new(9, Some(6), Some(24)))?;
+        assert_eq!(Status::Incompatible, postgresql.status());
+        let postgresql = initialized_instance("9.6", Version::new(9, Some(6), Some(24)))?;
+        assert_eq!(Status::Stopped, postgresql.status());
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_compatible() -> Result<()> {
+        let postgresql = initialized_instance("16", V16)?;
+        assert_eq!(Status::Stopped, postgresql.status());
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_incompatible() -> Result<()> {
+        let postgresql = initialized_instance("15", V16)?;
+        assert_eq!(Status::Incompatible, postgresql.status());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_incompatible() -> Result<()> {
+        let mut postgresql = initialized_instance("16", V15)?;
+        let result = postgresql.start().await;
+
+        match result {
+            Err(Error::IncompatibleDataDirectory {
+                data_version,
+                binary_version,
+            }) => {
+                assert_eq!(16, data_version.major);
+                assert_eq!(V15, binary_version);
+            }
+            _ => panic!("expected incompatible data directory error: {result:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pg_isready_deadline_passed() {
+        let postgresql = PostgreSQL::default();
+        let result = postgresql.pg_isready(Some(Instant::now())).await;
+        assert!(matches!(result, Err(Error::HealthCheckError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_run_hooks() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut postgresql = PostgreSQL::default();
+        for _ in 0..2 {
+            let runs = runs.clone();
+            postgresql.add_hook(HookEvent::AfterStart, move |_: &PostgreSQL| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            });
+        }
+        postgresql.add_hook(HookEvent::BeforeStop, |_: &PostgreSQL| {
+            Err(anyhow!("diagnostics failed"))
+        });
+
+        postgresql.run_hooks(HookEvent::AfterStart).await?;
+        postgresql.run_hooks(HookEvent::AfterStop).await?;
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+
+        let result = postgresql.stop().await;
+        match result {
+            Err(Error::HookError { event, error }) => {
+                assert_eq!(HookEvent::BeforeStop, event);
+                assert_eq!("diagnostics failed", error.to_string());
+            }
+            _ => panic!("expected hook error: {result:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_migrations() {
+        let mut postgresql = PostgreSQL::default();
+        postgresql.add_migrations("test", Migrations::new("migrations"));
+        assert_eq!(1, postgresql.hooks.get(HookEvent::AfterStart).len());
+        assert!(postgresql.hooks.get(HookEvent::AfterInitialize).is_empty());
+    }
+
+    #[test]
+    fn test_initdb_default() {
+        let postgresql = PostgreSQL::new(V16, Settings::default());
+        let args = postgresql.initdb().get_args();
+        assert!(args.contains(&OsString::from("--encoding")));
+        assert!(args.contains(&OsString::from("UTF8")));
+        assert!(!args.contains(&OsString::from("--data-checksums")));
+    }
+
+    #[test]
+    fn test_initdb_options() {
+        let settings = Settings {
+            init_options: InitOptions {
+                encoding: Some("SQL_ASCII".to_string()),
+                locale: Some("C".to_string()),
+                lc_collate: Some("C".to_string()),
+                lc_ctype: Some("C".to_string()),
+                locale_provider: Some("icu".to_string()),
+                icu_locale: Some("en-US".to_string()),
+                data_checksums: true,
+                wal_segment_size: Some(64),
+                auth_local: Some("trust".to_string()),
+                auth_host: Some("scram-sha-256".to_string()),
+                args: vec!["--no-sync".to_string()],
+            },
+            ..Default::default()
+        };
+        let postgresql = PostgreSQL::new(V16, settings);
+        let args: Vec<String> = postgresql
+            .initdb()
+            .get_args()
+            .iter()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        let expected = [
+            "--encoding",
+            "SQL_ASCII",
+            "--locale",
+            "C",
+            "--lc-collate",
+            "--lc-ctype",
+            "--locale-provider",
+            "icu",
+            "--icu-locale",
+            "en-US",
+            "--data-checksums",
+            "--wal-segsize",
+            "64",
+            "--auth-local",
+            "trust",
+            "--auth-host",
+            "scram-sha-256",
+        ];
+        for arg in expected {
+            assert!(args.contains(&arg.to_string()), "missing {arg}: {args:?}");
+        }
+        assert_eq!(Some(&"--no-sync".to_string()), args.last());
+    }
+
+    #[test]
+    fn test_start_options() {
+        let settings = Settings {
+            port: 5433,
+            preload_libraries: vec!["pg_stat_statements".to_string()],
+            resource_profile: ResourceProfile::Tiny,
+            ..Default::default()
+        };
+        let postgresql = PostgreSQL::new(V16, settings);
+        let options = postgresql.start_options();
+        assert!(options.starts_with("-F -p 5433 -c shared_preload_libraries=pg_stat_statements"));
+        assert!(options.contains(" -c shared_buffers=16MB"));
+        assert!(options.contains(" -c max_connections=20"));
+
+        let postgresql = PostgreSQL::new(V16, Settings::default());
+        assert_eq!("-F -p 0", postgresql.start_options());
+    }
 }
@@ -0,0 +1,422 @@
+//! Limit the resources used by the server
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Presets of the memory-oriented server parameters, to run many servers on one machine such as
+/// a CI runner
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceProfile {
+    /// Smallest footprint for short-lived test servers; 16MB shared buffers, 1MB work memory,
+    /// 20 connections and 64MB of WAL
+    Tiny,
+    /// Reduced footprint; 64MB shared buffers, 2MB work memory, 50 connections and 256MB of WAL
+    Small,
+    /// The PostgreSQL defaults
+    #[default]
+    Default,
+}
+
+impl ResourceProfile {
+    /// Get the server parameters set by the profile
+    pub fn parameters(&self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            ResourceProfile::Tiny => vec![
+                ("shared_buffers", "16MB"),
+                ("work_mem", "1MB"),
+                ("max_connections", "20"),
+                ("min_wal_size", "32MB"),
+                ("max_wal_size", "64MB"),
+            ],
+            ResourceProfile::Small => vec![
+                ("shared_buffers", "64MB"),
+                ("work_mem", "2MB"),
+                ("max_connections", "50"),
+                ("min_wal_size", "80MB"),
+                ("max_wal_size", "256MB"),
+            ],
+            ResourceProfile::Default => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ResourceProfile {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceProfile::Tiny => write!(formatter, "tiny"),
+            ResourceProfile::Small => write!(formatter, "small"),
+            ResourceProfile::Default => write!(formatter, "default"),
+        }
+    }
+}
+
+impl FromStr for ResourceProfile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "tiny" => Ok(ResourceProfile::Tiny),
+            "small" => Ok(ResourceProfile::Small),
+            "default" => Ok(ResourceProfile::Default),
+            _ => Err(format!("unknown resource profile {value}")),
+        }
+    }
+}
+
+/// Memory and CPU limits for the server processes. On Linux, when a limit is set, the server is
+/// started in a child [cgroup v2](https://docs.kernel.org/admin-guide/cgroup-v2.html) of the
+/// cgroup of the current process. Apart from the root cgroup, a cgroup that holds processes cannot
+/// enable controllers for its children, and the current process is not moved to another cgroup.
+/// If cgroup v2 is not available or the controllers cannot be enabled, a warning is logged and
+/// the server runs without limits. Limits are ignored on other platforms.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Maximum memory used by the server processes, in bytes (`memory.max`)
+    pub memory_max: Option<u64>,
+    /// Maximum CPU used by the server processes, as a percentage of one CPU; `200` allows two
+    /// CPUs (`cpu.max`)
+    pub cpu_max: Option<u32>,
+}
+
+impl ResourceLimits {
+    /// Check if any limit is set
+    pub(crate) fn is_empty(&self) -> bool {
+        self.memory_max.is_none() && self.cpu_max.is_none()
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) mod cgroup {
+    //! Place the server processes in a cgroup v2
+
+    use super::ResourceLimits;
+    use postgresql_commands::CommandBuilder;
+    use std::ffi::{OsStr, OsString};
+    use std::fs::{create_dir_all, read_to_string, remove_dir, write};
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::{Path, PathBuf};
+
+    /// Mount point of the cgroup v2 hierarchy
+    const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+    /// Error number returned when enabling controllers in a cgroup that holds processes
+    const EBUSY: i32 = 16;
+
+    /// Error number returned when enabling controllers in a cgroup that is not delegated
+    const EACCES: i32 = 13;
+
+    /// Period of the CPU limit, in microseconds
+    const CPU_PERIOD: u64 = 100_000;
+
+    /// Get the path of the cgroup for the server on the given port; a child of the cgroup of the
+    /// current process
+    pub(crate) fn path(port: u16) -> Result<PathBuf> {
+        let root = Path::new(CGROUP_ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "cgroup v2 is not mounted",
+            ));
+        }
+        let contents = read_to_string("/proc/self/cgroup")?;
+        let current = process_cgroup(&contents)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "process is not in a cgroup v2"))?;
+        Ok(root
+            .join(current.trim_start_matches('/'))
+            .join(format!("postgresql-embedded-{port}")))
+    }
+
+    /// Get the cgroup v2 path from the contents of `/proc/self/cgroup`
+    fn process_cgroup(contents: &str) -> Option<&str> {
+        contents.lines().find_map(|line| line.strip_prefix("0::"))
+    }
+
+    /// Format the `cpu.max` value for a percentage of one CPU
+    fn cpu_max(percent: u32) -> String {
+        let quota = CPU_PERIOD * u64::from(percent) / 100;
+        format!("{quota} {CPU_PERIOD}")
+    }
+
+    /// Create the cgroup with the limits, before the server is started in it with a
+    /// [CgroupCommand]. The cgroup is removed again if it cannot be set up.
+    pub(crate) fn create(cgroup: &Path, limits: &ResourceLimits) -> Result<()> {
+        let parent = cgroup
+            .parent()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "cgroup has no parent"))?;
+        let mut controllers = Vec::new();
+        if limits.memory_max.is_some() {
+            controllers.push("+memory");
+        }
+        if limits.cpu_max.is_some() {
+            controllers.push("+cpu");
+        }
+        enable_controllers(parent, &controllers.join(" "), |path, contents| {
+            write(path, contents)
+        })?;
+
+        create_dir_all(cgroup)?;
+        let result = configure(cgroup, limits);
+        if result.is_err() {
+            let _ = remove_dir(cgroup);
+        }
+        result
+    }
+
+    /// Enable the controllers for the children of the parent cgroup. Under the no internal
+    /// processes rule, a cgroup that holds processes cannot enable controllers for its children
+    /// and the write fails with `EBUSY`; the write fails with `EACCES` when the cgroup is not
+    /// delegated to the current user. The current process is never moved to another cgroup, so
+    /// these errors are returned and the server runs without limits. The write function is
+    /// passed in for testing.
+    fn enable_controllers<W>(parent: &Path, controllers: &str, write: W) -> Result<()>
+    where
+        W: Fn(&Path, &str) -> Result<()>,
+    {
+        let subtree_control = parent.join("cgroup.subtree_control");
+        write(&subtree_control, controllers).map_err(|error| {
+            let reason = match error.raw_os_error() {
+                Some(EBUSY) => "the cgroup holds processes",
+                Some(EACCES) => "the cgroup is not delegated to the current user",
+                _ => return error,
+            };
+            Error::new(
+                error.kind(),
+                format!(
+                    "cannot enable controllers in {}; {reason}: {error}",
+                    parent.to_string_lossy()
+                ),
+            )
+        })
+    }
+
+    /// Set the limits of the cgroup
+    fn configure(cgroup: &Path, limits: &ResourceLimits) -> Result<()> {
+        if let Some(memory_max) = limits.memory_max {
+            write(cgroup.join("memory.max"), memory_max.to_string())?;
+        }
+        if let Some(percent) = limits.cpu_max {
+            write(cgroup.join("cpu.max"), cpu_max(percent))?;
+        }
+        Ok(())
+    }
+
+    /// Remove the cgroup of the server on the given port, once the server has stopped
+    pub(crate) fn remove(port: u16) {
+        if let Ok(cgroup) = path(port) {
+            let _ = remove_dir(cgroup);
+        }
+    }
+
+    /// Command run inside a cgroup; a shell moves itself into the cgroup and then executes the
+    /// command of the builder, so that the command and the processes it starts are limited from
+    /// the start
+    #[derive(Debug)]
+    pub(crate) struct CgroupCommand<B: CommandBuilder> {
+        builder: B,
+        cgroup: PathBuf,
+    }
+
+    impl<B: CommandBuilder> CgroupCommand<B> {
+        /// Create a new [CgroupCommand] running the command of the builder in the cgroup
+        pub(crate) fn new(builder: B, cgroup: &Path) -> Self {
+            Self {
+                builder,
+                cgroup: cgroup.to_path_buf(),
+            }
+        }
+    }
+
+    impl<B: CommandBuilder> CommandBuilder for CgroupCommand<B> {
+        /// Get the program name
+        fn get_program(&self) -> &'static OsStr {
+            "sh".as_ref()
+        }
+
+        /// Location of the program binary
+        fn get_program_dir(&self) -> &Option<PathBuf> {
+            &None
+        }
+
+        /// Get the arguments for the command
+        fn get_args(&self) -> Vec<OsString> {
+            let mut args: Vec<OsString> = vec![
+                "-c".into(),
+                r#"echo $$ > "$0" && exec "$@""#.into(),
+                self.cgroup.join("cgroup.procs").into(),
+                self.builder.get_program_file().into(),
+            ];
+            args.extend(self.builder.get_args());
+            args
+        }
+
+        /// Get the environment variables for the command
+        fn get_envs(&self) -> Vec<(OsString, OsString)> {
+            self.builder.get_envs()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use postgresql_commands::pg_ctl::PgCtlBuilder;
+        use std::sync::Mutex;
+        use test_log::test;
+
+        #[test]
+        fn test_process_cgroup() {
+            let contents = "12:memory:/user.slice\n0::/user.slice/session-1.scope\n";
+            assert_eq!(
+                Some("/user.slice/session-1.scope"),
+                process_cgroup(contents)
+            );
+            assert_eq!(None, process_cgroup("4:memory:/docker\n"));
+        }
+
+        #[test]
+        fn test_cpu_max() {
+            assert_eq!("50000 100000", cpu_max(50));
+            assert_eq!("200000 100000", cpu_max(200));
+        }
+
+        #[test]
+        fn test_enable_controllers() -> Result<()> {
+            let parent = tempfile::tempdir()?;
+            let writes = Mutex::new(Vec::new());
+            enable_controllers(parent.path(), "+memory", |path, contents| {
+                writes
+                    .lock()
+                    .unwrap()
+                    .push((path.to_path_buf(), contents.to_string()));
+                Ok(())
+            })?;
+
+            let subtree_control = parent.path().join("cgroup.subtree_control");
+            assert_eq!(
+                vec![(subtree_control, "+memory".to_string())],
+                *writes.lock().unwrap()
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn test_enable_controllers_busy() -> Result<()> {
+            let parent = tempfile::tempdir()?;
+            let writes = Mutex::new(Vec::new());
+            let result = enable_controllers(parent.path(), "+memory +cpu", |path, contents| {
+                writes
+                    .lock()
+                    .unwrap()
+                    .push((path.to_path_buf(), contents.to_string()));
+                Err(Error::from_raw_os_error(EBUSY))
+            });
+
+            // The current process is not moved; the controllers are written only once
+            let error = result.unwrap_err();
+            assert!(error.to_string().contains("the cgroup holds processes"));
+            let subtree_control = parent.path().join("cgroup.subtree_control");
+            assert_eq!(
+                vec![(subtree_control, "+memory +cpu".to_string())],
+                *writes.lock().unwrap()
+            );
+            assert_eq!(0, std::fs::read_dir(parent.path())?.count());
+            Ok(())
+        }
+
+        #[test]
+        fn test_enable_controllers_not_delegated() -> Result<()> {
+            let parent = tempfile::tempdir()?;
+            let result = enable_controllers(parent.path(), "+memory", |_, _| {
+                Err(Error::from_raw_os_error(EACCES))
+            });
+            let error = result.unwrap_err();
+            assert_eq!(ErrorKind::PermissionDenied, error.kind());
+            assert!(error.to_string().contains("not delegated"));
+            Ok(())
+        }
+
+        #[test]
+        fn test_enable_controllers_error() -> Result<()> {
+            let parent = tempfile::tempdir()?;
+            let result = enable_controllers(parent.path(), "+memory", |_, _| {
+                Err(Error::from(ErrorKind::NotFound))
+            });
+            assert_eq!(ErrorKind::NotFound, result.unwrap_err().kind());
+            Ok(())
+        }
+
+        #[test]
+        fn test_cgroup_command() {
+            let pg_ctl = PgCtlBuilder::new().program_dir("bin").pgdata("data");
+            let command = CgroupCommand::new(pg_ctl, Path::new("/sys/fs/cgroup/postgresql"));
+            assert_eq!(OsStr::new("sh"), command.get_program_file());
+            assert_eq!(
+                vec![
+                    OsString::from("-c"),
+                    OsString::from(r#"echo $$ > "$0" && exec "$@""#),
+                    OsString::from("/sys/fs/cgroup/postgresql/cgroup.procs"),
+                    OsString::from("bin/pg_ctl"),
+                    OsString::from("--pgdata"),
+                    OsString::from("data"),
+                ],
+                command.get_args()
+            );
+        }
+
+        #[test]
+        fn test_cgroup_command_runs_in_shell() -> Result<()> {
+            let dir = tempfile::tempdir()?;
+            let cgroup = dir.path().join("cgroup");
+            create_dir_all(&cgroup)?;
+            let pg_ctl = PgCtlBuilder::new().program_dir("/bin/does-not-exist");
+            let output = CgroupCommand::new(pg_ctl, &cgroup).build().output()?;
+
+            // The shell wrote its process id before executing the (missing) program
+            let pid = read_to_string(cgroup.join("cgroup.procs"))?;
+            assert!(pid.trim().parse::<u32>().is_ok());
+            assert!(!output.status.success());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_resource_profile_parameters() {
+        let parameters = ResourceProfile::Tiny.parameters();
+        assert!(parameters.contains(&("shared_buffers", "16MB")));
+        assert!(parameters.contains(&("max_connections", "20")));
+        assert!(ResourceProfile::Small
+            .parameters()
+            .contains(&("work_mem", "2MB")));
+        assert!(ResourceProfile::Default.parameters().is_empty());
+    }
+
+    #[test]
+    fn test_resource_profile_from_str() {
+        for profile in [
+            ResourceProfile::Tiny,
+            ResourceProfile::Small,
+            ResourceProfile::Default,
+        ] {
+            assert_eq!(Ok(profile), profile.to_string().parse());
+        }
+        assert!("huge".parse::<ResourceProfile>().is_err());
+    }
+
+    #[test]
+    fn test_resource_limits_is_empty() {
+        assert!(ResourceLimits::default().is_empty());
+        let limits = ResourceLimits {
+            cpu_max: Some(100),
+            ..Default::default()
+        };
+        assert!(!limits.is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+//! libpq connection service and password files
+
+use crate::error::Result;
+use crate::settings::Settings;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the password file written next to the connection service file
+const PGPASS_FILE_NAME: &str = ".pgpass";
+
+/// Write a connection service entry with the given name to the
+/// [connection service file](https://www.postgresql.org/docs/current/libpq-pgservice.html) at
+/// the given path, and the password to a
+/// [password file](https://www.postgresql.org/docs/current/libpq-pgpass.html) in the same
+/// directory. Existing entries for other services and servers are preserved.
+///
+/// Returns the path of the password file.
+pub(crate) fn write_service_file(
+    settings: &Settings,
+    path: &Path,
+    service_name: &str,
+) -> Result<PathBuf> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    fs::create_dir_all(&directory)?;
+    let passfile = directory.join(PGPASS_FILE_NAME);
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.into()),
+    };
+    let entry = service_entry(settings, service_name, &passfile);
+    fs::write(path, upsert_service(&contents, service_name, &entry))?;
+
+    let contents = match fs::read_to_string(&passfile) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.into()),
+    };
+    let pgpass = upsert_pgpass(&contents, &pgpass_prefix(settings), &pgpass_line(settings));
+    // The password file is written to a temporary file in the same directory, which is only
+    // readable by the owner from the start, and renamed into place
+    let mut temp_file = tempfile::NamedTempFile::new_in(&directory)?;
+    temp_file.write_all(pgpass.as_bytes())?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(&passfile).map_err(|error| error.error)?;
+
+    Ok(passfile)
+}
+
+/// Format the connection service entry for the settings
+fn service_entry(settings: &Settings, service_name: &str, passfile: &Path) -> String {
+    format!(
+        "[{service_name}]\nhost={}\nport={}\nuser={}\npassfile={}\n",
+        settings.host,
+        settings.port,
+        settings.username,
+        passfile.to_string_lossy()
+    )
+}
+
+/// Replace the section for the service in the connection service file contents with the entry,
+/// or append the entry if the service does not exist
+fn upsert_service(contents: &str, service_name: &str, entry: &str) -> String {
+    let header = format!("[{service_name}]");
+    let mut result = String::new();
+    let mut in_service = false;
+    let mut replaced = false;
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if in_service {
+                result.push('\n');
+            }
+            in_service = trimmed == header;
+            if in_service {
+                if !result.is_empty() && !result.ends_with("\n\n") {
+                    result.push('\n');
+                }
+                result.push_str(entry);
+                replaced = true;
+                continue;
+            }
+        }
+        if !in_service {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+
+    if !replaced {
+        if !result.is_empty() && !result.ends_with("\n\n") {
+            result.push('\n');
+        }
+        result.push_str(entry);
+    }
+    result
+}
+
+/// Escape a password file field; `:` and `\` are escaped with a backslash
+fn escape_pgpass_field(field: &str) -> String {
+    field.replace('\\', "\\\\").replace(':', "\\:")
+}
+
+/// Get the `host:port:*:user:` prefix that identifies the password file line for the settings
+fn pgpass_prefix(settings: &Settings) -> String {
+    format!(
+        "{}:{}:*:{}:",
+        escape_pgpass_field(&settings.host),
+        settings.port,
+        escape_pgpass_field(&settings.username)
+    )
+}
+
+/// Format the password file line for the settings
+fn pgpass_line(settings: &Settings) -> String {
+    format!(
+        "{}{}",
+        pgpass_prefix(settings),
+        escape_pgpass_field(&settings.password)
+    )
+}
+
+/// Replace the line starting with the prefix in the password file contents, or append the line
+fn upsert_pgpass(contents: &str, prefix: &str, line: &str) -> String {
+    let mut result = String::new();
+    let mut replaced = false;
+
+    for existing in contents.lines() {
+        if existing.starts_with(prefix) {
+            if !replaced {
+                result.push_str(line);
+                result.push('\n');
+                replaced = true;
+            }
+        } else {
+            result.push_str(existing);
+            result.push('\n');
+        }
+    }
+
+    if !replaced {
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn settings() -> Settings {
+        Settings {
+            host: "localhost".to_string(),
+            port: 5432,
+            username: "postgres".to_string(),
+            password: "pa:ss\\word".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pgpass_line() {
+        assert_eq!(
+            "localhost:5432:*:postgres:pa\\:ss\\\\word",
+            pgpass_line(&settings())
+        );
+    }
+
+    #[test]
+    fn test_upsert_pgpass() {
+        let settings = settings();
+        let prefix = pgpass_prefix(&settings);
+        let line = pgpass_line(&settings);
+        let contents = format!("other:5432:*:postgres:secret\n{prefix}old\n");
+        assert_eq!(
+            format!("other:5432:*:postgres:secret\n{line}\n"),
+            upsert_pgpass(&contents, &prefix, &line)
+        );
+        assert_eq!(format!("{line}\n"), upsert_pgpass("", &prefix, &line));
+    }
+
+    #[test]
+    fn test_upsert_service() {
+        let contents = "[other]\nhost=other\n\n[embedded]\nhost=old\nport=1\n\n[last]\nhost=last\n";
+        let entry = "[embedded]\nhost=localhost\n";
+        assert_eq!(
+            "[other]\nhost=other\n\n[embedded]\nhost=localhost\n\n[last]\nhost=last\n",
+            upsert_service(contents, "embedded", entry)
+        );
+        assert_eq!(
+            "[other]\nhost=other\n\n[embedded]\nhost=localhost\n",
+            upsert_service("[other]\nhost=other\n", "embedded", entry)
+        );
+    }
+
+    #[test]
+    fn test_write_service_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pg_service.conf");
+        let settings = settings();
+        // An existing password file readable by other users is replaced
+        fs::write(dir.path().join(".pgpass"), "")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = fs::Permissions::from_mode(0o644);
+            fs::set_permissions(dir.path().join(".pgpass"), permissions)?;
+        }
+
+        let passfile = write_service_file(&settings, &path, "embedded")?;
+        write_service_file(&settings, &path, "embedded")?;
+
+        assert_eq!(dir.path().join(".pgpass"), passfile);
+        let service = fs::read_to_string(&path)?;
+        assert_eq!(
+            format!(
+                "[embedded]\nhost=localhost\nport=5432\nuser=postgres\npassfile={}\n",
+                passfile.to_string_lossy()
+            ),
+            service
+        );
+        assert_eq!(
+            format!("{}\n", pgpass_line(&settings)),
+            fs::read_to_string(&passfile)?
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&passfile)?.permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+//! Stop running servers when the process is interrupted or terminated
+
+use crate::settings::Settings;
+use lazy_static::lazy_static;
+use postgresql_commands::pg_ctl::Mode::Stop;
+use postgresql_commands::pg_ctl::PgCtlBuilder;
+use postgresql_commands::pg_ctl::ShutdownMode::Immediate;
+use postgresql_commands::CommandBuilder;
+use std::collections::HashMap;
+use std::fs::{remove_dir_all, remove_file};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing::debug;
+
+lazy_static! {
+    /// Registries of the installed [guards](SignalGuard)
+    static ref GUARDS: Guards = Guards::default();
+}
+
+#[cfg(any(unix, feature = "tokio"))]
+lazy_static! {
+    /// Signal listener shared by the installed [guards](SignalGuard), or `None` when no guard is
+    /// installed
+    static ref LISTENER: Mutex<Option<Listener>> = Mutex::new(None);
+}
+
+#[cfg(unix)]
+lazy_static! {
+    /// Condition of the action that runs the default handling of `SIGINT` and `SIGTERM`, or `None`
+    /// before the first guard is installed. Unregistering a signal handler does not restore the
+    /// default handling, so the action is registered once for the process and enabled whenever no
+    /// guard is installed.
+    static ref DEFAULT_ACTION: Mutex<Option<Arc<AtomicBool>>> = Mutex::new(None);
+}
+
+/// Settings of the servers started while a [guard](SignalGuard) is installed, keyed by data
+/// directory
+#[derive(Debug, Default)]
+pub(crate) struct Registry {
+    servers: Mutex<HashMap<PathBuf, Settings>>,
+}
+
+impl Registry {
+    /// Register a started server
+    fn register(&self, settings: &Settings) {
+        if let Ok(mut servers) = self.servers.lock() {
+            servers.insert(settings.data_dir.clone(), settings.clone());
+        }
+    }
+
+    /// Remove a server once it has been stopped
+    fn unregister(&self, data_dir: &Path) {
+        if let Ok(mut servers) = self.servers.lock() {
+            servers.remove(data_dir);
+        }
+    }
+
+    /// Stop all registered servers without waiting for clients to disconnect (immediate mode) and
+    /// remove the data directory and password file of temporary servers. Errors are ignored so
+    /// that every server is attempted.
+    fn stop_all(&self) {
+        let servers: Vec<Settings> = match self.servers.lock() {
+            Ok(mut servers) => servers.drain().map(|(_, settings)| settings).collect(),
+            Err(_) => return,
+        };
+
+        for settings in servers {
+            debug!(
+                "Stopping database {} on signal",
+                settings.data_dir.to_string_lossy()
+            );
+            let mut pg_ctl = PgCtlBuilder::from(&settings)
+                .mode(Stop)
+                .pgdata(&settings.data_dir)
+                .shutdown_mode(Immediate)
+                .wait()
+                .build();
+            let _ = pg_ctl.output();
+
+            if settings.temporary {
+                let _ = remove_dir_all(&settings.data_dir);
+                let _ = remove_file(&settings.password_file);
+            }
+        }
+    }
+}
+
+/// Registries of the installed [guards](SignalGuard)
+#[derive(Debug, Default)]
+struct Guards {
+    registries: Mutex<Vec<Arc<Registry>>>,
+}
+
+impl Guards {
+    /// Add the registry of an installed guard
+    #[cfg(any(unix, feature = "tokio"))]
+    fn add(&self, registry: Arc<Registry>) {
+        self.registries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(registry);
+    }
+
+    /// Remove the registry of a dropped guard. Returns `true` if no guard is installed anymore.
+    #[cfg(any(unix, feature = "tokio"))]
+    fn remove(&self, registry: &Arc<Registry>) -> bool {
+        let mut registries = self
+            .registries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        registries.retain(|installed| !Arc::ptr_eq(installed, registry));
+        registries.is_empty()
+    }
+
+    /// Register a started server with every registry
+    fn register(&self, settings: &Settings) {
+        if let Ok(registries) = self.registries.lock() {
+            for registry in registries.iter() {
+                registry.register(settings);
+            }
+        }
+    }
+
+    /// Remove a stopped server from every registry
+    fn unregister(&self, data_dir: &Path) {
+        if let Ok(registries) = self.registries.lock() {
+            for registry in registries.iter() {
+                registry.unregister(data_dir);
+            }
+        }
+    }
+
+    /// Stop the servers of every registry. The registries are drained before the signal is
+    /// re-raised, so that the servers of all guards are stopped.
+    #[cfg(any(unix, feature = "tokio"))]
+    fn stop_all(&self) {
+        let registries: Vec<Arc<Registry>> = match self.registries.lock() {
+            Ok(registries) => registries.clone(),
+            Err(_) => return,
+        };
+
+        for registry in registries {
+            registry.stop_all();
+        }
+    }
+}
+
+/// Register a started server with every installed guard, so that it is stopped if the process
+/// receives a signal
+pub(crate) fn register(settings: &Settings) {
+    GUARDS.register(settings);
+}
+
+/// Remove a server from the installed guards once it has been stopped
+pub(crate) fn unregister(data_dir: &Path) {
+    GUARDS.unregister(data_dir);
+}
+
+/// Enable or disable the default handling of `SIGINT` and `SIGTERM`
+#[cfg(unix)]
+fn set_default_action(enabled: bool) -> crate::Result<()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+
+    let mut default_action = DEFAULT_ACTION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    match default_action.as_ref() {
+        Some(condition) => condition.store(enabled, Ordering::SeqCst),
+        // Until the action is registered the default handling is in place
+        None if enabled => {}
+        None => {
+            let condition = Arc::new(AtomicBool::new(false));
+            for signal in [SIGINT, SIGTERM] {
+                signal_hook::flag::register_conditional_default(signal, condition.clone())?;
+            }
+            *default_action = Some(condition);
+        }
+    }
+    Ok(())
+}
+
+/// Thread that waits for `SIGINT` or `SIGTERM` while a [guard](SignalGuard) is installed
+#[cfg(unix)]
+#[derive(Debug)]
+struct Listener {
+    handle: signal_hook::iterator::Handle,
+    thread: std::thread::JoinHandle<()>,
+}
+
+#[cfg(unix)]
+impl Listener {
+    /// Register the signal handlers and start waiting for a signal
+    fn start() -> crate::Result<Self> {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+
+        let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM])?;
+        set_default_action(false)?;
+        let handle = signals.handle();
+        let thread = std::thread::spawn(move || {
+            // The iterator ends when the listener is stopped, which unregisters the handlers
+            if let Some(signal) = signals.forever().next() {
+                GUARDS.stop_all();
+                let _ = signal_hook::low_level::emulate_default_handler(signal);
+            }
+        });
+        Ok(Self { handle, thread })
+    }
+
+    /// Unregister the signal handlers and restore the default handling of the signals
+    fn stop(self) {
+        let _ = set_default_action(true);
+        self.handle.close();
+        let _ = self.thread.join();
+    }
+}
+
+/// Thread that waits for Ctrl-C while a [guard](SignalGuard) is installed
+#[cfg(all(not(unix), feature = "tokio"))]
+#[derive(Debug)]
+struct Listener {
+    shutdown: tokio::sync::oneshot::Sender<()>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+#[cfg(all(not(unix), feature = "tokio"))]
+impl Listener {
+    /// Register the Ctrl-C handler and start waiting for Ctrl-C on a runtime owned by the listener,
+    /// so that a guard can be installed outside a tokio runtime
+    fn start() -> crate::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let mut ctrl_c = {
+            let _runtime = runtime.enter();
+            tokio::signal::windows::ctrl_c()?
+        };
+        let (shutdown, shutdown_receiver) = tokio::sync::oneshot::channel();
+        let thread = std::thread::spawn(move || {
+            runtime.block_on(async move {
+                tokio::select! {
+                    _ = ctrl_c.recv() => {
+                        GUARDS.stop_all();
+                        std::process::exit(130);
+                    }
+                    _ = shutdown_receiver => {}
+                }
+            });
+        });
+        Ok(Self { shutdown, thread })
+    }
+
+    /// Stop waiting for Ctrl-C; without listeners, the Ctrl-C handler falls back to the default
+    /// handling
+    fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// Guard that stops the [PostgreSQL](crate::PostgreSQL) servers started while it is installed
+/// when the process receives `SIGINT` (Ctrl-C) or `SIGTERM`.
+///
+/// `Drop` does not run when a process is killed by a signal, which leaves the servers running and
+/// temporary data directories behind. While a guard is alive, a signal stops every server
+/// started since the guard was installed in immediate mode, removes temporary data directories,
+/// and then re-raises the signal so that the process exits as it would have without the guard.
+/// All installed guards share one listener, so the servers of every guard are stopped before the
+/// process exits. On Windows only Ctrl-C is handled, the `tokio` feature is required, and the
+/// process exits with code 130 after the cleanup.
+///
+/// Handling signals is opt-in, because it replaces the default signal handling of the process.
+/// Dropping the last guard removes the signal handlers; once no guard is installed, `SIGINT` and
+/// `SIGTERM` terminate the process again.
+///
+/// ```no_run
+/// use postgresql_embedded::{PostgreSQL, SignalGuard};
+///
+/// #[tokio::main]
+/// async fn main() -> postgresql_embedded::Result<()> {
+///     let _guard = SignalGuard::install()?;
+///     let mut postgresql = PostgreSQL::default();
+///     postgresql.setup().await?;
+///     postgresql.start().await?;
+///     Ok(())
+/// }
+/// ```
+#[cfg(any(unix, feature = "tokio"))]
+#[derive(Debug)]
+pub struct SignalGuard {
+    registry: Arc<Registry>,
+}
+
+#[cfg(any(unix, feature = "tokio"))]
+impl SignalGuard {
+    /// Install the signal handlers, if no other guard is installed; dropping the last guard
+    /// removes the signal handlers.
+    pub fn install() -> crate::Result<Self> {
+        let mut listener = LISTENER.lock().unwrap_or_else(PoisonError::into_inner);
+        if listener.is_none() {
+            *listener = Some(Listener::start()?);
+        }
+
+        let registry = Arc::new(Registry::default());
+        GUARDS.add(registry.clone());
+        Ok(Self { registry })
+    }
+}
+
+#[cfg(any(unix, feature = "tokio"))]
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        let mut listener = LISTENER.lock().unwrap_or_else(PoisonError::into_inner);
+        let no_guards = GUARDS.remove(&self.registry);
+
+        // The guards are released first, as the listener drains them when it receives a signal
+        if no_guards {
+            if let Some(listener) = listener.take() {
+                listener.stop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+    use test_log::test;
+
+    #[test]
+    fn test_stop_all() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let temporary = Settings {
+            installation_dir: dir.path().join("installation"),
+            data_dir: dir.path().join("temporary"),
+            password_file: dir.path().join(".pgpass"),
+            ..Default::default()
+        };
+        let persistent = Settings {
+            data_dir: dir.path().join("persistent"),
+            temporary: false,
+            ..temporary.clone()
+        };
+        let unregistered = Settings {
+            data_dir: dir.path().join("unregistered"),
+            ..temporary.clone()
+        };
+        let registry = Registry::default();
+        for settings in [&temporary, &persistent, &unregistered] {
+            create_dir_all(&settings.data_dir)?;
+            registry.register(settings);
+        }
+        std::fs::write(&temporary.password_file, "password")?;
+        registry.unregister(&unregistered.data_dir);
+
+        registry.stop_all();
+
+        assert!(!temporary.data_dir.exists());
+        assert!(!temporary.password_file.exists());
+        assert!(persistent.data_dir.exists());
+        assert!(unregistered.data_dir.exists());
+        Ok(())
+    }
+
+    #[cfg(any(unix, feature = "tokio"))]
+    #[test]
+    fn test_signal_guard() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let settings = Settings {
+            data_dir: dir.path().join("data"),
+            ..Default::default()
+        };
+        let registered = |guard: &SignalGuard| {
+            guard
+                .registry
+                .servers
+                .lock()
+                .map(|servers| servers.contains_key(&settings.data_dir))
+                .unwrap_or_default()
+        };
+
+        let guard = SignalGuard::install()?;
+        register(&settings);
+        assert!(registered(&guard));
+        unregister(&settings.data_dir);
+        assert!(!registered(&guard));
+
+        let registry = guard.registry.clone();
+        drop(guard);
+        let installed = GUARDS
+            .registries
+            .lock()
+            .map(|registries| {
+                registries
+                    .iter()
+                    .any(|installed| Arc::ptr_eq(installed, &registry))
+            })
+            .unwrap_or_default();
+        assert!(!installed);
+        Ok(())
+    }
+
+    #[cfg(any(unix, feature = "tokio"))]
+    #[test]
+    fn test_stop_all_guards() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let first = Settings {
+            installation_dir: dir.path().join("installation"),
+            data_dir: dir.path().join("first"),
+            password_file: dir.path().join(".pgpass"),
+            ..Default::default()
+        };
+        let second = Settings {
+            data_dir: dir.path().join("second"),
+            ..first.clone()
+        };
+
+        // The guards are tested without installing signal handlers or touching the registries of
+        // the installed guards
+        let guards = Guards::default();
+        let first_registry = Arc::new(Registry::default());
+        guards.add(first_registry.clone());
+        create_dir_all(&first.data_dir)?;
+        guards.register(&first);
+        let second_registry = Arc::new(Registry::default());
+        guards.add(second_registry.clone());
+        create_dir_all(&second.data_dir)?;
+        guards.register(&second);
+
+        guards.stop_all();
+
+        assert!(!first.data_dir.exists());
+        assert!(!second.data_dir.exists());
+        assert!(!guards.remove(&second_registry));
+        assert!(guards.remove(&first_registry));
+        Ok(())
+    }
+}
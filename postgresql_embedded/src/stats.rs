@@ -0,0 +1,428 @@
+//! Runtime statistics
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Query for the statistics of each database
+pub(crate) const DATABASES_SQL: &str = "SELECT datname AS name, \
+    pg_database_size(datid) AS size_bytes, \
+    numbackends AS backends, \
+    xact_commit, xact_rollback, blks_read, blks_hit, \
+    tup_returned, tup_fetched, tup_inserted, tup_updated, tup_deleted, \
+    conflicts, temp_files, temp_bytes, deadlocks \
+    FROM pg_stat_database WHERE datname IS NOT NULL ORDER BY datname";
+
+/// Query for the activity of each server process
+pub(crate) const ACTIVITY_SQL: &str = "SELECT pid, datname AS database, \
+    usename AS username, application_name, client_addr, backend_type, state, \
+    wait_event_type, wait_event, query \
+    FROM pg_stat_activity ORDER BY pid";
+
+/// Query for the background writer statistics; only columns available in all supported versions
+pub(crate) const BGWRITER_SQL: &str =
+    "SELECT buffers_clean, maxwritten_clean, buffers_alloc FROM pg_stat_bgwriter";
+
+/// Query for the client connection counts
+pub(crate) const CONNECTIONS_SQL: &str = "SELECT \
+    current_setting('max_connections')::bigint AS max_connections, \
+    count(*) AS total, \
+    count(*) FILTER (WHERE state = 'active') AS active, \
+    count(*) FILTER (WHERE state = 'idle') AS idle, \
+    count(*) FILTER (WHERE state LIKE 'idle in transaction%') AS idle_in_transaction \
+    FROM pg_stat_activity WHERE backend_type = 'client backend'";
+
+/// Name, type, help and value of a metric
+struct Metric<V> {
+    name: &'static str,
+    metric_type: &'static str,
+    help: &'static str,
+    value: V,
+}
+
+/// Metric with a value for each database
+type DatabaseMetric = Metric<fn(&DatabaseStats) -> i64>;
+
+/// Snapshot of the runtime statistics of a [PostgreSQL](crate::PostgreSQL) server
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Statistics of each database from `pg_stat_database`
+    pub databases: Vec<DatabaseStats>,
+    /// Server processes from `pg_stat_activity`
+    pub activity: Vec<Activity>,
+    /// Background writer statistics from `pg_stat_bgwriter`
+    pub bgwriter: BgWriterStats,
+    /// Client connection counts
+    pub connections: ConnectionStats,
+}
+
+/// Statistics of a database from `pg_stat_database`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatabaseStats {
+    /// Database name
+    pub name: String,
+    /// Disk space used by the database
+    pub size_bytes: i64,
+    /// Number of backends connected to the database
+    pub backends: i64,
+    /// Number of committed transactions
+    pub xact_commit: i64,
+    /// Number of rolled back transactions
+    pub xact_rollback: i64,
+    /// Number of disk blocks read
+    pub blks_read: i64,
+    /// Number of disk blocks found in the buffer cache
+    pub blks_hit: i64,
+    /// Number of live rows fetched by sequential scans and index entries returned by index scans
+    pub tup_returned: i64,
+    /// Number of live rows fetched by index scans
+    pub tup_fetched: i64,
+    /// Number of rows inserted
+    pub tup_inserted: i64,
+    /// Number of rows updated
+    pub tup_updated: i64,
+    /// Number of rows deleted
+    pub tup_deleted: i64,
+    /// Number of queries canceled due to conflicts with recovery
+    pub conflicts: i64,
+    /// Number of temporary files created by queries
+    pub temp_files: i64,
+    /// Total amount of data written to temporary files by queries
+    pub temp_bytes: i64,
+    /// Number of deadlocks detected
+    pub deadlocks: i64,
+}
+
+/// Server process from `pg_stat_activity`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    /// Process id
+    pub pid: i32,
+    /// Database the process is connected to
+    pub database: Option<String>,
+    /// User logged into the process
+    pub username: Option<String>,
+    /// Application name of the client
+    pub application_name: Option<String>,
+    /// Address of the client; empty for Unix socket connections and background processes
+    pub client_addr: Option<String>,
+    /// Type of process, such as `client backend` or `checkpointer`
+    pub backend_type: String,
+    /// State of a client backend, such as `active` or `idle`
+    pub state: Option<String>,
+    /// Type of event the process is waiting for
+    pub wait_event_type: Option<String>,
+    /// Name of the event the process is waiting for
+    pub wait_event: Option<String>,
+    /// Most recent query of a client backend
+    pub query: Option<String>,
+}
+
+/// Background writer statistics from `pg_stat_bgwriter`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BgWriterStats {
+    /// Number of buffers written by the background writer
+    pub buffers_clean: i64,
+    /// Number of times the background writer stopped because it had written too many buffers
+    pub maxwritten_clean: i64,
+    /// Number of buffers allocated
+    pub buffers_alloc: i64,
+}
+
+/// Client connection counts from `pg_stat_activity`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionStats {
+    /// Maximum number of concurrent connections
+    pub max_connections: i64,
+    /// Number of client connections
+    pub total: i64,
+    /// Number of client connections executing a query
+    pub active: i64,
+    /// Number of client connections waiting for a command
+    pub idle: i64,
+    /// Number of client connections idle in a transaction
+    pub idle_in_transaction: i64,
+}
+
+/// Parse the output of a `psql --csv` query with a header row
+pub(crate) fn parse_csv<T: DeserializeOwned>(csv: &str) -> anyhow::Result<Vec<T>> {
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let rows = reader.deserialize().collect::<Result<Vec<T>, _>>()?;
+    Ok(rows)
+}
+
+impl Stats {
+    /// Format the statistics in the
+    /// [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/),
+    /// for example to serve them to a local dashboard during a load test.
+    pub fn to_prometheus(&self) -> String {
+        let mut output = String::new();
+
+        let database_metrics: [DatabaseMetric; 15] = [
+            Metric {
+                name: "database_size_bytes",
+                metric_type: "gauge",
+                help: "Disk space used by the database",
+                value: |d| d.size_bytes,
+            },
+            Metric {
+                name: "database_backends",
+                metric_type: "gauge",
+                help: "Number of backends connected to the database",
+                value: |d| d.backends,
+            },
+            Metric {
+                name: "database_xact_commit_total",
+                metric_type: "counter",
+                help: "Number of committed transactions",
+                value: |d| d.xact_commit,
+            },
+            Metric {
+                name: "database_xact_rollback_total",
+                metric_type: "counter",
+                help: "Number of rolled back transactions",
+                value: |d| d.xact_rollback,
+            },
+            Metric {
+                name: "database_blks_read_total",
+                metric_type: "counter",
+                help: "Number of disk blocks read",
+                value: |d| d.blks_read,
+            },
+            Metric {
+                name: "database_blks_hit_total",
+                metric_type: "counter",
+                help: "Number of disk blocks found in the buffer cache",
+                value: |d| d.blks_hit,
+            },
+            Metric {
+                name: "database_tup_returned_total",
+                metric_type: "counter",
+                help: "Number of rows returned by queries",
+                value: |d| d.tup_returned,
+            },
+            Metric {
+                name: "database_tup_fetched_total",
+                metric_type: "counter",
+                help: "Number of rows fetched by queries",
+                value: |d| d.tup_fetched,
+            },
+            Metric {
+                name: "database_tup_inserted_total",
+                metric_type: "counter",
+                help: "Number of rows inserted",
+                value: |d| d.tup_inserted,
+            },
+            Metric {
+                name: "database_tup_updated_total",
+                metric_type: "counter",
+                help: "Number of rows updated",
+                value: |d| d.tup_updated,
+            },
+            Metric {
+                name: "database_tup_deleted_total",
+                metric_type: "counter",
+                help: "Number of rows deleted",
+                value: |d| d.tup_deleted,
+            },
+            Metric {
+                name: "database_conflicts_total",
+                metric_type: "counter",
+                help: "Number of queries canceled due to conflicts with recovery",
+                value: |d| d.conflicts,
+            },
+            Metric {
+                name: "database_temp_files_total",
+                metric_type: "counter",
+                help: "Number of temporary files created by queries",
+                value: |d| d.temp_files,
+            },
+            Metric {
+                name: "database_temp_bytes_total",
+                metric_type: "counter",
+                help: "Amount of data written to temporary files by queries",
+                value: |d| d.temp_bytes,
+            },
+            Metric {
+                name: "database_deadlocks_total",
+                metric_type: "counter",
+                help: "Number of deadlocks detected",
+                value: |d| d.deadlocks,
+            },
+        ];
+        for Metric {
+            name,
+            metric_type,
+            help,
+            value,
+        } in database_metrics
+        {
+            write_family(&mut output, name, metric_type, help);
+            for database in &self.databases {
+                let _ = writeln!(
+                    output,
+                    "postgresql_{name}{{database=\"{}\"}} {}",
+                    escape_label(&database.name),
+                    value(database)
+                );
+            }
+        }
+
+        let bgwriter = &self.bgwriter;
+        let connections = &self.connections;
+        let metrics = [
+            Metric {
+                name: "bgwriter_buffers_clean_total",
+                metric_type: "counter",
+                help: "Number of buffers written by the background writer",
+                value: bgwriter.buffers_clean,
+            },
+            Metric {
+                name: "bgwriter_maxwritten_clean_total",
+                metric_type: "counter",
+                help: "Number of times the background writer stopped because it had written too many buffers",
+                value: bgwriter.maxwritten_clean,
+            },
+            Metric {
+                name: "bgwriter_buffers_alloc_total",
+                metric_type: "counter",
+                help: "Number of buffers allocated",
+                value: bgwriter.buffers_alloc,
+            },
+            Metric {
+                name: "connections_max",
+                metric_type: "gauge",
+                help: "Maximum number of concurrent connections",
+                value: connections.max_connections,
+            },
+        ];
+        for Metric {
+            name,
+            metric_type,
+            help,
+            value,
+        } in metrics
+        {
+            write_family(&mut output, name, metric_type, help);
+            let _ = writeln!(output, "postgresql_{name} {value}");
+        }
+
+        write_family(
+            &mut output,
+            "connections",
+            "gauge",
+            "Number of client connections by state",
+        );
+        for (state, value) in [
+            ("active", connections.active),
+            ("idle", connections.idle),
+            ("idle_in_transaction", connections.idle_in_transaction),
+            ("total", connections.total),
+        ] {
+            let _ = writeln!(
+                output,
+                "postgresql_connections{{state=\"{state}\"}} {value}"
+            );
+        }
+
+        output
+    }
+}
+
+/// Write the help and type lines of a metric family
+fn write_family(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(output, "# HELP postgresql_{name} {help}");
+    let _ = writeln!(output, "# TYPE postgresql_{name} {metric_type}");
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_parse_databases() -> anyhow::Result<()> {
+        let csv = "name,size_bytes,backends,xact_commit,xact_rollback,blks_read,blks_hit,tup_returned,tup_fetched,tup_inserted,tup_updated,tup_deleted,conflicts,temp_files,temp_bytes,deadlocks\n\
+            postgres,7631663,1,120,2,210,4502,31000,1900,10,3,1,0,0,0,0\n";
+        let databases: Vec<DatabaseStats> = parse_csv(csv)?;
+        assert_eq!(1, databases.len());
+        let database = &databases[0];
+        assert_eq!("postgres", database.name);
+        assert_eq!(7_631_663, database.size_bytes);
+        assert_eq!(120, database.xact_commit);
+        assert_eq!(4502, database.blks_hit);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_activity() -> anyhow::Result<()> {
+        let csv = "pid,database,username,application_name,client_addr,backend_type,state,wait_event_type,wait_event,query\n\
+            42,,,,,checkpointer,,Activity,CheckpointerMain,\n\
+            43,postgres,postgres,psql,127.0.0.1,client backend,active,,,\"SELECT 'a, b'\nFROM \"\"t\"\"\"\n";
+        let activity: Vec<Activity> = parse_csv(csv)?;
+        assert_eq!(2, activity.len());
+        assert_eq!(
+            Activity {
+                pid: 42,
+                backend_type: "checkpointer".to_string(),
+                wait_event_type: Some("Activity".to_string()),
+                wait_event: Some("CheckpointerMain".to_string()),
+                ..Default::default()
+            },
+            activity[0]
+        );
+        assert_eq!(Some("postgres".to_string()), activity[1].database);
+        assert_eq!(Some("127.0.0.1".to_string()), activity[1].client_addr);
+        assert_eq!(
+            Some("SELECT 'a, b'\nFROM \"t\"".to_string()),
+            activity[1].query
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let csv = "buffers_clean,maxwritten_clean,buffers_alloc\nfoo,0,0\n";
+        assert!(parse_csv::<BgWriterStats>(csv).is_err());
+    }
+
+    #[test]
+    fn test_to_prometheus() {
+        let stats = Stats {
+            databases: vec![DatabaseStats {
+                name: "my\"db".to_string(),
+                size_bytes: 1024,
+                xact_commit: 5,
+                ..Default::default()
+            }],
+            bgwriter: BgWriterStats {
+                buffers_alloc: 7,
+                ..Default::default()
+            },
+            connections: ConnectionStats {
+                max_connections: 100,
+                total: 3,
+                active: 1,
+                idle: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let output = stats.to_prometheus();
+        assert!(output.contains("# TYPE postgresql_database_size_bytes gauge\n"));
+        assert!(output.contains("postgresql_database_size_bytes{database=\"my\\\"db\"} 1024\n"));
+        assert!(output.contains("postgresql_database_xact_commit_total{database=\"my\\\"db\"} 5\n"));
+        assert!(output.contains("postgresql_bgwriter_buffers_alloc_total 7\n"));
+        assert!(output.contains("postgresql_connections_max 100\n"));
+        assert!(output.contains("postgresql_connections{state=\"idle\"} 2\n"));
+        assert!(output.contains("postgresql_connections{state=\"total\"} 3\n"));
+    }
+}
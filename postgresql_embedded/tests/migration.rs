@@ -0,0 +1,86 @@
+use postgresql_embedded::{Error, Migrations, PostgreSQL, Result};
+use std::fs;
+use test_log::test;
+
+#[test(tokio::test)]
+async fn test_migrate() -> Result<()> {
+    let mut postgresql = PostgreSQL::default();
+    postgresql.setup().await?;
+    postgresql.start().await?;
+
+    let database_name = "test";
+    let dir = tempfile::tempdir()?;
+    fs::write(
+        dir.path().join("V1__init.sql"),
+        "CREATE TABLE person (id INTEGER, name VARCHAR(20));",
+    )?;
+    fs::write(
+        dir.path().join("V2__add_person.sql"),
+        "INSERT INTO person VALUES (1, 'Alice');",
+    )?;
+    let migrations = Migrations::new(dir.path());
+
+    let applied = postgresql.migrate(database_name, &migrations).await?;
+    assert!(postgresql.database_exists(database_name).await?);
+    let scripts: Vec<&str> = applied.iter().map(|m| m.script.as_str()).collect();
+    assert_eq!(vec!["V1__init.sql", "V2__add_person.sql"], scripts);
+
+    let applied = postgresql.migrate(database_name, &migrations).await?;
+    assert!(applied.is_empty());
+
+    // A failed migration is rolled back and is not recorded as applied
+    fs::write(
+        dir.path().join("V3__invalid.sql"),
+        "INSERT INTO person VALUES (2, 'Bob');\nSELECT * FROM missing;",
+    )?;
+    let result = postgresql.migrate(database_name, &migrations).await;
+    assert!(matches!(result, Err(Error::MigrationError(_))));
+    fs::write(
+        dir.path().join("V3__invalid.sql"),
+        "INSERT INTO person VALUES (2, 'Bob');",
+    )?;
+    let applied = postgresql.migrate(database_name, &migrations).await?;
+    assert_eq!(1, applied.len());
+
+    fs::write(
+        dir.path().join("V1__init.sql"),
+        "CREATE TABLE person (id BIGINT, name VARCHAR(20));",
+    )?;
+    let result = postgresql.migrate(database_name, &migrations).await;
+    assert!(matches!(
+        result,
+        Err(Error::MigrationChecksumMismatch { .. })
+    ));
+
+    postgresql.stop().await
+}
+
+#[test(tokio::test)]
+async fn test_add_migrations() -> Result<()> {
+    let database_name = "test";
+    let dir = tempfile::tempdir()?;
+    fs::write(
+        dir.path().join("V1__init.sql"),
+        "CREATE TABLE person (id INTEGER, name VARCHAR(20))",
+    )?;
+
+    let mut postgresql = PostgreSQL::default();
+    postgresql.add_migrations(database_name, Migrations::new(dir.path()));
+    postgresql.setup().await?;
+    postgresql.start().await?;
+    assert!(postgresql.database_exists(database_name).await?);
+    let applied = postgresql
+        .migrate(database_name, &Migrations::new(dir.path()))
+        .await?;
+    assert!(applied.is_empty());
+    postgresql.stop().await?;
+
+    // Migrations changed after they were applied fail the next start
+    fs::write(
+        dir.path().join("V1__init.sql"),
+        "CREATE TABLE person (id BIGINT, name VARCHAR(20))",
+    )?;
+    let result = postgresql.start().await;
+    assert!(matches!(result, Err(Error::HookError { .. })));
+    Ok(())
+}
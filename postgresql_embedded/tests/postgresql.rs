@@ -1,7 +1,13 @@
 use anyhow::bail;
-use postgresql_archive::LATEST;
-use postgresql_embedded::{PostgreSQL, Result, Settings, Status};
+use postgresql_archive::{LATEST, V15, V16};
+use postgresql_embedded::{
+    Backoff, ConnectionStatus, Error, HookEvent, PostgreSQL, Progress, ProgressEvent, Result,
+    Settings, Status, UpgradeMode,
+};
 use std::fs::{remove_dir_all, remove_file};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use test_log::test;
 
 async fn lifecycle() -> Result<()> {
@@ -110,11 +116,13 @@ async fn test_persistent_database_reuse() -> Result<()> {
     assert!(data_dir.exists());
     assert!(password_file.exists());
 
-    let mut settings = Settings::default();
-    settings.data_dir = data_dir.clone();
-    settings.password = password.clone();
-    settings.password_file = password_file.clone();
-    settings.temporary = false;
+    let settings = Settings {
+        data_dir: data_dir.clone(),
+        password: password.clone(),
+        password_file: password_file.clone(),
+        temporary: false,
+        ..Default::default()
+    };
 
     {
         let mut postgresql = PostgreSQL::new(version, settings);
@@ -164,3 +172,274 @@ async fn test_username_setting() -> Result<()> {
     assert!(!database_exists);
     Ok(())
 }
+
+#[test(tokio::test)]
+async fn test_incompatible_data_directory() -> Result<()> {
+    let settings = Settings {
+        temporary: false,
+        ..Default::default()
+    };
+    let data_dir = settings.data_dir.clone();
+    let password_file = settings.password_file.clone();
+
+    {
+        let mut postgresql = PostgreSQL::new(V15, settings.clone());
+        postgresql.setup().await?;
+    }
+
+    let mut postgresql = PostgreSQL::new(V16, settings);
+    let result = postgresql.setup().await;
+    assert!(matches!(
+        result,
+        Err(Error::IncompatibleDataDirectory { .. })
+    ));
+    assert_eq!(Status::Incompatible, postgresql.status());
+    let result = postgresql.start().await;
+    assert!(matches!(
+        result,
+        Err(Error::IncompatibleDataDirectory { .. })
+    ));
+
+    let _ = remove_dir_all(&data_dir);
+    let _ = remove_file(&password_file);
+    Ok(())
+}
+
+async fn upgrade(mode: UpgradeMode) -> Result<()> {
+    let database_name = "test";
+    let settings = Settings {
+        temporary: false,
+        ..Default::default()
+    };
+    let data_dir = settings.data_dir.clone();
+    let password_file = settings.password_file.clone();
+
+    {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut postgresql = PostgreSQL::new(V15, settings.clone());
+        for event in [HookEvent::AfterStart, HookEvent::BeforeStop] {
+            let events = events.clone();
+            postgresql.add_hook(event, move |_postgresql: &PostgreSQL| {
+                events.lock().unwrap().push(event);
+                Ok(())
+            });
+        }
+        postgresql.setup().await?;
+        postgresql.start().await?;
+        postgresql.create_database(database_name).await?;
+
+        postgresql.upgrade_to(V16, mode).await?;
+        assert_eq!(16, postgresql.version().major);
+        assert_eq!(Status::Started, postgresql.status());
+        assert!(postgresql.database_exists(database_name).await?);
+        // Hooks are not run when the server is restarted for the upgrade
+        assert_eq!(vec![HookEvent::AfterStart], *events.lock().unwrap());
+        postgresql.stop().await?;
+    }
+
+    let mut postgresql = PostgreSQL::new(V16, settings);
+    postgresql.start().await?;
+    assert!(postgresql.database_exists(database_name).await?);
+    postgresql.stop().await?;
+
+    let _ = remove_dir_all(&data_dir);
+    let _ = remove_file(&password_file);
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_upgrade_copy() -> Result<()> {
+    upgrade(UpgradeMode::Copy).await
+}
+
+#[test(tokio::test)]
+async fn test_upgrade_link() -> Result<()> {
+    upgrade(UpgradeMode::Link).await
+}
+
+#[test(tokio::test)]
+async fn test_create_extension() -> Result<()> {
+    let settings = Settings {
+        preload_libraries: vec!["pg_stat_statements".to_string()],
+        ..Default::default()
+    };
+    let mut postgresql = PostgreSQL::new(LATEST, settings);
+    postgresql.setup().await?;
+    postgresql.start().await?;
+
+    let database_name = "test";
+    postgresql.create_database(database_name).await?;
+    postgresql
+        .create_extension(database_name, "pg_stat_statements", None)
+        .await?;
+    postgresql
+        .create_extension(database_name, "pg_stat_statements", None)
+        .await?;
+    let result = postgresql
+        .create_extension(database_name, "missing_extension", None)
+        .await;
+    assert!(matches!(result, Err(Error::CreateExtensionError(_))));
+
+    postgresql.stop().await
+}
+
+#[test(tokio::test)]
+async fn test_hooks() -> Result<()> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut postgresql = PostgreSQL::default();
+    for event in [
+        HookEvent::AfterInitialize,
+        HookEvent::AfterStart,
+        HookEvent::BeforeStop,
+        HookEvent::AfterStop,
+    ] {
+        let events = events.clone();
+        postgresql.add_hook(event, move |postgresql: &PostgreSQL| {
+            events.lock().unwrap().push((event, postgresql.status()));
+            Ok(())
+        });
+    }
+
+    postgresql.setup().await?;
+    postgresql.start().await?;
+    postgresql.stop().await?;
+
+    assert_eq!(
+        vec![
+            (HookEvent::AfterInitialize, Status::Stopped),
+            (HookEvent::AfterStart, Status::Started),
+            (HookEvent::BeforeStop, Status::Started),
+            (HookEvent::AfterStop, Status::Stopped),
+        ],
+        *events.lock().unwrap()
+    );
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_setup_progress() -> Result<()> {
+    let (progress, receiver) = Progress::channel();
+    let mut postgresql = PostgreSQL::default();
+    postgresql.set_progress(progress);
+
+    postgresql.setup().await?;
+
+    let events: Vec<ProgressEvent> = receiver.try_iter().collect();
+    assert_eq!(Some(&ProgressEvent::Resolving), events.first());
+    assert_eq!(Some(&ProgressEvent::Done), events.last());
+    assert_eq!(
+        1,
+        events
+            .iter()
+            .filter(|event| **event == ProgressEvent::Done)
+            .count()
+    );
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_health() -> Result<()> {
+    let mut postgresql = PostgreSQL::default();
+    postgresql.setup().await?;
+    postgresql.start().await?;
+
+    assert_eq!(ConnectionStatus::Accepting, postgresql.health().await?);
+    postgresql.wait_until_ready(Duration::from_secs(5)).await?;
+
+    postgresql.stop().await?;
+    assert_eq!(ConnectionStatus::NoResponse, postgresql.health().await?);
+    let backoff = Backoff {
+        initial_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(100),
+        multiplier: 2,
+    };
+    let result = postgresql
+        .wait_until_ready_with_backoff(Duration::from_secs(1), backoff)
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::DatabaseNotReady {
+            status: ConnectionStatus::NoResponse,
+            ..
+        })
+    ));
+    Ok(())
+}
+
+#[test(tokio::test)]
+async fn test_stats() -> Result<()> {
+    let mut postgresql = PostgreSQL::default();
+    postgresql.setup().await?;
+    postgresql.start().await?;
+    postgresql.create_database("test").await?;
+
+    let stats = postgresql.stats().await?;
+    let database = stats
+        .databases
+        .iter()
+        .find(|database| database.name == "test")
+        .expect("test database statistics");
+    assert!(database.size_bytes > 0);
+    assert!(stats
+        .activity
+        .iter()
+        .any(|activity| activity.backend_type == "client backend"));
+    assert!(stats.connections.total >= 1);
+    assert!(stats.connections.max_connections > 0);
+    assert!(stats
+        .to_prometheus()
+        .contains("postgresql_database_size_bytes{database=\"test\"}"));
+
+    postgresql.stop().await?;
+    Ok(())
+}
+
+/// Check if the file system of the given directory supports reflinks, which `pg_upgrade --clone`
+/// requires
+fn reflinks_supported(dir: &Path) -> bool {
+    let Ok(probe_dir) = tempfile::tempdir_in(dir) else {
+        return false;
+    };
+    let source = probe_dir.path().join("source");
+    if std::fs::write(&source, "reflink").is_err() {
+        return false;
+    }
+    std::process::Command::new("cp")
+        .arg("--reflink=always")
+        .arg(&source)
+        .arg(probe_dir.path().join("target"))
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+#[test(tokio::test)]
+async fn test_upgrade_clone() -> Result<()> {
+    let database_name = "test";
+    let settings = Settings {
+        temporary: false,
+        ..Default::default()
+    };
+    let data_dir = settings.data_dir.clone();
+    let password_file = settings.password_file.clone();
+    let parent_dir = data_dir.parent().expect("data directory parent");
+    if !reflinks_supported(parent_dir) {
+        tracing::warn!("Skipping test_upgrade_clone; reflinks are not supported");
+        return Ok(());
+    }
+
+    let mut postgresql = PostgreSQL::new(V15, settings);
+    postgresql.setup().await?;
+    postgresql.start().await?;
+    postgresql.create_database(database_name).await?;
+
+    postgresql.upgrade_to(V16, UpgradeMode::Clone).await?;
+    assert_eq!(16, postgresql.version().major);
+    assert_eq!(Status::Started, postgresql.status());
+    assert!(postgresql.database_exists(database_name).await?);
+    postgresql.stop().await?;
+
+    let _ = remove_dir_all(&data_dir);
+    let _ = remove_file(&password_file);
+    Ok(())
+}